@@ -69,6 +69,45 @@ case "$1" in
     run)
         cargo run "$2"
         ;;
+    add) # Add a job (with its description as text, or pasted/piped in with "-"):
+        cargo run -- add "${@:2}"
+        ;;
+    import) # Import a csv file of jobs into the database:
+        cargo run -- import "${@:2}"
+        ;;
+    duplicates) # List (and optionally merge) duplicate jobs:
+        cargo run -- duplicates "${@:2}"
+        ;;
+    merge) # Merge duplicate jobs into the first id:
+        cargo run -- merge "${@:2}"
+        ;;
+    event) # Add, list and remove deadlines, follow-ups and interviews:
+        cargo run -- event "${@:2}"
+        ;;
+    stage) # Move a job to a new stage of its application:
+        cargo run -- stage "${@:2}"
+        ;;
+    stats) # Print the statistics and the application funnel:
+        cargo run -- stats "${@:2}"
+        ;;
+    charts) # Write the charts of the statistics page as SVG files:
+        cargo run -- charts "${@:2}"
+        ;;
+    goal) # Set the weekly application goal or show this week's progress:
+        cargo run -- goal "${@:2}"
+        ;;
+    check-links) # Check every job's link and list the dead ones:
+        cargo run -- check-links "${@:2}"
+        ;;
+    user) # Add, list and remove the users who can log in, or change a password:
+        cargo run -- user "${@:2}"
+        ;;
+    token) # Make, list and revoke the API tokens of scripts:
+        cargo run -- token "${@:2}"
+        ;;
+    archive) # Save a copy of a job's posting page, from its link or a saved file:
+        cargo run -- archive "${@:2}"
+        ;;
     test)
         cargo test
         ;;
@@ -89,6 +128,19 @@ case "$1" in
         echo "  fmt             - Check for rust format"
         echo "  clippy          - Rust clippy"
         echo "  run             - Run the project in rust"
+        echo "  add <title> <rate> - Add a job (options: --company, --link, --applied, --description <text>|-, --from-html <page.html>)"
+        echo "  import <file>   - Import a csv file of jobs (options: --dry-run, --atomic, --delimiter, --map, --on-conflict)"
+        echo "  duplicates      - List duplicate jobs (option: --merge to merge each group)"
+        echo "  merge <ids>     - Merge duplicate jobs into the first id"
+        echo "  event <cmd>     - Add, list or remove job dates (add, list, remove)"
+        echo "  stage <id> <s>  - Move a job to a stage (applied, screen, interview, offer, rejected)"
+        echo "  stats [funnel]  - Print the statistics, or only the application funnel"
+        echo "  charts [dir]    - Write the statistics charts as SVG files"
+        echo "  goal <cmd>      - Set the weekly goal or show the progress (set <n>, status)"
+        echo "  check-links     - Check every job's link and list the dead ones (option: --all to list every link)"
+        echo "  archive <id>    - Save a job's posting page from its link (option: --file <page.html>)"
+        echo "  user <cmd>      - Add, list or remove the users who can log in (add, list, remove, passwd)"
+        echo "  token <cmd>     - Make, list or revoke API tokens for scripts (create <name> [--write], list, revoke <id>)"
         echo "  test            - Run test cases in rust"
         echo "  docs            - Open the project documentation"
         echo ""
@@ -103,6 +155,20 @@ case "$1" in
         echo "  $0 build            # Build the project"
         echo "  $0 run              # Run the project with a default port of '8000'"
         echo "  $0 run <port>       # Run the project with a port of <port>"
+        echo "  $0 import jobs.csv --dry-run  # Check a csv file and report bad rows without importing"
+        echo "  $0 import jobs.csv --atomic   # Import a csv file only if every row is valid"
+        echo "  $0 import jobs.tsv --delimiter tab --map title=Role  # Import tab separated jobs with a \"Role\" title column"
+        echo "  $0 import jobs.csv --on-conflict update  # Update jobs already in the database instead of skipping them"
+        echo "  $0 add Welder 28 --company Daimler --description - < posting.txt  # Keep the posting's text"
+        echo "  $0 add --from-html posting.html  # Read the title, pay, company and description from a saved page"
+        echo "  $0 merge 3 7 12     # Merge jobs 7 and 12 into job 3"
+        echo "  $0 event add 3 interview 2025-04-01 14:30 Phone screen  # Add an interview to job 3"
+        echo "  $0 stage 3 interview  # Job 3 got an interview"
+        echo "  $0 goal set 5       # Aim for 5 applications a week"
+        echo "  $0 user add nathan  # Add a user, typing their password twice"
+        echo "  $0 token create backup --write  # Make a token a script can change jobs with"
+        echo "  JOBS_HOST=0.0.0.0 $0 run  # Share the server on the network"
+        echo "  $0 archive 3 --file posting.html  # Keep the page saved from the browser for job 3"
         echo ""
 
         exit 1
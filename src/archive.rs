@@ -0,0 +1,567 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Archive Module
+//!
+//! Keeps a copy of each job's posting page, so the posting isn't lost
+//! when its link stops working. The page is either uploaded (saved from
+//! the browser) or fetched from the job's link with a `Fetcher`. Before
+//! it is stored, the page is sanitized: scripts, styles, forms and event
+//! handlers are removed, and relative links are made absolute.
+//!
+//! Links are only fetched from public hosts: a user of a shared server
+//! could otherwise save a link to this computer or its network (a router's
+//! admin page, the cloud metadata address) and read the page back.
+
+use crate::database_methods::{get_job, save_archive, DatabaseError};
+use ammonia::{Url, UrlRelative};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+/// The largest page that is fetched (uploads have the same limit in their form).
+const MAX_PAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// The most redirects followed to reach a page.
+const MAX_REDIRECTS: usize = 5;
+
+/// Why a page could not be fetched.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FetchError {
+    Status(u16),       // The server answered with an error status.
+    NotHtml(String),   // The page is not HTML, with its content type.
+    Transport(String), // The server could not be reached, or the page could not be read.
+    Private(String),   // The host is this computer or on its network, with the host.
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Status(code) => write!(f, "the server answered with status {}", code),
+            FetchError::NotHtml(kind) => write!(f, "the page is not HTML ({})", kind),
+            FetchError::Transport(message) => write!(f, "{}", message),
+            FetchError::Private(host) => {
+                write!(f, "{} is on this computer or a private network", host)
+            }
+        }
+    }
+}
+
+/// Something that can download the HTML of a page.
+///
+/// The server and the CLI use `HttpFetcher`. Tests use a stand-in that
+/// returns pages from memory, so they never need the network.
+pub trait Fetcher {
+    /// Download the HTML of the page at `url`.
+    fn fetch(&self, url: &str) -> Result<String, FetchError>;
+}
+
+// How the addresses of a host and port ("example.com:443") are looked up.
+type Resolve = fn(&str) -> io::Result<Vec<SocketAddr>>;
+
+/// Fetches pages over HTTP(S) from public hosts, following redirects.
+pub struct HttpFetcher {
+    agent: ureq::Agent,
+    resolve: Resolve,
+}
+
+impl Default for HttpFetcher {
+    fn default() -> Self {
+        HttpFetcher::with_resolver(public_addresses)
+    }
+}
+
+impl HttpFetcher {
+    // A fetcher that looks up the hosts with `resolve`. The agent looks them up the
+    // same way, so a host can't answer with another address once it was checked.
+    fn with_resolver(resolve: Resolve) -> HttpFetcher {
+        HttpFetcher {
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(20))
+                .user_agent("Mozilla/5.0 (job list archiver)")
+                .redirects(0) // Followed in `fetch`, to check the host of each one.
+                .resolver(resolve)
+                .build(),
+            resolve,
+        }
+    }
+
+    // Check that the host of a url may be fetched from.
+    fn check_host(&self, url: &Url) -> Result<(), FetchError> {
+        let host = url.host_str().unwrap_or_default();
+        let port = url.port_or_known_default().unwrap_or(80);
+        match (self.resolve)(&format!("{}:{}", host, port)) {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                Err(FetchError::Private(host.to_string()))
+            }
+            Err(err) => Err(FetchError::Transport(err.to_string())),
+        }
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    fn fetch(&self, url: &str) -> Result<String, FetchError> {
+        let mut url = web_url(url)
+            .ok_or_else(|| FetchError::Transport(format!("{} is not a web address", url)))?;
+        let mut redirects = 0;
+        let response = loop {
+            self.check_host(&url)?;
+            let response = match self.agent.get(url.as_str()).call() {
+                Ok(response) => response,
+                Err(ureq::Error::Status(code, _)) => return Err(FetchError::Status(code)),
+                Err(err) => return Err(FetchError::Transport(err.to_string())),
+            };
+            if !(300..400).contains(&response.status()) {
+                break response;
+            }
+
+            // A redirect, to a web address relative to the page:
+            redirects += 1;
+            if redirects > MAX_REDIRECTS {
+                return Err(FetchError::Transport("too many redirects".to_string()));
+            }
+            url = response
+                .header("Location")
+                .and_then(|location| url.join(location).ok())
+                .filter(|next| matches!(next.scheme(), "http" | "https"))
+                .ok_or(FetchError::Status(response.status()))?;
+        };
+        let kind = response.content_type().to_string();
+        if !kind.contains("html") {
+            return Err(FetchError::NotHtml(kind));
+        }
+
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(
+            &mut std::io::Read::take(response.into_reader(), MAX_PAGE_BYTES),
+            &mut bytes,
+        )
+        .map_err(|err| FetchError::Transport(err.to_string()))?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+/// Look up the addresses of a host and port ("example.com:443"), refusing the
+/// hosts on this computer or a private network with `PermissionDenied`.
+pub fn public_addresses(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    let addresses: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+    if addresses.iter().all(|address| is_public(address.ip())) {
+        Ok(addresses)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is on this computer or a private network", netloc),
+        ))
+    }
+}
+
+/// Whether an address is on the internet: not loopback, private, link-local
+/// (the cloud metadata address), shared, unspecified, broadcast or multicast.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))) // Shared by a carrier's NAT.
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || (first & 0xfe00) == 0xfc00 // Unique local.
+                    || (first & 0xffc0) == 0xfe80) // Link-local.
+            }
+        },
+    }
+}
+
+/// Why a job's page could not be archived.
+#[derive(Debug)]
+pub enum ArchiveError {
+    NoJob(i64),              // There is no job with the id.
+    NoLink(i64),             // The job has no web link to fetch.
+    Fetch(FetchError),       // The page could not be fetched.
+    Database(DatabaseError), // The database could not be read or written.
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveError::NoJob(id) => write!(f, "There is no job with id {}.", id),
+            ArchiveError::NoLink(id) => write!(f, "Job {} has no link to fetch.", id),
+            ArchiveError::Fetch(err) => write!(f, "Could not fetch the page: {}.", err),
+            ArchiveError::Database(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<DatabaseError> for ArchiveError {
+    fn from(err: DatabaseError) -> Self {
+        ArchiveError::Database(err)
+    }
+}
+
+/// Parse a job's link as a web url, if it is one ("No Link" and file paths are not).
+pub fn web_url(link: &str) -> Option<Url> {
+    Url::parse(link.trim())
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Sanitize the HTML of a page so it is safe to show on this site.
+///
+/// Only the content of the page is kept. Scripts, styles, frames, forms and
+/// event handler attributes are removed, and links open in a new tab.
+///
+/// # Arguments
+/// * `html` - The HTML of the page.
+/// * `base` - The url of the page, to make its relative links and images absolute.
+///
+/// # Returns
+/// * The sanitized HTML.
+pub fn sanitize(html: &str, base: Option<&Url>) -> String {
+    let mut builder = ammonia::Builder::default();
+    builder.link_rel(Some("noopener noreferrer nofollow"));
+    builder.set_tag_attribute_value("a", "target", "_blank");
+    builder.url_relative(match base {
+        Some(base) => UrlRelative::RewriteWithBase(base.clone()),
+        None => UrlRelative::Deny,
+    });
+    builder.clean(html).to_string()
+}
+
+/// Archive a page uploaded for a job, replacing any older copy.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user the job belongs to.
+/// * `job_id` - The job the page belongs to.
+/// * `html` - The HTML of the page.
+/// * `file_name` - The name of the uploaded file, kept as the source of the page.
+///
+/// # Returns
+/// * `Ok(())` if the page was saved.
+/// * `Err(ArchiveError)` if the user has no such job or the database fails.
+pub fn archive_upload(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: i64,
+    html: &str,
+    file_name: &str,
+) -> Result<(), ArchiveError> {
+    let job = get_job(connection, user_id, job_id)?.ok_or(ArchiveError::NoJob(job_id))?;
+    let base = job.get_optional_link().as_deref().and_then(web_url);
+    save(
+        connection,
+        user_id,
+        job_id,
+        &sanitize(html, base.as_ref()),
+        file_name,
+    )
+}
+
+/// Fetch a job's page from its link and archive it, replacing any older copy.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user the job belongs to.
+/// * `job_id` - The job to archive the page of.
+/// * `fetcher` - How the page is downloaded.
+///
+/// # Returns
+/// * `Ok(())` if the page was saved.
+/// * `Err(ArchiveError)` if the user has no such job, it has no link, or the page can't be fetched.
+pub fn archive_link(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: i64,
+    fetcher: &dyn Fetcher,
+) -> Result<(), ArchiveError> {
+    let job = get_job(connection, user_id, job_id)?.ok_or(ArchiveError::NoJob(job_id))?;
+    let url = job
+        .get_optional_link()
+        .as_deref()
+        .and_then(web_url)
+        .ok_or(ArchiveError::NoLink(job_id))?;
+    let html = fetcher.fetch(url.as_str()).map_err(ArchiveError::Fetch)?;
+    save(
+        connection,
+        user_id,
+        job_id,
+        &sanitize(&html, Some(&url)),
+        url.as_str(),
+    )
+}
+
+// Save a sanitized page, the job may have been removed since it was read.
+fn save(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: i64,
+    html: &str,
+    source: &str,
+) -> Result<(), ArchiveError> {
+    if save_archive(connection, user_id, job_id, html, source)? {
+        Ok(())
+    } else {
+        Err(ArchiveError::NoJob(job_id))
+    }
+}
+
+/// Testing the sanitizing and archiving with a stand-in fetcher.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database_methods::{create_table, enter_data, get_archive};
+    use crate::job::Job;
+    use rusqlite::Connection;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    // The user the test jobs belong to:
+    const USER: i64 = 1;
+
+    // Returns pages from memory instead of the network.
+    struct LocalFetcher {
+        pages: HashMap<String, String>,
+    }
+
+    impl Fetcher for LocalFetcher {
+        fn fetch(&self, url: &str) -> Result<String, FetchError> {
+            self.pages.get(url).cloned().ok_or(FetchError::Status(404))
+        }
+    }
+
+    // Start a stub HTTP server on this computer that answers `/page` with a posting,
+    // `/moved` with a redirect to it, and `/away` with a redirect to it through
+    // `localhost`. Returns its address.
+    fn stub_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to start the stub server");
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap_or_default();
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    line.clear();
+                }
+
+                let page = "<p>Posting</p>";
+                let response = match request_line.split_whitespace().nth(1) {
+                    Some("/moved") => "301 Moved\r\nLocation: /page\r\n".to_string(),
+                    Some("/away") => {
+                        format!("302 Found\r\nLocation: http://localhost:{}/page\r\n", port)
+                    }
+                    _ => format!(
+                        "200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n",
+                        page.len()
+                    ),
+                };
+                let body = if response.starts_with("200") {
+                    page
+                } else {
+                    ""
+                };
+                let _ = (&stream).write_all(
+                    format!("HTTP/1.1 {}Connection: close\r\n\r\n{}", response, body).as_bytes(),
+                );
+            }
+        });
+        format!("http://127.0.0.1:{}", port)
+    }
+
+    // Allows the stub server on 127.0.0.1, and checks any other host like the server does:
+    fn stub_resolve(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        if netloc.starts_with("127.0.0.1:") {
+            netloc.to_socket_addrs().map(Iterator::collect)
+        } else {
+            public_addresses(netloc)
+        }
+    }
+
+    // Add a job with a link to a database, returning its id.
+    fn add_job(connection: &Connection, link: &str) -> i64 {
+        let job = Job::new(
+            None,
+            "Rust Developer".to_string(),
+            40.0,
+            "0".to_string(),
+            Some(link.to_string()),
+        );
+        enter_data(connection, USER, &job).expect("Failed to insert job")
+    }
+
+    #[test]
+    fn test_web_url() {
+        assert!(web_url("https://example.com/jobs/1").is_some());
+        assert!(web_url(" http://example.com ").is_some());
+        assert_eq!(web_url("No Link"), None);
+        assert_eq!(web_url("file:///etc/passwd"), None);
+        assert_eq!(web_url("javascript:alert(1)"), None);
+    }
+
+    // Scripts, styles and event handlers are removed, relative links are made absolute:
+    #[test]
+    fn test_sanitize() {
+        let html = r#"<html><head><title>Job</title><style>p { color: red }</style>
+            <script>alert("hi")</script></head>
+            <body><h1 onclick="steal()">Rust Developer</h1>
+            <iframe src="https://evil.example"></iframe>
+            <form action="/apply"><input name="email"></form>
+            <p>Apply <a href="/apply">here</a>. <img src="logo.png"></p></body></html>"#;
+        let base = web_url("https://example.com/jobs/1").unwrap();
+        let clean = sanitize(html, Some(&base));
+
+        assert!(clean.contains("<h1>Rust Developer</h1>"));
+        for removed in [
+            "script", "alert", "style", "color", "onclick", "iframe", "form", "input",
+        ] {
+            assert!(!clean.contains(removed), "{} was kept: {}", removed, clean);
+        }
+        assert!(clean.contains(r#"href="https://example.com/apply""#));
+        assert!(clean.contains(r#"target="_blank""#));
+        assert!(clean.contains(r#"src="https://example.com/jobs/logo.png""#));
+
+        // Without a base, relative links are dropped:
+        assert!(!sanitize(r#"<a href="/apply">x</a>"#, None).contains("href"));
+    }
+
+    // Fetching the job's link archives the sanitized page, and replaces the old copy:
+    #[test]
+    fn test_archive_link() {
+        let connection = Connection::open_in_memory().expect("Failed to open in-memory database");
+        create_table(&connection).expect("Failed to create table");
+        let url = "https://example.com/jobs/1";
+        let id = add_job(&connection, url);
+        let no_link = add_job(&connection, "No Link");
+
+        let mut fetcher = LocalFetcher {
+            pages: HashMap::from([(
+                url.to_string(),
+                "<p>Old posting</p><script>x()</script>".to_string(),
+            )]),
+        };
+        archive_link(&connection, USER, id, &fetcher).expect("Failed to archive");
+        let archive = get_archive(&connection, USER, id).unwrap().unwrap();
+        assert_eq!(archive.html, "<p>Old posting</p>");
+        assert_eq!(archive.source, url);
+
+        fetcher
+            .pages
+            .insert(url.to_string(), "<p>New posting</p>".to_string());
+        archive_link(&connection, USER, id, &fetcher).expect("Failed to archive again");
+        assert_eq!(
+            get_archive(&connection, USER, id).unwrap().unwrap().html,
+            "<p>New posting</p>"
+        );
+
+        assert!(matches!(
+            archive_link(&connection, USER, no_link, &fetcher),
+            Err(ArchiveError::NoLink(_))
+        ));
+        assert!(matches!(
+            archive_link(&connection, USER, 99, &fetcher),
+            Err(ArchiveError::NoJob(99))
+        ));
+        fetcher.pages.clear();
+        assert!(matches!(
+            archive_link(&connection, USER, id, &fetcher),
+            Err(ArchiveError::Fetch(FetchError::Status(404)))
+        ));
+        assert_eq!(get_archive(&connection, USER, no_link).unwrap(), None);
+    }
+
+    // Links to this computer or a private network are refused, also when a page redirects to one:
+    #[test]
+    fn test_private_links() {
+        let connection = Connection::open_in_memory().expect("Failed to open in-memory database");
+        create_table(&connection).expect("Failed to create table");
+        let server = stub_server();
+        let id = add_job(&connection, &format!("{}/page", server));
+        assert!(matches!(
+            archive_link(&connection, USER, id, &HttpFetcher::default()),
+            Err(ArchiveError::Fetch(FetchError::Private(host))) if host == "127.0.0.1"
+        ));
+        assert_eq!(get_archive(&connection, USER, id).unwrap(), None);
+
+        for link in [
+            "http://localhost/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://192.168.1.1/admin",
+            "http://10.0.0.8/",
+            "http://[::1]/",
+            "http://0.0.0.0/",
+        ] {
+            assert!(
+                matches!(
+                    HttpFetcher::default().fetch(link),
+                    Err(FetchError::Private(_))
+                ),
+                "{} was fetched",
+                link
+            );
+        }
+
+        // Redirects are followed, and their host is checked too:
+        let fetcher = HttpFetcher::with_resolver(stub_resolve);
+        assert_eq!(
+            fetcher.fetch(&format!("{}/moved", server)),
+            Ok("<p>Posting</p>".to_string())
+        );
+        assert!(matches!(
+            fetcher.fetch(&format!("{}/away", server)),
+            Err(FetchError::Private(host)) if host == "localhost"
+        ));
+
+        assert!(is_public("93.184.215.14".parse().unwrap()));
+        assert!(is_public(
+            "2606:2800:21f:cb07:6820:80da:af6b:8b2c".parse().unwrap()
+        ));
+        assert!(!is_public("100.64.0.1".parse().unwrap()));
+        assert!(!is_public("fd00::1".parse().unwrap()));
+        assert!(!is_public("fe80::1".parse().unwrap()));
+        assert!(!is_public("::ffff:127.0.0.1".parse().unwrap()));
+    }
+
+    // An uploaded page is sanitized with the job's link as its base:
+    #[test]
+    fn test_archive_upload() {
+        let connection = Connection::open_in_memory().expect("Failed to open in-memory database");
+        create_table(&connection).expect("Failed to create table");
+        let id = add_job(&connection, "https://example.com/jobs/1");
+
+        archive_upload(
+            &connection,
+            USER,
+            id,
+            "<a href='apply'>Apply</a>",
+            "posting.html",
+        )
+        .expect("Failed to archive");
+        let archive = get_archive(&connection, USER, id).unwrap().unwrap();
+        assert!(archive
+            .html
+            .contains(r#"href="https://example.com/jobs/apply""#));
+        assert_eq!(archive.source, "posting.html");
+
+        assert!(matches!(
+            archive_upload(&connection, USER, 99, "<p></p>", "posting.html"),
+            Err(ArchiveError::NoJob(99))
+        ));
+    }
+}
@@ -0,0 +1,567 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Auth Module
+//!
+//! User accounts and login sessions, so one server can be shared on a
+//! network. Passwords are kept as argon2 hashes. Logging in starts a
+//! session: a random token is kept in the browser's cookie, and only its
+//! SHA-256 hash is kept in the database. The `require_login` middleware
+//! lets only logged-in users reach the job list and its routes, and puts
+//! the `User` in the request for the handlers.
+//!
+//! Scripts use personal API tokens instead, sent as `Authorization: Bearer <token>`.
+//! Like sessions, only the hash of a token is kept. Tokens only work on the
+//! JSON endpoints and the calendar feed, and a read-only token can't change anything.
+//! Calendar apps can't send a header, so the feed also takes the token in its
+//! url (`/calendar.ics?token=<token>`).
+
+use crate::database_methods::{
+    add_api_token, add_session, add_user, get_session_user, get_token_user, get_user_login,
+    remove_session, Database, DatabaseError,
+};
+use crate::job::{TokenScope, User};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::{Duration, NaiveDateTime, SubsecRound};
+use log::error;
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::future::{ready, Ready};
+
+/// The name of the cookie that holds the session token.
+pub const SESSION_COOKIE: &str = "session";
+
+/// How long a login lasts.
+pub const SESSION_DAYS: i64 = 30;
+
+/// The shortest password allowed.
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// The start of every API token, so they are easy to spot (in a leaked file, for example).
+pub const TOKEN_PREFIX: &str = "jobs_";
+
+/// Why a user could not be added.
+#[derive(Debug)]
+pub enum AuthError {
+    BadUsername,             // Empty, too long, or with spaces.
+    UsernameTaken,           // Another user has the name (ignoring case).
+    PasswordTooShort,        // Shorter than `MIN_PASSWORD_LENGTH`.
+    BadTokenName,            // An API token name that is empty or too long.
+    Hash(String),            // The password could not be hashed.
+    Database(DatabaseError), // The database could not be read or written.
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::BadUsername => {
+                write!(f, "A username must be 1 to 64 characters, without spaces.")
+            }
+            AuthError::UsernameTaken => write!(f, "That username is taken."),
+            AuthError::BadTokenName => write!(f, "A token name must be 1 to 64 characters."),
+            AuthError::PasswordTooShort => write!(
+                f,
+                "A password must be at least {} characters.",
+                MIN_PASSWORD_LENGTH
+            ),
+            AuthError::Hash(err) => write!(f, "Could not hash the password: {}", err),
+            AuthError::Database(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<DatabaseError> for AuthError {
+    fn from(err: DatabaseError) -> Self {
+        AuthError::Database(err)
+    }
+}
+
+/// Hash a password with argon2 and a random salt, for storing.
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AuthError::PasswordTooShort);
+    }
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| AuthError::Hash(err.to_string()))
+}
+
+/// Check a password against a stored argon2 hash.
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// A new random token (256 bits, as hex) for a session.
+pub fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+/// The SHA-256 hash of a token (as hex), the only form of it kept in the database.
+pub fn token_hash(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+// Write bytes as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Add a user who can log in.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `username` - The name to log in with.
+/// * `password` - The password, hashed before it is stored.
+///
+/// # Returns
+/// * `Ok(id)` The id of the new user.
+/// * `Err(AuthError)` if the username or password isn't allowed, or the username is taken.
+pub fn create_user(
+    connection: &Connection,
+    username: &str,
+    password: &str,
+) -> Result<i64, AuthError> {
+    let username = username.trim();
+    if username.is_empty()
+        || username.chars().count() > 64
+        || username.contains(char::is_whitespace)
+    {
+        return Err(AuthError::BadUsername);
+    }
+    let password_hash = hash_password(password)?;
+    add_user(connection, username, &password_hash)?.ok_or(AuthError::UsernameTaken)
+}
+
+/// Log a user in with their password.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `username`, `password` - What the user typed in the login form.
+/// * `now` - The current time (UTC).
+///
+/// # Returns
+/// * `Ok(Some(token))` The token of the new session, for the cookie.
+/// * `Ok(None)` if the username or password is wrong.
+/// * `Err(DatabaseError)` if the database fails.
+pub fn login(
+    connection: &Connection,
+    username: &str,
+    password: &str,
+    now: NaiveDateTime,
+) -> Result<Option<String>, DatabaseError> {
+    let Some((user, password_hash)) = get_user_login(connection, username.trim())? else {
+        return Ok(None);
+    };
+    if !verify_password(password, &password_hash) {
+        return Ok(None);
+    }
+    let token = new_token();
+    add_session(
+        connection,
+        &token_hash(&token),
+        user.id,
+        now,
+        now + Duration::days(SESSION_DAYS),
+    )?;
+    Ok(Some(token))
+}
+
+/// Get the logged-in user of a session token, if the session is still going.
+pub fn session_user(
+    connection: &Connection,
+    token: &str,
+    now: NaiveDateTime,
+) -> Result<Option<User>, DatabaseError> {
+    get_session_user(connection, &token_hash(token), now)
+}
+
+/// End the session of a token.
+pub fn logout(connection: &Connection, token: &str) -> Result<bool, DatabaseError> {
+    remove_session(connection, &token_hash(token))
+}
+
+/// Make a personal API token for a user.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user the token acts for.
+/// * `name` - What the token is for, like "backup script".
+/// * `scope` - What the token is allowed to do.
+/// * `now` - The current time (UTC).
+///
+/// # Returns
+/// * `Ok(token)` The new token, to show the user once: only its hash is kept.
+/// * `Err(AuthError)` if the name isn't allowed or the database fails.
+pub fn create_api_token(
+    connection: &Connection,
+    user_id: i64,
+    name: &str,
+    scope: TokenScope,
+    now: NaiveDateTime,
+) -> Result<String, AuthError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(AuthError::BadTokenName);
+    }
+    let token = format!("{}{}", TOKEN_PREFIX, new_token());
+    add_api_token(connection, user_id, name, &token_hash(&token), scope, now)?;
+    Ok(token)
+}
+
+/// Get the user and scope of an API token, if it hasn't been revoked.
+pub fn token_user(
+    connection: &Connection,
+    token: &str,
+    now: NaiveDateTime,
+) -> Result<Option<(User, TokenScope)>, DatabaseError> {
+    get_token_user(connection, &token_hash(token), now)
+}
+
+/// The scope an API token needs for a request, or `None` if tokens can't be used for it.
+///
+/// Only the JSON endpoints and the calendar feed take tokens. The pages and forms need a login.
+pub fn token_scope_needed(method: &Method, path: &str) -> Option<TokenScope> {
+    match (method.as_str(), path) {
+        ("GET", "/api/jobs" | "/api/stats" | "/calendar.ics") | ("POST", "/duplicates") => {
+            Some(TokenScope::Read)
+        }
+        ("POST", "/update" | "/stage") => Some(TokenScope::Write),
+        _ => None,
+    }
+}
+
+/// Only allow the pages of this site as the page to go back to after logging in
+/// (not `//other.site` or `https://other.site`).
+pub fn safe_next(next: Option<&str>) -> &str {
+    match next {
+        Some(next) if next.starts_with('/') && !next.starts_with("//") && !next.contains('\\') => {
+            next
+        }
+        _ => "/",
+    }
+}
+
+/// The url of the login page, that goes back to `path` after logging in.
+pub fn login_url(path: &str) -> String {
+    if path == "/" {
+        return "/login".to_string();
+    }
+    let encoded: String = path
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect();
+    format!("/login?next={}", encoded)
+}
+
+/// Middleware that lets only logged-in users through.
+///
+/// The user of the session cookie, or of the API token in the `Authorization`
+/// header (or the url of the calendar feed), is put in the request (see the `User` extractor). Without a session,
+/// pages redirect to the login page and JSON requests get `401 Unauthorized`
+/// with `{"success": false}`. A token that isn't allowed to do the request gets
+/// `403 Forbidden`.
+pub async fn require_login(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let database = request.app_data::<web::Data<Database>>().cloned();
+
+    // Scripts send an API token instead of the session cookie, calendar apps in the feed's url:
+    if let Some(token) = bearer_token(request.request()).or_else(|| feed_token(request.request())) {
+        let now = chrono::Utc::now().naive_utc().trunc_subsecs(0); // To the second, like the other times.
+        let found = match database {
+            Some(database) => database
+                .open()
+                .and_then(|connection| token_user(&connection, &token, now))
+                .unwrap_or_else(|err| {
+                    error!("Error checking the API token: {}", err);
+                    None
+                }),
+            None => None,
+        };
+        let needed = token_scope_needed(request.method(), request.path());
+        let response = match (found, needed) {
+            (None, _) => token_error(HttpResponse::Unauthorized(), "Unknown API token."),
+            (Some(_), None) => token_error(
+                HttpResponse::Forbidden(),
+                "API tokens only work on the JSON endpoints and the calendar feed.",
+            ),
+            (Some((_, scope)), Some(needed)) if !scope.allows(needed) => {
+                token_error(HttpResponse::Forbidden(), "This API token is read-only.")
+            }
+            (Some((user, _)), Some(_)) => {
+                request.extensions_mut().insert(user);
+                return Ok(next.call(request).await?.map_into_left_body());
+            }
+        };
+        return Ok(request.into_response(response).map_into_right_body());
+    }
+
+    let user = request
+        .cookie(SESSION_COOKIE)
+        .zip(database)
+        .and_then(|(cookie, database)| {
+            let now = chrono::Utc::now().naive_utc();
+            database
+                .open()
+                .and_then(|connection| session_user(&connection, cookie.value(), now))
+                .unwrap_or_else(|err| {
+                    error!("Error checking the session: {}", err);
+                    None
+                })
+        });
+
+    match user {
+        Some(user) => {
+            request.extensions_mut().insert(user);
+            Ok(next.call(request).await?.map_into_left_body())
+        }
+        None => {
+            let response = unauthorized(request.request());
+            Ok(request.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+pub fn bearer_token(request: &HttpRequest) -> Option<String> {
+    let value = request
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?;
+    let (scheme, token) = value.trim().split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim().to_string())
+}
+
+/// The token in the url of the calendar feed (`/calendar.ics?token=<token>`),
+/// the only route that takes one there.
+pub fn feed_token(request: &HttpRequest) -> Option<String> {
+    if request.path() != "/calendar.ics" {
+        return None;
+    }
+    form_urlencoded::parse(request.query_string().as_bytes())
+        .find(|(name, _)| name == "token")
+        .map(|(_, token)| token.into_owned())
+}
+
+// The JSON answer to a request with an API token that can't be used.
+fn token_error(mut response: actix_web::HttpResponseBuilder, message: &str) -> HttpResponse {
+    response.json(serde_json::json!({ "success": false, "error": message }))
+}
+
+// The answer to a request without a login: JSON for scripts, the login page for browsers.
+fn unauthorized(request: &HttpRequest) -> HttpResponse {
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+    };
+    let wants_json = header("Content-Type").contains("json")
+        || header("Accept").contains("json")
+        || request.path().starts_with("/api/");
+
+    if wants_json {
+        HttpResponse::Unauthorized().json(serde_json::json!({ "success": false }))
+    } else {
+        // Only a page can be gone back to, a form post is lost:
+        let path = match request.method() {
+            &actix_web::http::Method::GET => request
+                .uri()
+                .path_and_query()
+                .map_or("/", |path| path.as_str()),
+            _ => "/",
+        };
+        HttpResponse::SeeOther()
+            .append_header(("LOCATION", login_url(path)))
+            .finish()
+    }
+}
+
+/// Handlers behind `require_login` take the logged-in `User` as an argument.
+impl FromRequest for User {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            request
+                .extensions()
+                .get::<User>()
+                .cloned()
+                .ok_or_else(|| actix_web::error::ErrorUnauthorized("Not logged in.")),
+        )
+    }
+}
+
+/// Testing the accounts and sessions on an in-memory database.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database_methods::{create_table, get_users, remove_user, set_password};
+
+    fn time(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").expect("Bad test time")
+    }
+
+    #[test]
+    fn test_passwords() {
+        let hash = hash_password("correct horse").expect("Failed to hash");
+        assert!(hash.starts_with("$argon2"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong horse", &hash));
+        assert!(!verify_password("correct horse", "not a hash"));
+        assert_ne!(
+            hash,
+            hash_password("correct horse").unwrap(),
+            "Every hash has its own salt."
+        );
+        assert!(matches!(
+            hash_password("short"),
+            Err(AuthError::PasswordTooShort)
+        ));
+    }
+
+    #[test]
+    fn test_tokens() {
+        let token = new_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, new_token());
+        assert_eq!(token_hash(&token), token_hash(&token));
+        assert_ne!(token_hash(&token), token);
+    }
+
+    // Users log in with their password, sessions end when logged out or expired:
+    #[test]
+    fn test_login_and_sessions() {
+        let connection = Connection::open_in_memory().expect("Failed to open in-memory database");
+        create_table(&connection).expect("Failed to create table");
+        let id = create_user(&connection, "nathan", "password123").expect("Failed to add user");
+        assert!(matches!(
+            create_user(&connection, "Nathan", "password456"),
+            Err(AuthError::UsernameTaken)
+        ));
+        assert!(matches!(
+            create_user(&connection, "two words", "password456"),
+            Err(AuthError::BadUsername)
+        ));
+        assert_eq!(get_users(&connection).unwrap().len(), 1);
+
+        let now = time("2025-03-12 10:00");
+        assert_eq!(login(&connection, "nathan", "wrong", now).unwrap(), None);
+        assert_eq!(
+            login(&connection, "nobody", "password123", now).unwrap(),
+            None
+        );
+        let token = login(&connection, " NATHAN ", "password123", now)
+            .unwrap()
+            .expect("The password is right");
+
+        let user = session_user(&connection, &token, now).unwrap().unwrap();
+        assert_eq!((user.id, user.username.as_str()), (id, "nathan"));
+        assert_eq!(session_user(&connection, "guessed", now).unwrap(), None);
+        assert_eq!(
+            session_user(&connection, &token, time("2025-04-12 10:00")).unwrap(),
+            None,
+            "Sessions expire after 30 days."
+        );
+
+        assert!(logout(&connection, &token).unwrap());
+        assert_eq!(session_user(&connection, &token, now).unwrap(), None);
+
+        // Changing the password or removing the user ends their sessions:
+        let token = login(&connection, "nathan", "password123", now)
+            .unwrap()
+            .unwrap();
+        set_password(
+            &connection,
+            "nathan",
+            &hash_password("new password").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(session_user(&connection, &token, now).unwrap(), None);
+        let token = login(&connection, "nathan", "new password", now)
+            .unwrap()
+            .unwrap();
+        assert!(remove_user(&connection, "nathan").unwrap());
+        assert_eq!(session_user(&connection, &token, now).unwrap(), None);
+    }
+
+    // API tokens act for their user, only on the JSON endpoints their scope allows:
+    #[test]
+    fn test_api_tokens() {
+        let connection = Connection::open_in_memory().expect("Failed to open in-memory database");
+        create_table(&connection).expect("Failed to create table");
+        let id = create_user(&connection, "nathan", "password123").expect("Failed to add user");
+        let now = time("2025-03-12 10:00");
+
+        let token = create_api_token(&connection, id, " backup ", TokenScope::Read, now).unwrap();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        let (user, scope) = token_user(&connection, &token, now).unwrap().unwrap();
+        assert_eq!((user.id, scope), (id, TokenScope::Read));
+        assert_eq!(token_user(&connection, "jobs_guessed", now).unwrap(), None);
+        let stored: String = connection
+            .query_row("SELECT token_hash FROM api_tokens", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, token_hash(&token), "Only the hash is kept.");
+        assert!(matches!(
+            create_api_token(&connection, id, "  ", TokenScope::Read, now),
+            Err(AuthError::BadTokenName)
+        ));
+
+        assert_eq!(
+            token_scope_needed(&Method::GET, "/api/stats"),
+            Some(TokenScope::Read)
+        );
+        assert_eq!(
+            token_scope_needed(&Method::POST, "/update"),
+            Some(TokenScope::Write)
+        );
+        assert_eq!(
+            token_scope_needed(&Method::GET, "/calendar.ics"),
+            Some(TokenScope::Read)
+        );
+        assert_eq!(token_scope_needed(&Method::POST, "/rem"), None);
+        assert_eq!(token_scope_needed(&Method::GET, "/"), None);
+        assert!(TokenScope::Write.allows(TokenScope::Read));
+        assert!(!TokenScope::Read.allows(TokenScope::Write));
+    }
+
+    #[test]
+    fn test_redirects() {
+        assert_eq!(safe_next(Some("/jobs/3")), "/jobs/3");
+        assert_eq!(safe_next(Some("//evil.example")), "/");
+        assert_eq!(safe_next(Some("https://evil.example")), "/");
+        assert_eq!(safe_next(Some("/\\evil.example")), "/");
+        assert_eq!(safe_next(None), "/");
+
+        assert_eq!(login_url("/"), "/login");
+        assert_eq!(login_url("/?q=rust dev"), "/login?next=/%3Fq%3Drust%20dev");
+    }
+}
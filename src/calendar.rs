@@ -0,0 +1,217 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Calendar Module
+//!
+//! Builds iCalendar (RFC 5545) files from the events of each job, so the
+//! deadlines, follow-up reminders and interviews show up in a calendar app.
+//! Every event has a UID made from its database id, so a calendar that
+//! subscribes to the feed updates its events instead of duplicating them.
+
+use crate::job::{EventKind, Job, JobEvent};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+/// The product id written in every calendar file.
+const PRODUCT_ID: &str = "-//Job Application List//EN";
+
+/// The stable UID of an event, the same every time the calendar is built.
+pub fn event_uid(event: &JobEvent) -> String {
+    format!(
+        "event-{}@job-application-list",
+        event.id.unwrap_or_default()
+    )
+}
+
+/// Escape text for an iCalendar value (backslashes, commas, semicolons and newlines).
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Write one content line, folded so no line is longer than 75 octets.
+fn push_line(ics: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            ics.push_str("\r\n "); // A folded line starts with a space.
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+/// Write the VEVENT of one event.
+fn push_event(ics: &mut String, event: &JobEvent, job: Option<&Job>, stamp: &str) {
+    let title = job.map_or_else(|| format!("Job {}", event.job_id), |j| j.get_title());
+    let summary = match job.and_then(|j| j.get_company()) {
+        Some(company) => format!("{}: {} ({})", event.kind.label(), title, company),
+        None => format!("{}: {}", event.kind.label(), title),
+    };
+
+    push_line(ics, "BEGIN:VEVENT");
+    push_line(ics, &format!("UID:{}", event_uid(event)));
+    push_line(ics, &format!("DTSTAMP:{}", stamp));
+    match event.time {
+        // Timed events use the local ("floating") time, interviews last an hour:
+        Some(time) => {
+            let start = event.date.and_time(time);
+            push_line(ics, &format!("DTSTART:{}", start.format("%Y%m%dT%H%M%S")));
+            let minutes = if event.kind == EventKind::Interview {
+                60
+            } else {
+                15
+            };
+            let end = start + Duration::minutes(minutes);
+            push_line(ics, &format!("DTEND:{}", end.format("%Y%m%dT%H%M%S")));
+        }
+        // All-day events end on the next day:
+        None => {
+            push_line(
+                ics,
+                &format!("DTSTART;VALUE=DATE:{}", event.date.format("%Y%m%d")),
+            );
+            let end = event.date + Duration::days(1);
+            push_line(ics, &format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")));
+        }
+    }
+    push_line(ics, &format!("SUMMARY:{}", escape_text(&summary)));
+
+    let link = job
+        .and_then(|j| j.get_optional_link())
+        .filter(|l| l.starts_with("http"));
+    let description: Vec<String> = event.note.iter().cloned().chain(link.clone()).collect();
+    if !description.is_empty() {
+        push_line(
+            ics,
+            &format!("DESCRIPTION:{}", escape_text(&description.join("\n"))),
+        );
+    }
+    if let Some(link) = link {
+        push_line(ics, &format!("URL:{}", link));
+    }
+    push_line(ics, "END:VEVENT");
+}
+
+/// Build an iCalendar file from events.
+///
+/// # Arguments
+/// * `name` - The name of the calendar shown by calendar apps.
+/// * `events` - The events to write.
+/// * `jobs` - The jobs, used for the title, company and link of each event.
+/// * `now` - The time the calendar is built (written as each event's `DTSTAMP`).
+///
+/// # Returns
+/// * The calendar as a `String` with CRLF line endings.
+pub fn build_calendar(name: &str, events: &[JobEvent], jobs: &[Job], now: DateTime<Utc>) -> String {
+    let jobs: HashMap<i64, &Job> = jobs
+        .iter()
+        .filter_map(|job| job.get_id().map(|id| (id, job)))
+        .collect();
+    let stamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+
+    let mut ics = String::new();
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(&mut ics, &format!("PRODID:{}", PRODUCT_ID));
+    push_line(&mut ics, "CALSCALE:GREGORIAN");
+    push_line(&mut ics, "METHOD:PUBLISH");
+    push_line(&mut ics, &format!("X-WR-CALNAME:{}", escape_text(name)));
+    for event in events {
+        push_event(&mut ics, event, jobs.get(&event.job_id).copied(), &stamp);
+    }
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+/// Testing the calendar files.
+/// The events are made by hand and the calendar text is checked
+/// line by line against the RFC 5545 format.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // Helper function to make an event with an id:
+    fn event(id: i64, kind: &str, time: Option<&str>, note: Option<&str>) -> JobEvent {
+        let mut event = JobEvent::parse(1, kind, "2025-03-14", time, note).unwrap();
+        event.id = Some(id);
+        event
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_build_calendar() {
+        let job = Job::new(
+            Some(1),
+            "Bus Driver".to_string(),
+            31.0,
+            "1".to_string(),
+            Some("https://example.com/jobs/1".to_string()),
+        )
+        .with_company(Some("TriMet".to_string()));
+        let events = vec![
+            event(7, "deadline", None, None),
+            event(
+                8,
+                "interview",
+                Some("09:30"),
+                Some("Room 2; bring ID, resume"),
+            ),
+        ];
+
+        let ics = build_calendar("Jobs", &events, &[job], now());
+        let lines: Vec<&str> = ics.split("\r\n").collect();
+
+        assert_eq!(lines[0], "BEGIN:VCALENDAR");
+        assert!(lines.contains(&"UID:event-7@job-application-list"));
+        assert!(lines.contains(&"DTSTAMP:20250301T120000Z"));
+        assert!(lines.contains(&"DTSTART;VALUE=DATE:20250314"));
+        assert!(lines.contains(&"DTEND;VALUE=DATE:20250315"));
+        assert!(lines.contains(&"DTSTART:20250314T093000"));
+        assert!(lines.contains(&"DTEND:20250314T103000"));
+        assert!(lines.contains(&"SUMMARY:Interview: Bus Driver (TriMet)"));
+        assert!(lines
+            .contains(&"DESCRIPTION:Room 2\\; bring ID\\, resume\\nhttps://example.com/jobs/1"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn test_uid_is_stable() {
+        let first = build_calendar("Jobs", &[event(3, "follow_up", None, None)], &[], now());
+        let later = build_calendar(
+            "Jobs",
+            &[event(3, "follow_up", Some("10:00"), None)],
+            &[],
+            now() + Duration::days(1),
+        );
+        assert!(first.contains("UID:event-3@job-application-list"));
+        assert!(later.contains("UID:event-3@job-application-list"));
+    }
+
+    #[test]
+    fn test_long_lines_are_folded() {
+        let note = "x".repeat(200);
+        let ics = build_calendar(
+            "Jobs",
+            &[event(1, "deadline", None, Some(&note))],
+            &[],
+            now(),
+        );
+        assert!(ics.split("\r\n").all(|line| line.len() <= 75));
+        assert!(
+            ics.replace("\r\n ", "").contains(&note),
+            "Unfolding restores the line."
+        );
+    }
+}
@@ -0,0 +1,352 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Charts Module
+//!
+//! Draws the charts of the statistics page as SVG images, in Rust, so
+//! they need no JavaScript or network to show up. The same SVG is
+//! embedded in the page, served on `/charts/<name>.svg` and written to
+//! files by the `charts` command.
+
+use crate::database_methods::{applications_per_week, get_jobs, DatabaseError};
+use crate::job::Job;
+use crate::stats::{WeekCount, WEEKS_SHOWN};
+use chrono::{Duration, NaiveDate};
+
+/// The size of every chart, in pixels.
+const WIDTH: f64 = 600.0;
+const HEIGHT: f64 = 320.0;
+
+/// The space around the plot for the title and the axis labels.
+const MARGIN_LEFT: f64 = 50.0;
+const MARGIN_RIGHT: f64 = 20.0;
+const MARGIN_TOP: f64 = 40.0;
+const MARGIN_BOTTOM: f64 = 70.0;
+
+/// The number of steps on the y axis.
+const Y_TICKS: i64 = 5;
+
+/// The color of the bars (the same green as the buttons).
+const BAR_COLOR: &str = "#4CAF50";
+
+/// The charts drawn from the job list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChartKind {
+    Applications, // Applications made each week.
+    Pay,          // A histogram of the hourly rates.
+    Status,       // The number of jobs at each status.
+}
+
+impl ChartKind {
+    /// Every chart, in the order they are shown.
+    pub const ALL: [ChartKind; 3] = [ChartKind::Applications, ChartKind::Pay, ChartKind::Status];
+
+    /// The name of the chart, used in its url and file name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChartKind::Applications => "applications",
+            ChartKind::Pay => "pay",
+            ChartKind::Status => "status",
+        }
+    }
+
+    /// Find a chart by its name.
+    pub fn from_name(name: &str) -> Option<ChartKind> {
+        ChartKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// The title drawn at the top of the chart.
+    pub fn title(&self) -> &'static str {
+        match self {
+            ChartKind::Applications => "Applications per Week",
+            ChartKind::Pay => "Hourly Pay",
+            ChartKind::Status => "Jobs by Status",
+        }
+    }
+}
+
+/// Draw a chart of a user's jobs.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user whose jobs are drawn.
+/// * `kind` - The chart to draw.
+///
+/// # Returns
+/// * `Ok(String)` with the SVG image.
+/// * `Err(DatabaseError)` if the jobs can't be read.
+pub fn render_chart(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    kind: ChartKind,
+) -> Result<String, DatabaseError> {
+    Ok(match kind {
+        ChartKind::Applications => {
+            let per_week: Vec<WeekCount> = applications_per_week(connection, user_id, WEEKS_SHOWN)?
+                .into_iter()
+                .map(|(week, applications)| WeekCount { week, applications })
+                .collect();
+            applications_chart(&per_week)
+        }
+        ChartKind::Pay => pay_chart(&get_jobs(connection, user_id)?),
+        ChartKind::Status => status_chart(&get_jobs(connection, user_id)?),
+    })
+}
+
+/// Draw the applications made each week, with the weeks without any
+/// applications (between the first and last week) as empty bars.
+pub fn applications_chart(per_week: &[WeekCount]) -> String {
+    let weeks: Vec<(NaiveDate, i64)> = per_week
+        .iter()
+        .filter_map(|w| Some((w.week.parse().ok()?, w.applications)))
+        .collect();
+
+    let mut bars: Vec<(String, i64)> = Vec::new();
+    if let (Some(first), Some(last)) = (weeks.first(), weeks.last()) {
+        let mut week = first.0;
+        while week <= last.0 {
+            let count = weeks
+                .iter()
+                .find(|(w, _)| *w == week)
+                .map_or(0, |(_, count)| *count);
+            bars.push((week.format("%b %d").to_string(), count));
+            week += Duration::days(7);
+        }
+    }
+    bar_chart(ChartKind::Applications.title(), &bars)
+}
+
+/// Draw a histogram of the hourly rates, in bins of $5 (wider bins when
+/// the rates are spread out, so there are at most 10).
+pub fn pay_chart(jobs: &[Job]) -> String {
+    let rates: Vec<f64> = jobs.iter().map(|job| job.get_hourly() as f64).collect();
+    let mut bars: Vec<(String, i64)> = Vec::new();
+
+    if let (Some(min), Some(max)) = (
+        rates.iter().copied().reduce(f64::min),
+        rates.iter().copied().reduce(f64::max),
+    ) {
+        let width = 5.0 * ((max - min) / 50.0).ceil().max(1.0);
+        let mut start = (min / width).floor() * width;
+        while start <= max {
+            let end = start + width;
+            let count = rates.iter().filter(|&&r| r >= start && r < end).count();
+            bars.push((format!("${}-{}", start, end), count as i64));
+            start = end;
+        }
+    }
+    bar_chart(ChartKind::Pay.title(), &bars)
+}
+
+/// Draw the number of jobs not applied to and at each stage. Jobs applied
+/// to without a stage count as "Applied".
+pub fn status_chart(jobs: &[Job]) -> String {
+    let status = |job: &Job| -> String {
+        if job.get_applied() != "Yes" {
+            return "Not applied".to_string();
+        }
+        let name = job.get_stage().map_or("applied", |stage| stage.name());
+        name[..1].to_uppercase() + &name[1..]
+    };
+
+    let mut bars: Vec<(String, i64)> = Vec::new();
+    for job in jobs {
+        let label = status(job);
+        match bars.iter_mut().find(|(l, _)| *l == label) {
+            Some((_, count)) => *count += 1,
+            None => bars.push((label, 1)),
+        }
+    }
+    bar_chart(ChartKind::Status.title(), &bars)
+}
+
+/// Escape text for an SVG (XML) document.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Draw a bar chart with one bar for each label.
+///
+/// # Arguments
+/// * `title` - The title drawn at the top.
+/// * `bars` - The label and the count of each bar, from left to right.
+///
+/// # Returns
+/// * The SVG image, with a "No data yet" message when there are no bars.
+pub fn bar_chart(title: &str, bars: &[(String, i64)]) -> String {
+    let title = escape_xml(title);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\" font-family=\"Arial, sans-serif\" role=\"img\" \
+         aria-label=\"{t}\">\n<title>{t}</title>\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"#fff\"/>\n\
+         <text x=\"{x}\" y=\"24\" text-anchor=\"middle\" font-size=\"16\" fill=\"#333\">{t}</text>\n",
+        w = WIDTH,
+        h = HEIGHT,
+        x = WIDTH / 2.0,
+        t = title
+    );
+
+    if bars.is_empty() {
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-size=\"14\" fill=\"#777\">No data yet</text>\n</svg>\n",
+            WIDTH / 2.0,
+            HEIGHT / 2.0
+        ));
+        return svg;
+    }
+
+    // The y axis goes up to a multiple of the number of ticks, so every tick is a whole number:
+    let max = bars.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    let step = ((max + Y_TICKS - 1) / Y_TICKS).max(1);
+    let top = step * Y_TICKS;
+
+    let plot_width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_height = HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    let bottom = MARGIN_TOP + plot_height;
+    let y_of = |value: i64| bottom - plot_height * value as f64 / top as f64;
+
+    // Grid lines and labels of the y axis:
+    for tick in 0..=Y_TICKS {
+        let value = tick * step;
+        let y = y_of(value);
+        svg.push_str(&format!(
+            "<line x1=\"{}\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\" stroke=\"#ddd\"/>\n\
+             <text x=\"{}\" y=\"{}\" text-anchor=\"end\" font-size=\"11\" fill=\"#333\">{}</text>\n",
+            MARGIN_LEFT,
+            WIDTH - MARGIN_RIGHT,
+            MARGIN_LEFT - 6.0,
+            y + 4.0,
+            value,
+            y = y
+        ));
+    }
+
+    // The bars, with their count as a tooltip, and their labels (slanted when there are many):
+    let slot = plot_width / bars.len() as f64;
+    let slanted = bars.len() > 6;
+    for (index, (label, count)) in bars.iter().enumerate() {
+        let label = escape_xml(label);
+        let x = MARGIN_LEFT + slot * index as f64;
+        let y = y_of(*count);
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\">\
+             <title>{}: {}</title></rect>\n",
+            x + slot * 0.15,
+            y,
+            slot * 0.7,
+            bottom - y,
+            BAR_COLOR,
+            label,
+            count
+        ));
+
+        let center = x + slot / 2.0;
+        let label_y = bottom + 16.0;
+        if slanted {
+            svg.push_str(&format!(
+                "<text x=\"{center:.1}\" y=\"{label_y}\" text-anchor=\"end\" font-size=\"11\" \
+                 fill=\"#333\" transform=\"rotate(-40 {center:.1} {label_y})\">{label}</text>\n"
+            ));
+        } else {
+            svg.push_str(&format!(
+                "<text x=\"{center:.1}\" y=\"{label_y}\" text-anchor=\"middle\" font-size=\"11\" \
+                 fill=\"#333\">{label}</text>\n"
+            ));
+        }
+    }
+
+    // The axes, drawn last so they are on top of the bars:
+    svg.push_str(&format!(
+        "<line x1=\"{l}\" y1=\"{t}\" x2=\"{l}\" y2=\"{b}\" stroke=\"#333\"/>\n\
+         <line x1=\"{l}\" y1=\"{b}\" x2=\"{r}\" y2=\"{b}\" stroke=\"#333\"/>\n</svg>\n",
+        l = MARGIN_LEFT,
+        t = MARGIN_TOP,
+        b = bottom,
+        r = WIDTH - MARGIN_RIGHT
+    ));
+    svg
+}
+
+/// Testing the charts on hand-made data.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::Stage;
+
+    // Create a job with a rate and application status for the charts.
+    fn job(rate: f32, applied: &str, stage: Option<Stage>) -> Job {
+        Job::new(None, "Job".to_string(), rate, applied.to_string(), None).with_stage(stage)
+    }
+
+    // Count the bars drawn in a chart (the background is a rect too).
+    fn count_bars(svg: &str) -> usize {
+        svg.matches(&format!("fill=\"{}\"", BAR_COLOR)).count()
+    }
+
+    // Every bar is drawn with its label, and the labels are escaped:
+    #[test]
+    fn test_bar_chart() {
+        let svg = bar_chart(
+            "Pay <Jobs>",
+            &[("A & B".to_string(), 3), ("C".to_string(), 7)],
+        );
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(count_bars(&svg), 2);
+        assert!(svg.contains("<title>A &amp; B: 3</title>"));
+        assert!(svg.contains("Pay &lt;Jobs&gt;"));
+        assert!(
+            svg.contains(">10</text>"),
+            "The y axis goes up to 10 for 7."
+        );
+
+        let empty = bar_chart("Nothing", &[]);
+        assert!(empty.contains("No data yet"));
+        assert_eq!(count_bars(&empty), 0);
+    }
+
+    // The weeks without applications are drawn as empty bars:
+    #[test]
+    fn test_applications_chart() {
+        let per_week = vec![
+            WeekCount {
+                week: "2025-03-03".to_string(),
+                applications: 2,
+            },
+            WeekCount {
+                week: "2025-03-24".to_string(),
+                applications: 1,
+            },
+        ];
+        let svg = applications_chart(&per_week);
+        assert_eq!(count_bars(&svg), 4, "Four weeks from Mar 03 to Mar 24.");
+        assert!(svg.contains("<title>Mar 10: 0</title>"));
+        assert!(svg.contains("<title>Mar 24: 1</title>"));
+    }
+
+    // The rates are counted in $5 bins, and the statuses by stage:
+    #[test]
+    fn test_pay_and_status_charts() {
+        let jobs = vec![
+            job(17.0, "Yes", Some(Stage::Interview)),
+            job(19.5, "Yes", None),
+            job(24.0, "No", None),
+            job(31.0, "No", None),
+        ];
+
+        let pay = pay_chart(&jobs);
+        assert!(pay.contains("<title>$15-20: 2</title>"));
+        assert!(pay.contains("<title>$25-30: 0</title>"));
+        assert!(pay.contains("<title>$30-35: 1</title>"));
+
+        let status = status_chart(&jobs);
+        assert!(status.contains("<title>Not applied: 2</title>"));
+        assert!(status.contains("<title>Applied: 1</title>"));
+        assert!(status.contains("<title>Interview: 1</title>"));
+    }
+}
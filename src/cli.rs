@@ -0,0 +1,1047 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # CLI Module
+//!
+//! The command line commands that work on the database without
+//! starting the server. The first command line argument picks the
+//! command, for example `list import jobs.csv --dry-run`. When the
+//! first argument is not a command, it is the port for the server.
+
+use crate::archive::{archive_link, archive_upload, HttpFetcher};
+use crate::auth::{create_api_token, create_user, hash_password};
+use crate::charts::{render_chart, ChartKind};
+use crate::csv_reader::{parse_delimiter, read_csv_file, ConflictPolicy, Field, ImportOptions};
+use crate::database_methods::{
+    add_event, bulk_update, enter_data, get_api_tokens, get_events, get_users, merge_jobs,
+    remove_api_token, remove_event, remove_user, set_goal, set_password, set_stage, DatabaseError,
+};
+use crate::duplicates::{duplicate_groups, find_duplicates};
+use crate::goals::goal_progress;
+use crate::job::{check_hourly, BulkAction, Job, JobEvent, Stage, TokenScope, User};
+use crate::links::{check_links, LinkHealth, UreqClient};
+use crate::posting::{parse_posting, Posting};
+use crate::stats::{get_funnel, get_stats, FunnelStep};
+use chrono::SubsecRound;
+use rusqlite::Connection;
+use std::io::{IsTerminal, Read};
+
+/// Check if a command line argument is one of the CLI commands.
+pub fn is_command(arg: &str) -> bool {
+    matches!(
+        arg,
+        "add"
+            | "import"
+            | "duplicates"
+            | "merge"
+            | "event"
+            | "stage"
+            | "bulk"
+            | "stats"
+            | "charts"
+            | "goal"
+            | "archive"
+            | "check-links"
+            | "user"
+            | "token"
+    )
+}
+
+/// Run the CLI command in `args[0]` with the rest of `args` as its arguments.
+///
+/// The commands work on the jobs of the user named by the `JOBS_USER`
+/// environment variable, which can be left out when there is only one user.
+///
+/// # Arguments
+/// * `args` - The command line arguments, starting with the command name.
+/// * `connection` - A reference to an active SQLite connection.
+///
+/// # Returns
+/// * The exit code for the process (0 on success).
+pub fn run(args: &[String], connection: &Connection) -> i32 {
+    if args[0] == "user" {
+        return user(&args[1..], connection);
+    }
+
+    // Every other command works on the jobs of one user:
+    let user_id = match cli_user(connection) {
+        Ok(user) => user.id,
+        Err(message) => {
+            eprintln!("{}", message);
+            return 1;
+        }
+    };
+
+    match args[0].as_str() {
+        "add" => add(&args[1..], connection, user_id),
+        "import" => import(&args[1..], connection, user_id),
+        "duplicates" => duplicates(&args[1..], connection, user_id),
+        "merge" => merge(&args[1..], connection, user_id),
+        "event" => event(&args[1..], connection, user_id),
+        "stage" => stage(&args[1..], connection, user_id),
+        "bulk" => bulk(&args[1..], connection, user_id),
+        "stats" => stats(&args[1..], connection, user_id),
+        "charts" => charts(&args[1..], connection, user_id),
+        "goal" => goal(&args[1..], connection, user_id),
+        "archive" => archive(&args[1..], connection, user_id),
+        "check-links" => check_links_command(&args[1..], connection, user_id),
+        "token" => token(&args[1..], connection, user_id),
+        command => {
+            eprintln!("Unknown command: {}", command);
+            2
+        }
+    }
+}
+
+// The user the commands work on, from `JOBS_USER` or the only user there is.
+fn cli_user(connection: &Connection) -> Result<User, String> {
+    let users = get_users(connection).map_err(|e| format!("Error reading the users: {}", e))?;
+    match std::env::var("JOBS_USER") {
+        Ok(name) => users
+            .into_iter()
+            .find(|user| user.username.eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| format!("There is no user {} (from JOBS_USER).", name)),
+        Err(_) => match <[User; 1]>::try_from(users) {
+            Ok([user]) => Ok(user),
+            Err(users) if users.is_empty() => {
+                Err("No users yet, add one first with: list user add <name>".to_string())
+            }
+            Err(_) => Err(
+                "There are several users, pick one with JOBS_USER=<name> before the command."
+                    .to_string(),
+            ),
+        },
+    }
+}
+
+const ADD_USAGE: &str = "Usage: list add <title> <hourly_rate> [--company <name>] [--link <url>] \
+    [--applied] [--description <text>|-]
+       list add --from-html <page.html> [<title> <hourly_rate>] [options]
+  --description - reads the description from stdin (paste it, then press Ctrl-D)
+  --from-html reads the details from a saved posting page, the other options replace them";
+
+/// Add a job, with its description typed, pasted or piped in, or its details read from a saved posting page.
+///
+/// Usage: `add <title> <hourly_rate> [--company <name>] [--link <url>] [--applied] [--description <text>|-] [--from-html <page.html>]`
+///
+/// With `--from-html`, the title and rate may be left out when the page has them.
+/// Jobs that may already be in the list are shown as a warning, the job is added anyway.
+fn add(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    let mut positional: Vec<&String> = Vec::new();
+    let mut company: Option<String> = None;
+    let mut link: Option<String> = None;
+    let mut applied = false;
+    let mut description: Option<String> = None;
+    let mut from_html: Option<&String> = None;
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        match (option.as_str(), options.clone().next()) {
+            ("--applied", _) => applied = true,
+            ("--company", Some(value)) => company = Some(value.clone()),
+            ("--link", Some(value)) => link = Some(value.clone()),
+            ("--from-html", Some(value)) => from_html = Some(value),
+            ("--description", Some(value)) if value == "-" => {
+                let mut text = String::new();
+                if let Err(e) = std::io::stdin().read_to_string(&mut text) {
+                    eprintln!("Error reading the description: {}", e);
+                    return 1;
+                }
+                description = Some(text);
+            }
+            ("--description", Some(value)) => description = Some(value.clone()),
+            (flag, _) if flag.starts_with("--") => {
+                eprintln!("{}", ADD_USAGE);
+                return 2;
+            }
+            _ => {
+                positional.push(option);
+                continue; // A positional argument has no value to skip.
+            }
+        }
+        if option != "--applied" {
+            options.next(); // Skip the option's value.
+        }
+    }
+
+    // The details of the saved page fill in whatever wasn't given:
+    let posting = match from_html {
+        Some(path) => match std::fs::read(path) {
+            Ok(bytes) => parse_posting(&String::from_utf8_lossy(&bytes)),
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path, e);
+                return 1;
+            }
+        },
+        None => Posting::default(),
+    };
+    if from_html.is_some() && posting.is_empty() {
+        eprintln!("Warning: no job details were found in the page.");
+    }
+
+    let title = positional
+        .first()
+        .map(|t| t.to_string())
+        .or(posting.title.clone());
+    let rate = match positional.get(1) {
+        Some(rate) => match rate.parse::<f32>() {
+            Ok(rate) => Some(rate),
+            Err(_) => {
+                eprintln!("{}", ADD_USAGE);
+                return 2;
+            }
+        },
+        None => posting.hourly,
+    };
+    let (title, rate) = match (title, rate) {
+        (Some(title), Some(rate)) if positional.len() <= 2 => (title, rate),
+        (title, _) if from_html.is_some() && positional.len() <= 2 => {
+            let missing = if title.is_none() {
+                "title"
+            } else {
+                "hourly rate"
+            };
+            eprintln!(
+                "The page has no {}, give it after the file: list add --from-html <page.html> <title> <hourly_rate>",
+                missing
+            );
+            return 2;
+        }
+        _ => {
+            eprintln!("{}", ADD_USAGE);
+            return 2;
+        }
+    };
+    // The same rates as the add form, a NaN rate can't be read back from the database:
+    let rate = match check_hourly(rate) {
+        Ok(rate) => rate,
+        Err(message) => {
+            eprintln!("{}", message);
+            return 2;
+        }
+    };
+    let link = link.or(posting.link.clone()).unwrap_or_default();
+    let company = company.or(posting.company.clone());
+    let description = description.or(posting.full_description());
+
+    let job = Job::new(None, title, rate, (applied as i32).to_string(), Some(link))
+        .with_company(company)
+        .with_description(description);
+
+    match find_duplicates(connection, user_id, &job) {
+        Ok(duplicates) => {
+            for duplicate in duplicates {
+                println!(
+                    "Warning: may be a duplicate of job {} ({}, {})",
+                    duplicate.id, duplicate.title, duplicate.reason
+                );
+            }
+        }
+        Err(e) => eprintln!("Error checking for duplicates: {}", e),
+    }
+
+    match enter_data(connection, user_id, &job) {
+        Ok(id) => {
+            println!("Added job {}.", id);
+            0
+        }
+        Err(e) => {
+            eprintln!("Error adding the job: {}", e);
+            1
+        }
+    }
+}
+
+const IMPORT_USAGE: &str = "Usage: list import <file.csv> [--dry-run] [--atomic] \
+    [--delimiter <char|tab>] [--map <field>=<header>]... [--on-conflict skip|update|duplicate]
+  fields: id, title, rate, applied, link, company";
+
+/// Import a csv file into the database and print the report.
+///
+/// Usage: `import <file.csv> [--dry-run] [--atomic] [--delimiter <char|tab>] [--map <field>=<header>]...`
+///
+/// Each `--map` adds a header name for a field, for example `--map title=Position`.
+/// `--on-conflict` picks what happens to rows already in the database (default: skip).
+fn import(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    let mut file: Option<&str> = None;
+    let mut options = ImportOptions::default();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => options.dry_run = true,
+            "--atomic" => options.atomic = true,
+            "--delimiter" => match args.next().and_then(|d| parse_delimiter(d)) {
+                Some(delimiter) => options.delimiter = delimiter,
+                None => {
+                    eprintln!("The delimiter must be a single character or \"tab\".");
+                    return 2;
+                }
+            },
+            "--on-conflict" => match args.next().and_then(|p| ConflictPolicy::from_name(p)) {
+                Some(policy) => options.on_conflict = policy,
+                None => {
+                    eprintln!("{}", IMPORT_USAGE);
+                    return 2;
+                }
+            },
+            "--map" => match args.next().and_then(|m| m.split_once('=')) {
+                Some((name, header)) => match Field::from_name(name) {
+                    Some(field) => options.mapping.add_alias(field, header),
+                    None => {
+                        eprintln!("Unknown field \"{}\".\n{}", name, IMPORT_USAGE);
+                        return 2;
+                    }
+                },
+                None => {
+                    eprintln!("{}", IMPORT_USAGE);
+                    return 2;
+                }
+            },
+            flag if flag.starts_with("--") => {
+                eprintln!("Unknown import option: {}", flag);
+                return 2;
+            }
+            path => file = Some(path),
+        }
+    }
+
+    let file = match file {
+        Some(file) => file,
+        None => {
+            eprintln!("{}", IMPORT_USAGE);
+            return 2;
+        }
+    };
+
+    match read_csv_file(file, connection, user_id, &options) {
+        Ok(report) => {
+            print!("{}", report);
+            if report.errors.is_empty() {
+                0
+            } else {
+                1
+            }
+        }
+        Err(e) => {
+            eprintln!("Error reading CSV file: {}", e);
+            1
+        }
+    }
+}
+
+/// List the groups of duplicate jobs, and merge each group into its first job with `--merge`.
+///
+/// Usage: `duplicates [--merge]`
+fn duplicates(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    let merge = match args {
+        [] => false,
+        [flag] if flag == "--merge" => true,
+        _ => {
+            eprintln!("Usage: list duplicates [--merge]");
+            return 2;
+        }
+    };
+
+    let groups = match duplicate_groups(connection, user_id) {
+        Ok(groups) => groups,
+        Err(e) => {
+            eprintln!("Error finding duplicates: {}", e);
+            return 1;
+        }
+    };
+    if groups.is_empty() {
+        println!("No duplicate jobs found.");
+        return 0;
+    }
+
+    for group in &groups {
+        println!("Duplicate group:");
+        for job in group {
+            println!(
+                "  {:>4}  {} ({}) {} [{}]",
+                job.get_id().unwrap_or_default(),
+                job.get_title(),
+                job.get_company().unwrap_or_default(),
+                job.get_optional_link().unwrap_or_default(),
+                job.get_stage().map_or("not applied", |stage| stage.name())
+            );
+        }
+
+        if merge {
+            let ids: Vec<i64> = group.iter().filter_map(|job| job.get_id()).collect();
+            match merge_jobs(connection, user_id, ids[0], &ids[1..]) {
+                Ok(removed) => println!("  Merged {} jobs into job {}.", removed, ids[0]),
+                Err(e) => {
+                    eprintln!("Error merging jobs: {}", e);
+                    return 1;
+                }
+            }
+        }
+    }
+    0
+}
+
+/// Merge jobs into one job, filling in its missing details and removing the others.
+///
+/// Usage: `merge <keep_id> <duplicate_id>...`
+fn merge(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    let ids: Option<Vec<i64>> = args.iter().map(|a| a.parse().ok()).collect();
+    let ids = match ids {
+        Some(ids) if ids.len() >= 2 => ids,
+        _ => {
+            eprintln!("Usage: list merge <keep_id> <duplicate_id>...");
+            return 2;
+        }
+    };
+
+    match merge_jobs(connection, user_id, ids[0], &ids[1..]) {
+        Ok(removed) => {
+            println!("Merged {} jobs into job {}.", removed, ids[0]);
+            0
+        }
+        Err(DatabaseError::JobNotFound(id)) => {
+            eprintln!("There is no job with id {}.", id);
+            1
+        }
+        Err(e) => {
+            eprintln!("Error merging jobs: {}", e);
+            1
+        }
+    }
+}
+
+const EVENT_USAGE: &str =
+    "Usage: list event add <job_id> <deadline|follow-up|interview> <YYYY-MM-DD> [HH:MM] [note...]
+       list event list [job_id]
+       list event remove <event_id>";
+
+/// Add, list and remove the dates (deadlines, follow-ups, interviews) of jobs.
+///
+/// Usage: `event add <job_id> <kind> <date> [time] [note...]`, `event list [job_id]`, `event remove <id>`
+fn event(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    match args.first().map(String::as_str) {
+        Some("add") if args.len() >= 4 => {
+            let job_id = match args[1].parse::<i64>() {
+                Ok(id) => id,
+                Err(_) => {
+                    eprintln!("{}", EVENT_USAGE);
+                    return 2;
+                }
+            };
+            // The time is optional, so only take it if it looks like one:
+            let (time, note) = match args.get(4) {
+                Some(t) if t.contains(':') => (Some(t.as_str()), &args[5..]),
+                _ => (None, &args[4..]),
+            };
+            let note = note.join(" ");
+
+            let event = match JobEvent::parse(job_id, &args[2], &args[3], time, Some(&note)) {
+                Ok(event) => event,
+                Err(message) => {
+                    eprintln!("{}", message);
+                    return 2;
+                }
+            };
+            match add_event(connection, user_id, &event) {
+                Ok(Some(id)) => {
+                    println!("Added event {} to job {}.", id, job_id);
+                    0
+                }
+                Ok(None) => {
+                    eprintln!("There is no job with id {}.", job_id);
+                    1
+                }
+                Err(e) => {
+                    eprintln!("Error adding event: {}", e);
+                    1
+                }
+            }
+        }
+        Some("list") if args.len() <= 2 => {
+            let job_id = args.get(1).and_then(|id| id.parse::<i64>().ok());
+            match get_events(connection, user_id, job_id) {
+                Ok(events) => {
+                    for event in events {
+                        println!(
+                            "{:>4}  job {:>4}  {} {:<5}  {:<20}  {}",
+                            event.id.unwrap_or_default(),
+                            event.job_id,
+                            event.date,
+                            event
+                                .time
+                                .map(|t| t.format("%H:%M").to_string())
+                                .unwrap_or_default(),
+                            event.kind.label(),
+                            event.note.unwrap_or_default()
+                        );
+                    }
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Error listing events: {}", e);
+                    1
+                }
+            }
+        }
+        Some("remove") if args.len() == 2 => match args[1].parse::<i64>() {
+            Ok(id) => match remove_event(connection, user_id, id) {
+                Ok(true) => {
+                    println!("Removed event {}.", id);
+                    0
+                }
+                Ok(false) => {
+                    eprintln!("There is no event with id {}.", id);
+                    1
+                }
+                Err(e) => {
+                    eprintln!("Error removing event: {}", e);
+                    1
+                }
+            },
+            Err(_) => {
+                eprintln!("{}", EVENT_USAGE);
+                2
+            }
+        },
+        _ => {
+            eprintln!("{}", EVENT_USAGE);
+            2
+        }
+    }
+}
+
+/// Move a job to a new stage of its application.
+///
+/// Usage: `stage <job_id> <applied|screen|interview|offer|rejected>`
+fn stage(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    let (job_id, stage) = match args {
+        [id, stage] => match (id.parse::<i64>(), Stage::from_name(stage)) {
+            (Ok(id), Some(stage)) => (id, stage),
+            _ => {
+                eprintln!("Usage: list stage <job_id> <applied|screen|interview|offer|rejected>");
+                return 2;
+            }
+        },
+        _ => {
+            eprintln!("Usage: list stage <job_id> <applied|screen|interview|offer|rejected>");
+            return 2;
+        }
+    };
+
+    match set_stage(connection, user_id, job_id, stage) {
+        Ok(true) => {
+            println!("Moved job {} to {}.", job_id, stage.name());
+            0
+        }
+        Ok(false) => {
+            eprintln!("There is no job with id {}.", job_id);
+            1
+        }
+        Err(e) => {
+            eprintln!("Error changing the stage: {}", e);
+            1
+        }
+    }
+}
+
+const BULK_USAGE: &str = "Usage: list bulk <action> <job_id>...
+  actions: stage <applied|screen|interview|offer|rejected>, not-applied, tag <tag>,
+           archive, unarchive, delete";
+
+/// Make the same change to many jobs at once, all of them or none.
+///
+/// Usage: `bulk <action> [stage|tag] <job_id>...`
+fn bulk(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    // Only the stage and tag actions take a value before the ids:
+    let (action, ids) = match args {
+        [name, value, ids @ ..] if name == "stage" || name == "tag" => {
+            (BulkAction::parse(name, Some(value)), ids)
+        }
+        [name, ids @ ..] => (BulkAction::parse(name, None), ids),
+        [] => {
+            eprintln!("{}", BULK_USAGE);
+            return 2;
+        }
+    };
+    let action = match action {
+        Ok(action) => action,
+        Err(message) => {
+            eprintln!("{}\n{}", message, BULK_USAGE);
+            return 2;
+        }
+    };
+    let ids: Option<Vec<i64>> = ids.iter().map(|id| id.parse().ok()).collect();
+    let ids = match ids {
+        Some(ids) if !ids.is_empty() => ids,
+        _ => {
+            eprintln!("{}", BULK_USAGE);
+            return 2;
+        }
+    };
+
+    match bulk_update(connection, user_id, &ids, &action) {
+        Ok(changed) => {
+            println!("{} of {} jobs {}.", changed, ids.len(), action.done());
+            0
+        }
+        Err(e) => {
+            eprintln!("Error changing the jobs, none were changed: {}", e);
+            1
+        }
+    }
+}
+
+/// Print the statistics of the job list, or only the application funnel.
+///
+/// Usage: `stats [funnel]`
+fn stats(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    let result = match args.first().map(String::as_str) {
+        None => get_stats(connection, user_id).map(|stats| {
+            println!(
+                "{} jobs, {} applied, {} not applied ({:.1}% applied).",
+                stats.total, stats.applied, stats.not_applied, stats.application_rate
+            );
+            print_funnel(&stats.funnel);
+        }),
+        Some("funnel") if args.len() == 1 => {
+            get_funnel(connection, user_id).map(|f| print_funnel(&f))
+        }
+        _ => {
+            eprintln!("Usage: list stats [funnel]");
+            return 2;
+        }
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error getting the statistics: {}", e);
+            1
+        }
+    }
+}
+
+// Print the funnel as a table, with "-" for the missing numbers.
+fn print_funnel(funnel: &[FunnelStep]) {
+    let number = |value: Option<f64>, suffix: &str| {
+        value.map_or("-".to_string(), |v| format!("{:.1}{}", v, suffix))
+    };
+    println!(
+        "{:<10}  {:>5}  {:>10}  {:>10}",
+        "stage", "jobs", "conversion", "median days"
+    );
+    for step in funnel {
+        println!(
+            "{:<10}  {:>5}  {:>10}  {:>10}",
+            step.stage.name(),
+            step.jobs,
+            number(step.conversion, "%"),
+            number(step.median_days, "")
+        );
+    }
+}
+
+/// Write every chart of the statistics page as an SVG file.
+///
+/// Usage: `charts [directory]` (default: the current directory)
+fn charts(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    let directory = match args {
+        [] => std::path::Path::new("."),
+        [directory] => std::path::Path::new(directory),
+        _ => {
+            eprintln!("Usage: list charts [directory]");
+            return 2;
+        }
+    };
+    if let Err(e) = std::fs::create_dir_all(directory) {
+        eprintln!("Error creating {}: {}", directory.display(), e);
+        return 1;
+    }
+
+    for kind in ChartKind::ALL {
+        let svg = match render_chart(connection, user_id, kind) {
+            Ok(svg) => svg,
+            Err(e) => {
+                eprintln!("Error drawing the {} chart: {}", kind.name(), e);
+                return 1;
+            }
+        };
+        let file = directory.join(format!("{}.svg", kind.name()));
+        if let Err(e) = std::fs::write(&file, svg) {
+            eprintln!("Error writing {}: {}", file.display(), e);
+            return 1;
+        }
+        println!("Wrote {}", file.display());
+    }
+    0
+}
+
+/// Set the weekly application goal, or print the progress of this week.
+///
+/// Usage: `goal set <applications_per_week>`, `goal status`
+fn goal(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    const USAGE: &str = "Usage: list goal set <applications_per_week>
+       list goal status";
+
+    match args {
+        [command, target] if command == "set" => match target.parse::<i64>() {
+            Ok(target) if target > 0 => match set_goal(connection, user_id, target) {
+                Ok(()) => {
+                    println!("Weekly goal set to {} applications.", target);
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Error setting the goal: {}", e);
+                    1
+                }
+            },
+            _ => {
+                eprintln!("The goal must be a number of applications, at least 1.");
+                2
+            }
+        },
+        [command] if command == "status" => {
+            match goal_progress(connection, user_id, chrono::Utc::now().date_naive()) {
+                Ok(Some(p)) => {
+                    println!(
+                        "Week of {}: {}/{} applications ({:.0}%), {} to go.",
+                        p.week, p.applications, p.target, p.percent, p.remaining
+                    );
+                    println!(
+                        "Streak: {} weeks (best: {} weeks).",
+                        p.current_streak, p.best_streak
+                    );
+                    0
+                }
+                Ok(None) => {
+                    println!(
+                        "No weekly goal yet, set one with: list goal set <applications_per_week>"
+                    );
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Error getting the goal progress: {}", e);
+                    1
+                }
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
+
+/// Save a copy of a job's posting page, fetched from its link or read from a saved file.
+///
+/// Usage: `archive <job_id> [--file <page.html>]`
+fn archive(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    const USAGE: &str = "Usage: list archive <job_id> [--file <page.html>]";
+    let job_id = match args.first().map(|id| id.parse::<i64>()) {
+        Some(Ok(id)) => id,
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let result = match &args[1..] {
+        [] => archive_link(connection, user_id, job_id, &HttpFetcher::default()),
+        [flag, path] if flag == "--file" => match std::fs::read(path) {
+            Ok(bytes) => {
+                let html = String::from_utf8_lossy(&bytes);
+                let name = std::path::Path::new(path)
+                    .file_name()
+                    .map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+                archive_upload(connection, user_id, job_id, &html, &name)
+            }
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path, e);
+                return 1;
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    match result {
+        Ok(()) => {
+            println!("Archived the page of job {}.", job_id);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// Check the link of every job, and list the ones that don't work.
+///
+/// Usage: `check-links [--all]`
+///
+/// The status of each link is saved, so the job list flags the dead ones.
+/// With `--all`, the working links are listed too.
+fn check_links_command(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    let all = match args {
+        [] => false,
+        [flag] if flag == "--all" => true,
+        _ => {
+            eprintln!("Usage: list check-links [--all]");
+            return 2;
+        }
+    };
+
+    // To the second, like the other times of a job:
+    let now = chrono::Utc::now().naive_utc().trunc_subsecs(0);
+    let checks = match check_links(connection, user_id, &UreqClient::default(), now) {
+        Ok(checks) => checks,
+        Err(e) => {
+            eprintln!("Error checking the links: {}", e);
+            return 1;
+        }
+    };
+
+    let count = |health: LinkHealth| checks.iter().filter(|c| c.health() == health).count();
+    for check in checks
+        .iter()
+        .filter(|c| all || c.health() != LinkHealth::Ok)
+    {
+        let status = match &check.error {
+            Some(error) => format!("no answer: {}", error),
+            None => check.status.to_string(),
+        };
+        println!(
+            "{:<7}  job {}: {} <{}> ({})",
+            check.health().name(),
+            check.job_id,
+            check.title,
+            check.link,
+            status
+        );
+    }
+    println!(
+        "Checked {} links: {} ok, {} dead, {} can't be checked (the site blocks programs).",
+        checks.len(),
+        count(LinkHealth::Ok),
+        count(LinkHealth::Dead),
+        count(LinkHealth::Blocked)
+    );
+    0
+}
+
+/// Add, list and remove the users who can log in to the server, or change a password.
+///
+/// Usage: `user add <name>`, `user list`, `user remove <name>`, `user passwd <name>`
+///
+/// The password is typed twice when run in a terminal, otherwise it is
+/// read as one line from stdin (for scripts).
+fn user(args: &[String], connection: &Connection) -> i32 {
+    const USAGE: &str = "Usage: list user add <name>
+       list user list
+       list user remove <name>
+       list user passwd <name>";
+
+    match args {
+        [command, name] if command == "add" => {
+            let Some(password) = read_new_password() else {
+                return 1;
+            };
+            match create_user(connection, name, &password) {
+                Ok(id) => {
+                    println!("Added user {} (id {}).", name.trim(), id);
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            }
+        }
+        [command] if command == "list" => match get_users(connection) {
+            Ok(users) if users.is_empty() => {
+                println!("No users yet, add one with: list user add <name>");
+                0
+            }
+            Ok(users) => {
+                for user in users {
+                    println!("{:>4}  {}", user.id, user.username);
+                }
+                0
+            }
+            Err(e) => {
+                eprintln!("Error listing the users: {}", e);
+                1
+            }
+        },
+        [command, name] if command == "remove" => match remove_user(connection, name) {
+            Ok(true) => {
+                println!("Removed user {}.", name);
+                0
+            }
+            Ok(false) => {
+                eprintln!("There is no user {}.", name);
+                1
+            }
+            Err(e) => {
+                eprintln!("Error removing the user: {}", e);
+                1
+            }
+        },
+        [command, name] if command == "passwd" => {
+            let Some(password) = read_new_password() else {
+                return 1;
+            };
+            let hash = match hash_password(&password) {
+                Ok(hash) => hash,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return 1;
+                }
+            };
+            match set_password(connection, name, &hash) {
+                Ok(true) => {
+                    println!(
+                        "Changed the password of {}, their sessions were ended.",
+                        name
+                    );
+                    0
+                }
+                Ok(false) => {
+                    eprintln!("There is no user {}.", name);
+                    1
+                }
+                Err(e) => {
+                    eprintln!("Error changing the password: {}", e);
+                    1
+                }
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
+
+/// Make, list and revoke the API tokens that scripts use instead of logging in.
+///
+/// Usage: `token create <name> [--write]`, `token list`, `token revoke <id>`
+///
+/// Tokens are read-only unless made with `--write`. A new token is printed
+/// once, only its hash is kept.
+fn token(args: &[String], connection: &Connection, user_id: i64) -> i32 {
+    const USAGE: &str = "Usage: list token create <name> [--write]
+       list token list
+       list token revoke <id>";
+
+    match args {
+        [command, name, flags @ ..] if command == "create" => {
+            let scope = match flags {
+                [] => TokenScope::Read,
+                [flag] if flag == "--write" => TokenScope::Write,
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            };
+            let now = chrono::Utc::now().naive_utc().trunc_subsecs(0);
+            match create_api_token(connection, user_id, name, scope, now) {
+                Ok(token) => {
+                    println!("{}", token);
+                    eprintln!(
+                        "Made the {} token {}, copy it now, it won't be shown again.",
+                        scope.name(),
+                        name.trim()
+                    );
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            }
+        }
+        [command] if command == "list" => match get_api_tokens(connection, user_id) {
+            Ok(tokens) if tokens.is_empty() => {
+                println!("No API tokens yet, make one with: list token create <name>");
+                0
+            }
+            Ok(tokens) => {
+                for token in tokens {
+                    let used = token
+                        .last_used_at
+                        .map_or("never used".to_string(), |at| format!("last used {}", at));
+                    println!(
+                        "{:>4}  {:<5}  {}  (made {}, {})",
+                        token.id,
+                        token.scope.name(),
+                        token.name,
+                        token.created_at,
+                        used
+                    );
+                }
+                0
+            }
+            Err(e) => {
+                eprintln!("Error listing the API tokens: {}", e);
+                1
+            }
+        },
+        [command, id] if command == "revoke" => {
+            let Ok(id) = id.parse::<i64>() else {
+                eprintln!("{}", USAGE);
+                return 2;
+            };
+            match remove_api_token(connection, user_id, id) {
+                Ok(true) => {
+                    println!("Revoked API token {}.", id);
+                    0
+                }
+                Ok(false) => {
+                    eprintln!("There is no API token {}.", id);
+                    1
+                }
+                Err(e) => {
+                    eprintln!("Error revoking the API token: {}", e);
+                    1
+                }
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
+
+// Read a new password: typed twice without echo in a terminal, or one line from stdin.
+fn read_new_password() -> Option<String> {
+    if !std::io::stdin().is_terminal() {
+        let mut line = String::new();
+        return match std::io::stdin().read_line(&mut line) {
+            Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+            Err(e) => {
+                eprintln!("Error reading the password: {}", e);
+                None
+            }
+        };
+    }
+
+    let typed = rpassword::prompt_password("Password: ")
+        .and_then(|password| Ok((password, rpassword::prompt_password("Again: ")?)));
+    match typed {
+        Ok((password, again)) if password == again => Some(password),
+        Ok(_) => {
+            eprintln!("The passwords don't match.");
+            None
+        }
+        Err(e) => {
+            eprintln!("Error reading the password: {}", e);
+            None
+        }
+    }
+}
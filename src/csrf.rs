@@ -0,0 +1,331 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # CSRF Module
+//!
+//! Protection from cross-site request forgery: another site open in the
+//! browser could post a form to this server, and the browser would send the
+//! session cookie with it. Every form of a logged-in user carries a CSRF
+//! token, derived from their session token, that another site can't know.
+//! The `verify_csrf` middleware rejects the POSTs that don't send it back,
+//! in a `csrf_token` form field or an `X-CSRF-Token` header (for JavaScript),
+//! and the ones sent from a page of another site.
+//!
+//! Requests with an API token are not checked: a browser never adds the
+//! `Authorization` header on its own.
+//!
+//! The login form has no session yet to take a token from, so `verify_origin`
+//! only rejects the logins posted from a page of another site (which would log
+//! the user into the other site's account without them noticing).
+
+use crate::auth::{bearer_token, token_hash, SESSION_COOKIE};
+use actix_multipart::form::{text::Text, MultipartForm};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse};
+use log::info;
+use std::future::{ready, Ready};
+
+/// The name of the header that holds the CSRF token, for JavaScript requests.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// The largest form body accepted: the csv and page uploads (5MB each), and a
+/// previewed csv file sent back to be imported, which percent-encoding makes longer.
+pub const MAX_FORM_BYTES: usize = 16 * 1024 * 1024;
+
+/// The CSRF token of a session: the hash of the session token, so it needs no
+/// storage and changes with every login. The session token itself stays in the
+/// HttpOnly cookie.
+pub fn csrf_token(session_token: &str) -> String {
+    token_hash(&format!("csrf {}", session_token))
+}
+
+/// The CSRF token of the logged-in user, for the forms of a page.
+///
+/// Handlers put it in the page as `csrf_token`. It is empty without a session cookie.
+pub struct CsrfToken(pub String);
+
+impl FromRequest for CsrfToken {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let token = request
+            .cookie(SESSION_COOKIE)
+            .map_or(String::new(), |cookie| csrf_token(cookie.value()));
+        ready(Ok(CsrfToken(token)))
+    }
+}
+
+// Only the CSRF token of an uploaded (multipart) form:
+#[derive(MultipartForm)]
+struct CsrfUpload {
+    csrf_token: Option<Text<String>>,
+}
+
+/// Middleware that rejects forged requests to the routes that change something.
+///
+/// GET requests, requests without a session cookie (`require_login` turns them
+/// away) and requests with an API token go through. Any other request needs
+/// the CSRF token of its session, and must not come from a page of another site.
+/// A forged request gets `403 Forbidden`: JSON for scripts, a message for browsers.
+pub async fn verify_csrf(
+    mut request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    let session = request.cookie(SESSION_COOKIE);
+    let (Some(session), false, None) = (session, safe, bearer_token(request.request())) else {
+        return Ok(next.call(request).await?.map_into_left_body());
+    };
+    let expected = csrf_token(session.value());
+
+    if !same_origin(request.request()) {
+        info!("Rejected a request to {} from another site", request.path());
+        let response = forbidden(request.request());
+        return Ok(request.into_response(response).map_into_right_body());
+    }
+
+    let sent = match header_value(request.request(), CSRF_HEADER) {
+        Some(token) => Some(token.to_string()),
+        None => {
+            // The token is in the form, so the body is read, and put back for the handler:
+            let body = request
+                .extract::<web::Payload>()
+                .await?
+                .to_bytes_limited(MAX_FORM_BYTES)
+                .await
+                .map_err(actix_web::error::ErrorPayloadTooLarge)??;
+            let sent = form_token(request.request(), &body).await;
+            request.set_payload(Payload::from(body));
+            sent
+        }
+    };
+
+    if sent.is_some_and(|sent| same_token(&sent, &expected)) {
+        Ok(next.call(request).await?.map_into_left_body())
+    } else {
+        info!(
+            "Rejected a request to {} without its CSRF token",
+            request.path()
+        );
+        let response = forbidden(request.request());
+        Ok(request.into_response(response).map_into_right_body())
+    }
+}
+
+/// Middleware that rejects the POSTs sent from a page of another site, for the
+/// routes used before logging in (the login form), which have no CSRF token.
+///
+/// A forged request gets `403 Forbidden`, with a message for the browser.
+pub async fn verify_origin(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    if safe || same_origin(request.request()) {
+        return Ok(next.call(request).await?.map_into_left_body());
+    }
+    info!("Rejected a request to {} from another site", request.path());
+    let response = forbidden(request.request());
+    Ok(request.into_response(response).map_into_right_body())
+}
+
+// The CSRF token of a posted form, or an uploaded one.
+async fn form_token(request: &HttpRequest, body: &web::Bytes) -> Option<String> {
+    let content_type = header_value(request, header::CONTENT_TYPE.as_str()).unwrap_or("");
+    if content_type.starts_with("multipart/form-data") {
+        MultipartForm::<CsrfUpload>::from_request(request, &mut Payload::from(body.clone()))
+            .await
+            .ok()
+            .and_then(|form| form.into_inner().csrf_token)
+            .map(|token| token.into_inner())
+    } else {
+        // Read from the body itself, the form extractor would refuse a long form:
+        form_urlencoded::parse(body)
+            .find(|(name, _)| name == "csrf_token")
+            .map(|(_, token)| token.into_owned())
+    }
+}
+
+// Check that a request doesn't come from a page of another site. Browsers send
+// the `Origin` of the page with every POST (older ones only the `Referer`, the
+// page's url); requests with neither aren't from a page.
+fn same_origin(request: &HttpRequest) -> bool {
+    let source = header_value(request, header::ORIGIN.as_str())
+        .or_else(|| header_value(request, header::REFERER.as_str()));
+    match source {
+        Some(url) => url.split_once("://").is_some_and(|(_, rest)| {
+            rest.split('/').next() == Some(request.connection_info().host())
+        }),
+        None => true,
+    }
+}
+
+// Compare two tokens in the same time wherever they differ, so the time taken
+// doesn't tell how much of a guessed token was right.
+fn same_token(sent: &str, expected: &str) -> bool {
+    sent.len() == expected.len()
+        && sent
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |differ, (a, b)| differ | (a ^ b))
+            == 0
+}
+
+// The text of a header, if the request has it.
+fn header_value<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+// The answer to a forged request: JSON for scripts, a message for browsers.
+fn forbidden(request: &HttpRequest) -> HttpResponse {
+    let wants_json = header_value(request, header::CONTENT_TYPE.as_str())
+        .is_some_and(|value| value.contains("json"))
+        || header_value(request, CSRF_HEADER).is_some();
+    if wants_json {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "error": "Missing or wrong CSRF token, reload the page."
+        }))
+    } else {
+        HttpResponse::Forbidden()
+            .body("This form has expired or came from another site. Go back, reload the page and try again.")
+    }
+}
+
+/// Testing forged and real requests through the middleware.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::cookie::Cookie;
+    use actix_web::http::header::ContentType;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+
+    const SESSION: &str = "session-token";
+
+    // A handler that echoes the posted body, to check it reaches the handler whole:
+    async fn echo(body: String) -> HttpResponse {
+        HttpResponse::Ok().body(body)
+    }
+
+    #[actix_web::test]
+    async fn test_csrf_token() {
+        assert_eq!(csrf_token(SESSION), csrf_token(SESSION));
+        assert_ne!(csrf_token(SESSION), csrf_token("other-session"));
+        assert_ne!(csrf_token(SESSION), token_hash(SESSION));
+        assert!(same_token("abc", "abc"));
+        assert!(!same_token("abc", "abd"));
+        assert!(!same_token("abc", "abcd"));
+    }
+
+    // Forged form posts and JSON requests are rejected, real ones go through:
+    #[actix_web::test]
+    async fn test_verify_csrf() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(verify_csrf))
+                .route("/rem", web::post().to(echo)),
+        )
+        .await;
+        let token = csrf_token(SESSION);
+        let form = |body: String| {
+            test::TestRequest::post()
+                .uri("/rem")
+                .cookie(Cookie::new(SESSION_COOKIE, SESSION))
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload(body)
+        };
+        let status = |request: test::TestRequest| async {
+            test::call_service(&app, request.to_request())
+                .await
+                .status()
+        };
+
+        // A page of another site can post the form, but can't know the token:
+        assert_eq!(
+            status(form("id=3".to_string())).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(form("id=3&csrf_token=guessed".to_string())).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(form(format!("id=3&csrf_token={}", csrf_token("other")))).await,
+            StatusCode::FORBIDDEN
+        );
+
+        // The form of this site has it, and the handler still gets the whole body:
+        let body = format!("id=3&csrf_token={}", token);
+        let response = test::call_service(&app, form(body.clone()).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, body.as_bytes());
+
+        // ...unless it was sent from another site:
+        let forged = form(body.clone()).insert_header(("Origin", "https://evil.example"));
+        assert_eq!(status(forged).await, StatusCode::FORBIDDEN);
+        let real = form(body).insert_header(("Origin", "http://localhost:8080"));
+        assert_eq!(status(real).await, StatusCode::OK);
+
+        // A long form (a pasted posting, a csv file) is read whole to find its token:
+        let long = format!("description={}&csrf_token={}", "x".repeat(64 * 1024), token);
+        let response = test::call_service(&app, form(long.clone()).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await.len(), long.len());
+
+        // JavaScript sends the token in a header:
+        let json = || {
+            test::TestRequest::post()
+                .uri("/rem")
+                .cookie(Cookie::new(SESSION_COOKIE, SESSION))
+                .set_json(serde_json::json!({ "id": 3, "applied": true }))
+        };
+        assert_eq!(status(json()).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            status(json().insert_header((CSRF_HEADER, token.as_str()))).await,
+            StatusCode::OK
+        );
+
+        // Uploaded files carry it in a field of their form:
+        let upload = |token: &str| {
+            let body = format!(
+                "--XYZ\r\nContent-Disposition: form-data; name=\"csrf_token\"\r\n\r\n{}\r\n\
+                --XYZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"jobs.csv\"\r\n\
+                Content-Type: text/csv\r\n\r\ntitle,hourly\nWelder,28\r\n--XYZ--\r\n",
+                token
+            );
+            test::TestRequest::post()
+                .uri("/rem")
+                .cookie(Cookie::new(SESSION_COOKIE, SESSION))
+                .insert_header(("Content-Type", "multipart/form-data; boundary=XYZ"))
+                .set_payload(body)
+        };
+        assert_eq!(status(upload("guessed")).await, StatusCode::FORBIDDEN);
+        assert_eq!(status(upload(&token)).await, StatusCode::OK);
+
+        // Without a session there is nothing to forge (the login middleware turns it away),
+        // and scripts with an API token can't be forged by a browser:
+        let no_session = test::TestRequest::post()
+            .uri("/rem")
+            .insert_header(ContentType::form_url_encoded())
+            .set_payload("id=3");
+        assert_eq!(status(no_session).await, StatusCode::OK);
+        let api = form("id=3".to_string()).insert_header(("Authorization", "Bearer jobs_abc"));
+        assert_eq!(status(api).await, StatusCode::OK);
+    }
+}
@@ -4,27 +4,462 @@
 
 //! Read the data inside of a csv file that conforms to a
 //! job application. The csv file path is passed into the
-//! method. The method checks for headers, then loops through
-//! the file catpuring each line and unwraps their data.
+//! method. The method maps the headers to the job fields, then
+//! loops through the file catpuring each line and validates its data.
+//!
+//! Columns are found by their header name, not their position, so
+//! a spreadsheet with its columns in another order (or with extra
+//! columns) still imports correctly. A `ColumnMapping` holds the
+//! header names (aliases) accepted for each field.
+//!
+//! Every import produces an `ImportReport` that counts the rows
+//! imported and skipped and keeps an error message (with the line
+//! number) for each row that could not be imported.
+//!
+//! A row that matches a job already in the database (same id, or a
+//! duplicate found by the `duplicates` module) is handled by the
+//! `ConflictPolicy` of the import.
 
-use csv::Reader;
+use crate::database_methods::{owns_job, sync_status_history, update_job, DatabaseError};
+use crate::duplicates::find_duplicates;
+use crate::job::Job;
+use csv::{Reader, ReaderBuilder, StringRecord};
 use rusqlite::params;
 use std::error::Error;
+use std::fmt;
+use std::io::Read;
+
+/// The job fields a csv column can be mapped to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Id,
+    Title,
+    Rate,
+    Applied,
+    Link,
+    Company,
+}
+
+impl Field {
+    /// Every field, in the order of the original csv format.
+    pub const ALL: [Field; 6] = [
+        Field::Id,
+        Field::Title,
+        Field::Rate,
+        Field::Applied,
+        Field::Link,
+        Field::Company,
+    ];
+
+    /// The short name of the field used on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Id => "id",
+            Field::Title => "title",
+            Field::Rate => "rate",
+            Field::Applied => "applied",
+            Field::Link => "link",
+            Field::Company => "company",
+        }
+    }
+
+    /// Find a field by its short name.
+    pub fn from_name(name: &str) -> Option<Field> {
+        Field::ALL
+            .into_iter()
+            .find(|field| field.name() == name.trim().to_lowercase())
+    }
+
+    // Index of the field in the mapping arrays:
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// The header names accepted for each job field.
+///
+/// Header names are compared without case or surrounding spaces.
+/// Aliases added with `add_alias` are tried before the defaults.
+#[derive(Clone, Debug)]
+pub struct ColumnMapping {
+    aliases: [Vec<String>; 6],
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        let aliases = |names: &[&str]| names.iter().map(|s| s.to_string()).collect();
+        Self {
+            aliases: [
+                aliases(&["id", "job_id"]),
+                aliases(&["job_title", "title", "position", "job"]),
+                aliases(&["hourly_rate", "rate", "pay", "hourly", "wage"]),
+                aliases(&["applied", "applied?"]),
+                aliases(&["link", "url", "application link"]),
+                aliases(&["company", "employer", "organization"]),
+            ],
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Add a header name for a field, tried before the existing ones.
+    pub fn add_alias(&mut self, field: Field, header: &str) {
+        self.aliases[field.index()].insert(0, header.trim().to_lowercase());
+    }
+
+    /// Find the column of each field from the csv headers.
+    ///
+    /// # Returns
+    /// * `Ok(Columns)` with the index of every field that has a column.
+    /// * `Err(String)` if there is no column for the job title.
+    fn resolve(&self, headers: &StringRecord) -> Result<Columns, String> {
+        let mut columns = Columns {
+            indexes: [None; 6],
+            headers: headers.iter().map(|h| h.trim().to_string()).collect(),
+        };
+        for field in Field::ALL {
+            columns.indexes[field.index()] = self.aliases[field.index()].iter().find_map(|alias| {
+                columns
+                    .headers
+                    .iter()
+                    .position(|header| header.to_lowercase() == *alias)
+            });
+        }
+        if columns.index(Field::Title).is_none() {
+            return Err(format!(
+                "no job title column found in the headers ({})",
+                columns.headers.join(", ")
+            ));
+        }
+        Ok(columns)
+    }
+}
+
+/// The csv column used for each field, found from the headers.
+#[derive(Clone, Debug)]
+pub struct Columns {
+    indexes: [Option<usize>; 6],
+    headers: Vec<String>,
+}
+
+impl Columns {
+    /// The column index of a field, if the csv file has one.
+    pub fn index(&self, field: Field) -> Option<usize> {
+        self.indexes[field.index()]
+    }
+
+    /// The header name of the column used for a field.
+    pub fn header(&self, field: Field) -> Option<&str> {
+        self.index(field).map(|i| self.headers[i].as_str())
+    }
+
+    // Get the value of a field from a record:
+    fn get<'r>(&self, record: &'r StringRecord, field: Field) -> Option<&'r str> {
+        self.index(field).and_then(|i| record.get(i)).map(str::trim)
+    }
+}
+
+impl fmt::Display for Columns {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mapped: Vec<String> = Field::ALL
+            .iter()
+            .map(|field| match self.header(*field) {
+                Some(header) => format!("{} <- \"{}\"", field.name(), header),
+                None => format!("{} <- (none)", field.name()),
+            })
+            .collect();
+        write!(f, "{}", mapped.join(", "))
+    }
+}
+
+/// What to do with a row that matches a job already in the database.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    #[default]
+    Skip, // Leave the existing job alone and skip the row.
+    Update,    // Overwrite the existing job with the row.
+    Duplicate, // Add the row as a new job anyway (with a new id if its id is taken).
+}
+
+impl ConflictPolicy {
+    /// Find a policy by its command line name: skip, update or duplicate.
+    pub fn from_name(name: &str) -> Option<ConflictPolicy> {
+        match name {
+            "skip" => Some(ConflictPolicy::Skip),
+            "update" => Some(ConflictPolicy::Update),
+            "duplicate" => Some(ConflictPolicy::Duplicate),
+            _ => None,
+        }
+    }
+}
+
+/// Options that control how a csv file is imported.
+///
+/// # Fields
+/// - `dry_run`: Validate and insert every row, then roll everything back so the database is unchanged.
+/// - `atomic`: All-or-nothing. If any row fails, no rows are imported.
+/// - `delimiter`: The byte separating the columns (`b','` by default).
+/// - `mapping`: The header names accepted for each field.
+/// - `on_conflict`: What to do with rows that are already in the database.
+#[derive(Clone, Debug)]
+pub struct ImportOptions {
+    pub dry_run: bool,
+    pub atomic: bool,
+    pub delimiter: u8,
+    pub mapping: ColumnMapping,
+    pub on_conflict: ConflictPolicy,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            atomic: false,
+            delimiter: b',',
+            mapping: ColumnMapping::default(),
+            on_conflict: ConflictPolicy::default(),
+        }
+    }
+}
+
+impl ImportOptions {
+    /// Create a csv reader for these options over any source of csv data.
+    pub fn reader<R: Read>(&self, source: R) -> Reader<R> {
+        ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .flexible(true) // Short rows are reported by the field checks.
+            .from_reader(source)
+    }
+}
+
+/// Parse a delimiter name: one ASCII character, or "tab".
+pub fn parse_delimiter(name: &str) -> Option<u8> {
+    match name {
+        "tab" | "\\t" => Some(b'\t'),
+        _ if name.len() == 1 && name.is_ascii() => Some(name.as_bytes()[0]),
+        _ => None,
+    }
+}
+
+/// One row of a csv file: the job it holds, or why it is invalid.
+#[derive(Clone, Debug)]
+pub struct ParsedRow {
+    pub line: u64,                // The line number in the csv file.
+    pub job: Result<Job, String>, // The validated job or the error message.
+}
+
+/// A single row of the csv file that could not be imported.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowError {
+    pub line: u64,       // The line number in the csv file (the header is line 1).
+    pub message: String, // Why the row was rejected.
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// The result of importing a csv file.
+///
+/// # Fields
+/// - `imported`: The number of rows added to the database (or that would have been in a dry run).
+/// - `updated`: The number of existing jobs overwritten by a row.
+/// - `skipped`: The number of rows rejected, skipped as duplicates, or rolled back.
+/// - `errors`: One `RowError` for every rejected row.
+/// - `conflicts`: One note for every row that matched an existing job.
+/// - `committed`: Whether the imported rows were actually saved to the database.
+/// - `columns`: The columns that were mapped to each field.
+#[derive(Clone, Debug, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub errors: Vec<RowError>,
+    pub conflicts: Vec<RowError>,
+    pub committed: bool,
+    pub columns: Option<Columns>,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verb = if self.committed {
+            "imported"
+        } else {
+            "would import"
+        };
+        if let Some(columns) = &self.columns {
+            writeln!(f, "Columns: {}", columns)?;
+        }
+        writeln!(
+            f,
+            "{} {} rows, updated {} rows, skipped {} rows.",
+            verb, self.imported, self.updated, self.skipped
+        )?;
+        for error in &self.errors {
+            writeln!(f, "  {}", error)?;
+        }
+        for conflict in &self.conflicts {
+            writeln!(f, "  {}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+/// Validate one csv record and turn it into a `Job`.
+///
+/// Returns an error message describing the first invalid field.
+fn parse_record(record: &StringRecord, columns: &Columns) -> Result<Job, String> {
+    // The incremental id of each job (empty lets the database choose one):
+    let id = match columns.get(record, Field::Id) {
+        None | Some("") => None,
+        Some(s) => Some(
+            s.parse::<i64>()
+                .map_err(|_| format!("invalid id \"{}\"", s))?,
+        ),
+    };
+
+    // The job title:
+    let title = match columns.get(record, Field::Title) {
+        None | Some("") => return Err("missing job title".to_string()),
+        Some(s) => s.to_string(),
+    };
+
+    // Paid hourly rate in dolars:
+    let hourly_rate = match columns.get(record, Field::Rate) {
+        None | Some("") => return Err("missing hourly rate".to_string()),
+        Some(s) => match s.parse::<f32>() {
+            Ok(rate) if rate.is_finite() && rate >= 0.0 => rate,
+            _ => return Err(format!("invalid hourly rate \"{}\"", s)),
+        },
+    };
+
+    // 1 or 0 for yes or no (empty means not applied):
+    let applied = match columns
+        .get(record, Field::Applied)
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None | Some("") | Some("0") | Some("no") | Some("false") => false,
+        Some("1") | Some("yes") | Some("true") => true,
+        Some(s) => return Err(format!("invalid applied value \"{}\"", s)),
+    };
+
+    // The link to the application is optional:
+    let link = columns
+        .get(record, Field::Link)
+        .filter(|link| !link.is_empty())
+        .map(str::to_string);
+
+    // The company is optional:
+    let company = columns.get(record, Field::Company).map(str::to_string);
+
+    Ok(Job::new(id, title, hourly_rate, (applied as i64).to_string(), link).with_company(company))
+}
+
+/// What happened to one valid row of the csv file.
+enum Outcome {
+    Inserted,
+    Updated(String),    // A note on the job that was overwritten.
+    Skipped(String),    // A note on the job that was kept instead.
+    Duplicated(String), // A note on the job it duplicates.
+}
+
+/// Insert one job from the csv file for a user, handling a conflict with one of their jobs.
+fn import_job(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job: &Job,
+    policy: ConflictPolicy,
+) -> Result<Outcome, DatabaseError> {
+    // A job of the user with the same id is a conflict, otherwise look for a duplicate
+    // posting. The id is only kept for a new job when no job (of any user) has it:
+    let (same_id, free_id) = match job.get_id() {
+        Some(id) => {
+            let taken: bool = connection.query_row(
+                "SELECT EXISTS (SELECT 1 FROM jobs WHERE id = ?)",
+                [id],
+                |row| row.get(0),
+            )?;
+            (owns_job(connection, user_id, id)?, (!taken).then_some(id))
+        }
+        None => (false, None),
+    };
+    let conflict = if same_id {
+        job.get_id().map(|id| (id, "same id".to_string()))
+    } else {
+        find_duplicates(connection, user_id, job)?
+            .first()
+            .map(|d| (d.id, d.reason.to_string()))
+    };
+
+    // A job imported as applied starts its status history, like one added by hand:
+    let insert = |id: Option<i64>| -> Result<(), DatabaseError> {
+        connection.execute(
+            "INSERT INTO jobs (id, job_title, hourly_rate, applied, link, company,
+                applied_at, created_at, updated_at, user_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6,
+                CASE WHEN CAST(?4 AS INTEGER) = 1 THEN CURRENT_TIMESTAMP END,
+                CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, ?7)",
+            params![
+                id,
+                job.get_title(),
+                job.get_hourly(),
+                job.get_applied(),
+                job.get_optional_link(),
+                job.get_company(),
+                user_id
+            ],
+        )?;
+        let job_id = connection.last_insert_rowid();
+        sync_status_history(connection, job_id, job.get_applied() == "1")
+    };
+
+    match (conflict, policy) {
+        (None, _) => {
+            insert(free_id)?;
+            Ok(Outcome::Inserted)
+        }
+        (Some((id, reason)), ConflictPolicy::Skip) => Ok(Outcome::Skipped(format!(
+            "matches job {} ({}), skipped",
+            id, reason
+        ))),
+        (Some((id, reason)), ConflictPolicy::Update) => {
+            update_job(connection, user_id, id, job)?;
+            Ok(Outcome::Updated(format!(
+                "matches job {} ({}), updated",
+                id, reason
+            )))
+        }
+        (Some((id, reason)), ConflictPolicy::Duplicate) => {
+            insert(free_id)?;
+            Ok(Outcome::Duplicated(format!(
+                "matches job {} ({}), added anyway",
+                id, reason
+            )))
+        }
+    }
+}
 
 /// Reads a csv file.
 ///
-/// Reads the data inside a csv file expecting the following format: (id,job_title,hourly_rate,applied,link).
+/// Reads the data inside a csv file with headers. The columns may be in any order and
+/// are found by the header names in `options.mapping`; extra columns are ignored.
 ///
 /// # Arguments
 /// * `file`: A string slice (`&str`) representing the name of the csv file to read.
-/// * `app`: A mutable reference to the `Applications` object from the application module which has the list of jobs.
+/// * `connection`: A reference to the database connection the jobs are inserted into.
+/// * `user_id`: The user the jobs are imported for.
+/// * `options`: The `ImportOptions` for a dry run or an all-or-nothing import.
 ///
 /// # Returns
-/// Returns the `Result` containing the Ok(()).
-/// Returns an error of type `Box<dyn Error>` on failure when the csv file is unreadable.
+/// Returns the `Result` containing the `ImportReport` for the file.
+/// Returns an error of type `Box<dyn Error>` on failure when the csv file is unreadable
+/// or has no job title column.
 ///
 /// # CSV Format
-/// The csv file must follow this structure:
+/// The default headers follow this structure:
 /// ```csv
 /// id,job_title,hourly_rate,applied,link
 /// 1,Bus Driver,25,0,http://linke1.com
@@ -34,52 +469,111 @@ use std::error::Error;
 pub fn read_csv_file(
     file: &str,                        // The CSV file to add to the database.
     connection: &rusqlite::Connection, // The databse connection.
-) -> Result<(), Box<dyn Error>> {
-    let mut csv_reader = Reader::from_path(file)?; // Get the reader to the file.
-
-    // Prepare the SQL statement for inserting jobs into the database:
-    let mut stmt = connection.prepare(
-        "INSERT INTO jobs (id, job_title, hourly_rate, applied, link)
-        VALUES (?1, ?2, ?3, ?4, ?5)",
-    )?;
-
-    // Loop through each line in the csv file and capture all the data to att to the database.
-    for job in csv_reader.records() {
-        match job {
-            Ok(record) => {
-                // The incremental id of each job:
-                let id: i64 = record
-                    .get(0)
-                    .and_then(|s| s.parse::<i64>().ok())
-                    .unwrap_or(0); // Default to 0 (false) if None or parsing fails.
-
-                // The job title:
-                let job_title = record.get(1).expect("Failed to read title").to_string();
-
-                // Paid hourly rate in dolars:
-                let hourly_rate: f32 = record
-                    .get(2)
-                    .and_then(|s| s.parse::<f32>().ok()) // Parse if Some, return None if parse fails.
-                    .unwrap_or(0.0); // Default to 0.0 if None or parsing fails.
-
-                // 1 or 0 for yes or no:
-                let applied: bool = record
-                    .get(3)
-                    .and_then(|s| s.parse::<i64>().ok()) // Try to parse as i64.
-                    .map(|n| n == 1) // Convert i64 (1 or 0) to bool (true or false).
-                    .unwrap_or(false); // Default to false if parsing fails.
-
-                // The link to the application:
-                let link: String = record.get(4).unwrap_or("No Link").to_string();
-
-                // Insert the job into the database:
-                stmt.execute(params![id, job_title, hourly_rate, applied as i64, link])?;
+    user_id: i64,                      // The user the jobs belong to.
+    options: &ImportOptions,           // Dry run and all-or-nothing options.
+) -> Result<ImportReport, Box<dyn Error>> {
+    let csv_reader = options.reader(std::fs::File::open(file)?); // Get the reader to the file.
+    import_csv(csv_reader, connection, user_id, options)
+}
+
+/// Map the headers and validate every row of a csv file, without touching the database.
+///
+/// # Arguments
+/// * `csv_reader`: The csv reader over the job data (with headers), see `ImportOptions::reader`.
+/// * `mapping`: The header names accepted for each field.
+///
+/// # Returns
+/// * `Ok((Columns, Vec<ParsedRow>))` with the mapped columns and every row in order.
+/// * `Err(Box<dyn Error>)` if the headers can't be read or have no job title column.
+pub fn parse_csv<R: Read>(
+    csv_reader: &mut Reader<R>,
+    mapping: &ColumnMapping,
+) -> Result<(Columns, Vec<ParsedRow>), Box<dyn Error>> {
+    let columns = mapping.resolve(csv_reader.headers()?)?;
+    let rows = csv_reader
+        .records()
+        .map(|record| match record {
+            Ok(record) => ParsedRow {
+                line: record.position().map_or(0, |p| p.line()),
+                job: parse_record(&record, &columns),
+            },
+            Err(e) => ParsedRow {
+                line: e.position().map_or(0, |p| p.line()),
+                job: Err(e.to_string()),
+            },
+        })
+        .collect();
+    Ok((columns, rows))
+}
+
+/// Import the jobs from any csv reader into the database.
+///
+/// The headers are mapped to the job fields first, then every row is
+/// validated and inserted inside one transaction. The transaction
+/// is rolled back for a dry run, or for an atomic import with any row errors.
+///
+/// # Arguments
+/// * `csv_reader`: The csv reader over the job data (with headers), see `ImportOptions::reader`.
+/// * `connection`: A reference to the database connection the jobs are inserted into.
+/// * `user_id`: The user the jobs are imported for.
+/// * `options`: The `ImportOptions` for a dry run or an all-or-nothing import.
+///
+/// # Returns
+/// * `Ok(ImportReport)` describing what was imported.
+/// * `Err(Box<dyn Error>)` if the headers can't be mapped or the transaction could not be finished.
+pub fn import_csv<R: Read>(
+    mut csv_reader: Reader<R>,
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    options: &ImportOptions,
+) -> Result<ImportReport, Box<dyn Error>> {
+    let (columns, rows) = parse_csv(&mut csv_reader, &options.mapping)?;
+    let mut report = ImportReport::default();
+    let transaction = connection.unchecked_transaction()?;
+
+    // Loop through each line in the csv file and add the valid ones to the database.
+    for row in rows {
+        let line = row.line;
+        let result = row.job.and_then(|job| {
+            import_job(&transaction, user_id, &job, options.on_conflict).map_err(|e| e.to_string())
+        });
+
+        match result {
+            Ok(Outcome::Inserted) => report.imported += 1,
+            Ok(Outcome::Updated(message)) => {
+                report.updated += 1;
+                report.conflicts.push(RowError { line, message });
+            }
+            Ok(Outcome::Skipped(message)) => {
+                report.skipped += 1;
+                report.conflicts.push(RowError { line, message });
+            }
+            Ok(Outcome::Duplicated(message)) => {
+                report.imported += 1;
+                report.conflicts.push(RowError { line, message });
+            }
+            Err(message) => {
+                report.skipped += 1;
+                report.errors.push(RowError { line, message });
             }
-            Err(e) => eprintln!("Error reading application.csv file: {}", e),
         }
     }
 
-    Ok(())
+    if options.dry_run || (options.atomic && !report.errors.is_empty()) {
+        transaction.rollback()?;
+        if options.atomic && !options.dry_run {
+            // Nothing was saved, so every row counts as skipped:
+            report.skipped += report.imported + report.updated;
+            report.imported = 0;
+            report.updated = 0;
+        }
+    } else {
+        transaction.commit()?;
+        report.committed = true;
+    }
+    report.columns = Some(columns);
+
+    Ok(report)
 }
 
 /// Testing the read_csv_file method.
@@ -94,27 +588,39 @@ mod tests {
     use std::fs::File;
     use std::io::Write;
 
-    // Testing the read csv file method that extracts jobs inside the csv file.
-    #[test]
-    fn test_read_csv_file() {
-        // Create a new database connection:
+    // The user the test jobs belong to:
+    const USER: i64 = 1;
+
+    // Helper function to create an in-memory database with the jobs table:
+    fn setup_database() -> Connection {
         let connection = Connection::open_in_memory().expect("Failed to create in-memory database");
+        crate::database_methods::create_table(&connection).expect("Failed to create jobs table");
+        connection
+    }
 
-        // Create a new jobs table with the appropriate values:
+    // Helper function to count the jobs in the database:
+    fn count_jobs(connection: &Connection) -> i64 {
         connection
-            .execute(
-                "CREATE TABLE jobs (
-                    id INTEGER PRIMARY KEY,
-                    job_title TEXT NOT NULL,
-                    hourly_rate REAL NOT NULL,
-                    applied INTEGER NOT NULL,
-                    link TEXT NOT NULL
-                )",
-                [],
-            )
-            .expect("Failed to create jobs table");
+            .query_row("SELECT COUNT(*) FROM jobs", [], |row| row.get(0))
+            .expect("Failed to count rows")
+    }
 
-        let csv_filename = "test_application.csv";
+    // Helper function to import csv text instead of a file:
+    fn import_str(data: &str, connection: &Connection, options: &ImportOptions) -> ImportReport {
+        import_csv(options.reader(data.as_bytes()), connection, USER, options)
+            .expect("Import should not fail")
+    }
+
+    // Testing the read csv file method that extracts jobs inside the csv file.
+    #[test]
+    fn test_read_csv_file() {
+        // The real schema, so the import is checked against the tables it writes:
+        let connection = setup_database();
+
+        // A file of its own in the temporary directory, so the tests don't touch the project:
+        let path =
+            std::env::temp_dir().join(format!("test_application_{}.csv", std::process::id()));
+        let csv_filename = path.to_str().expect("The temporary path is not text");
 
         // Create a new csv file:
         let mut file = File::create(csv_filename).expect("Failed to create test CSV file");
@@ -128,19 +634,242 @@ mod tests {
 
         file.flush().unwrap(); // Ensure all lines are written before reading.
 
-        // Call the method we are testing and capture the result of () without error.
-        let result = read_csv_file(csv_filename, &connection);
+        // Call the method we are testing and capture the report without error.
+        let result = read_csv_file(csv_filename, &connection, USER, &ImportOptions::default());
 
-        assert!(result.is_ok(), "read_csv_file() should return Ok(())");
+        assert!(result.is_ok(), "read_csv_file() should return Ok(report)");
+        let report = result.unwrap();
+        assert_eq!(report.imported, 2);
+        assert!(report.committed);
 
         // Count the total rows in the jobs table:
-        let count: i64 = connection
-            .query_row("SELECT COUNT(*) FROM jobs", [], |row| row.get(0))
-            .expect("Failed to count rows");
+        let count = count_jobs(&connection);
 
         assert_eq!(
             count, 2,
             "Database should have 2 job entries after reading the CSV."
         );
+        let funnel = crate::stats::get_funnel(&connection, USER).expect("Failed to get the funnel");
+        assert_eq!(
+            funnel[0].jobs, 1,
+            "The applied job starts its status history."
+        );
+
+        std::fs::remove_file(&path).expect("Failed to remove the test CSV file");
+    }
+
+    // Bad rows are skipped and reported with their line numbers:
+    #[test]
+    fn test_import_reports_bad_rows() {
+        let connection = setup_database();
+        let data = "job_id,job_title,hourly_rate,applied,link\n\
+                    1,Bus Driver,25,0,http://link1.com\n\
+                    abc,Waiter,16,1,http://link2.com\n\
+                    3,,65,1,http://link3.com\n\
+                    4,Engineer,lots,1,http://link4.com\n\
+                    5,Pilot,60,maybe,http://link5.com\n\
+                    6,Chef,20,yes,\n";
+
+        let report = import_str(data, &connection, &ImportOptions::default());
+
+        assert_eq!(report.imported, 2, "Only the valid rows are imported.");
+        assert_eq!(report.skipped, 4);
+        let lines: Vec<u64> = report.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![3, 4, 5, 6]);
+        assert!(report.errors[1].message.contains("title"));
+        assert_eq!(count_jobs(&connection), 2);
+    }
+
+    // A dry run reports what would happen without changing the database:
+    #[test]
+    fn test_import_dry_run() {
+        let connection = setup_database();
+        let data = "job_id,job_title,hourly_rate,applied,link\n\
+                    1,Bus Driver,25,0,http://link1.com\n\
+                    2,Waiter,16,1,http://link2.com\n";
+        let options = ImportOptions {
+            dry_run: true,
+            ..ImportOptions::default()
+        };
+
+        let report = import_str(data, &connection, &options);
+
+        assert_eq!(report.imported, 2);
+        assert!(!report.committed);
+        assert_eq!(count_jobs(&connection), 0, "A dry run changes nothing.");
+    }
+
+    // An atomic import saves nothing if any row fails:
+    #[test]
+    fn test_import_atomic() {
+        let connection = setup_database();
+        let data = "job_id,job_title,hourly_rate,applied,link\n\
+                    1,Bus Driver,25,0,http://link1.com\n\
+                    2,Waiter,-16,1,http://link2.com\n";
+        let options = ImportOptions {
+            atomic: true,
+            ..ImportOptions::default()
+        };
+
+        let report = import_str(data, &connection, &options);
+
+        assert_eq!(report.imported, 0);
+        assert_eq!(report.skipped, 2);
+        assert_eq!(report.errors.len(), 1, "The negative rate is reported.");
+        assert_eq!(report.errors[0].line, 3);
+        assert!(!report.committed);
+        assert_eq!(count_jobs(&connection), 0);
+    }
+
+    // Columns are found by header name, in any order, with extra columns ignored:
+    #[test]
+    fn test_import_maps_columns_by_header() {
+        let connection = setup_database();
+        let data = "Notes,URL,Pay,Position\n\
+                    great team,http://link1.com,25,Bus Driver\n";
+
+        let report = import_str(data, &connection, &ImportOptions::default());
+
+        assert_eq!(report.imported, 1, "{}", report);
+        let (title, rate, link): (String, f32, String) = connection
+            .query_row("SELECT job_title, hourly_rate, link FROM jobs", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .expect("Failed to read job");
+        assert_eq!(title, "Bus Driver");
+        assert_eq!(rate, 25.0);
+        assert_eq!(link, "http://link1.com");
+    }
+
+    // Custom aliases and delimiters can be configured:
+    #[test]
+    fn test_import_custom_mapping_and_delimiter() {
+        let connection = setup_database();
+        let data = "Role;Salary per hour;Sent\n\
+                    Waiter;16;yes\n";
+        let mut options = ImportOptions {
+            delimiter: b';',
+            ..ImportOptions::default()
+        };
+        options.mapping.add_alias(Field::Title, "Role");
+        options.mapping.add_alias(Field::Rate, "salary per hour");
+        options.mapping.add_alias(Field::Applied, "Sent");
+
+        let report = import_str(data, &connection, &options);
+
+        assert_eq!(report.imported, 1, "{}", report);
+        let columns = report.columns.expect("Columns should be reported");
+        assert_eq!(columns.header(Field::Rate), Some("Salary per hour"));
+        assert_eq!(columns.index(Field::Link), None);
+    }
+
+    // A file without a title column is rejected before any row is read:
+    #[test]
+    fn test_import_without_title_column() {
+        let connection = setup_database();
+        let result = import_csv(
+            ImportOptions::default().reader("a,b\n1,2\n".as_bytes()),
+            &connection,
+            USER,
+            &ImportOptions::default(),
+        );
+        assert!(result.is_err(), "A file with no title column is an error.");
+    }
+
+    // Rows matching existing jobs follow the conflict policy:
+    #[test]
+    fn test_import_on_conflict() {
+        let connection = setup_database();
+        let first = "id,title,rate,applied,link,company\n\
+                     1,Barista,16,0,https://example.com/1,Black Rock\n";
+        import_str(first, &connection, &ImportOptions::default());
+
+        // The same id, and the same link with a new id:
+        let again = "id,title,rate,applied,link,company\n\
+                     1,Barista,18,1,https://example.com/1,Black Rock\n\
+                     2,Coffee Barista,17,0,http://www.example.com/1/,\n";
+
+        let report = import_str(again, &connection, &ImportOptions::default());
+        assert_eq!((report.imported, report.skipped), (0, 2), "{}", report);
+        assert!(report.errors.is_empty(), "Conflicts are not errors.");
+        assert_eq!(report.conflicts.len(), 2);
+
+        let update = ImportOptions {
+            on_conflict: ConflictPolicy::Update,
+            ..ImportOptions::default()
+        };
+        let report = import_str(again, &connection, &update);
+        assert_eq!(report.updated, 2, "{}", report);
+        let rate: f32 = connection
+            .query_row("SELECT hourly_rate FROM jobs WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .expect("Failed to read rate");
+        assert_eq!(rate, 17.0, "The last matching row wins.");
+
+        let duplicate = ImportOptions {
+            on_conflict: ConflictPolicy::Duplicate,
+            ..ImportOptions::default()
+        };
+        let report = import_str(again, &connection, &duplicate);
+        assert_eq!(report.imported, 2, "{}", report);
+        assert_eq!(count_jobs(&connection), 3, "The id 1 row gets a new id.");
+    }
+
+    // Jobs imported as applied are in the funnel, and an update keeps it in step:
+    #[test]
+    fn test_import_status_history() {
+        let connection = setup_database();
+        let applied = |connection: &Connection| {
+            crate::stats::get_funnel(connection, USER).expect("Failed to get the funnel")[0].jobs
+        };
+        let data = "title,rate,applied,link\n\
+                    Barista,16,1,https://example.com/1\n\
+                    Welder,28,yes,https://example.com/2\n\
+                    Cook,18,0,https://example.com/3\n";
+        import_str(data, &connection, &ImportOptions::default());
+        assert_eq!(applied(&connection), 2);
+
+        // Updated as not applied, a job leaves the funnel; updated as applied, it joins it:
+        let update = ImportOptions {
+            on_conflict: ConflictPolicy::Update,
+            ..ImportOptions::default()
+        };
+        let data = "title,rate,applied,link\n\
+                    Barista,16,0,https://example.com/1\n\
+                    Cook,18,1,https://example.com/3\n";
+        let report = import_str(data, &connection, &update);
+        assert_eq!(report.updated, 2, "{}", report);
+        assert_eq!(applied(&connection), 2);
+        let barista: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM status_history WHERE job_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .expect("Failed to read the history");
+        assert_eq!(
+            barista, 1,
+            "The history is kept, only left out of the funnel."
+        );
+    }
+
+    // Parsing keeps every row in order without changing the database:
+    #[test]
+    fn test_parse_csv() {
+        let data = "title,rate\nBarista,16\n,20\n";
+        let mut reader = ImportOptions::default().reader(data.as_bytes());
+
+        let (columns, rows) =
+            parse_csv(&mut reader, &ColumnMapping::default()).expect("Failed to parse");
+
+        assert_eq!(columns.header(Field::Title), Some("title"));
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0].job.as_ref().map(|j| j.get_title()),
+            Ok("Barista".to_string())
+        );
+        assert_eq!(rows[1].line, 3);
+        assert!(rows[1].job.is_err());
     }
 }
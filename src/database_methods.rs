@@ -3,33 +3,431 @@
 // Professor: Bart Massey
 
 //! This module containes the methods used to create, access, add to,
-//! remove, check if empty, and count for the database. Each method is
+//! remove, check if empty, and count for the database, to store the
+//! dates (events), status history (stages), tags and archived posting page
+//! of each job, the weekly application goals, the users with their login
+//! sessions and API tokens, the full-text search index, and the aggregate
+//! queries used for the statistics page. Each method is
 //! public and requires a connection to an active rusqlite::Connection
 //! object in order to modify the database.
+//!
+//! Every job and goal belongs to a user. The methods that read or change
+//! them take the id of the logged-in user and only ever see that user's
+//! rows, so a job id of another user acts as if there was no such job.
 
 use crate::job;
-use crate::job::Job;
+use crate::job::{
+    ApiToken, Archive, BulkAction, EventKind, Job, JobEvent, Stage, TokenScope, User,
+};
+use chrono::NaiveDateTime;
 use log::info;
+use rusqlite::OptionalExtension;
+use std::fmt;
+
+/// The errors of the database methods.
+#[derive(Debug)]
+pub enum DatabaseError {
+    /// SQLite failed: the database couldn't be opened, or a query failed.
+    Sqlite(rusqlite::Error),
+    /// A row holds a value that can't be read, like text where a number belongs.
+    BadValue(String),
+    /// The user has no job with this id.
+    JobNotFound(i64),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatabaseError::Sqlite(e) => write!(f, "Database error: {}", e),
+            DatabaseError::BadValue(e) => write!(f, "Unreadable value in the database: {}", e),
+            DatabaseError::JobNotFound(id) => write!(f, "There is no job with id {}", id),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
 
-/// Creates a `jobs` table in the SQLite database if it does not already exist.
+// Values of the wrong type in a row are told apart from the other SQLite errors:
+impl From<rusqlite::Error> for DatabaseError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::InvalidColumnType(..)
+            | rusqlite::Error::FromSqlConversionFailure(..)
+            | rusqlite::Error::IntegralValueOutOfRange(..) => {
+                DatabaseError::BadValue(e.to_string())
+            }
+            e => DatabaseError::Sqlite(e),
+        }
+    }
+}
+
+/// Where the database is. The server shares it with its handlers as app data,
+/// so the tests can give them a database of their own.
+#[derive(Clone, Debug)]
+pub struct Database {
+    path: String,
+}
+
+impl Database {
+    /// A database at a file path (or an SQLite `file:` URI).
+    pub fn new(path: &str) -> Self {
+        Database {
+            path: path.to_string(),
+        }
+    }
+
+    /// Open a connection to the database.
+    pub fn open(&self) -> Result<rusqlite::Connection, DatabaseError> {
+        Ok(rusqlite::Connection::open(&self.path)?)
+    }
+}
+
+/// Creates the `jobs`, `events`, `status_history`, `tags`, `goals`, `archives`, `users` and `sessions` tables in the SQLite database if they do not already exist.
+///
+/// Columns added after the first version of the table are added to an
+/// existing table with `add_column_if_missing`, and the data of an older
+/// database is changed once by `migrate`.
 ///
 /// # Arguments
 /// * `connection` - A reference to an active `rusqlite::Connection`.
 ///
 /// # Returns
 /// * `Ok(())` if the table is successfully created.
-/// * `Err(rusqlite::Error)` if an SQL execution error occurs.
-pub fn create_table(connection: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+/// * `Err(DatabaseError)` if an SQL execution error occurs.
+pub fn create_table(connection: &rusqlite::Connection) -> Result<(), DatabaseError> {
     connection.execute(
         "CREATE TABLE IF NOT EXISTS jobs (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
             job_title TEXT NOT NULL,
             hourly_rate REAL,
             applied INTEGER NOT NULL CHECK (applied IN (0, 1)),
-            link TEXT
+            link TEXT,
+            company TEXT,
+            applied_at TEXT,
+            created_at TEXT,
+            updated_at TEXT,
+            description TEXT,
+            link_status INTEGER,
+            link_checked_at TEXT,
+            user_id INTEGER REFERENCES users(id),
+            archived_at TEXT
         )",
         (), // Empty parameters
     )?;
+    // Older tables get the new columns. Their jobs keep no created or updated time
+    // (it's unknown) until they are changed:
+    add_column_if_missing(connection, "jobs", "company", "TEXT")?;
+    add_column_if_missing(connection, "jobs", "applied_at", "TEXT")?;
+    add_column_if_missing(connection, "jobs", "created_at", "TEXT")?;
+    add_column_if_missing(connection, "jobs", "updated_at", "TEXT")?;
+    add_column_if_missing(connection, "jobs", "description", "TEXT")?;
+    add_column_if_missing(connection, "jobs", "link_status", "INTEGER")?;
+    add_column_if_missing(connection, "jobs", "link_checked_at", "TEXT")?;
+    add_column_if_missing(connection, "jobs", "archived_at", "TEXT")?;
+    // Jobs from before there were users have no owner, the first user added gets them:
+    add_column_if_missing(
+        connection,
+        "jobs",
+        "user_id",
+        "INTEGER REFERENCES users(id)",
+    )?;
+
+    // The deadlines, follow-up reminders and interviews of each job:
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            job_id INTEGER NOT NULL REFERENCES jobs(id),
+            kind TEXT NOT NULL CHECK (kind IN ('deadline', 'follow_up', 'interview')),
+            date TEXT NOT NULL,
+            time TEXT,
+            note TEXT
+        )",
+        (),
+    )?;
+
+    // Every change of stage of each job, used for the funnel report:
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS status_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            job_id INTEGER NOT NULL REFERENCES jobs(id),
+            stage TEXT NOT NULL
+                CHECK (stage IN ('applied', 'screen', 'interview', 'offer', 'rejected')),
+            changed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        (),
+    )?;
+
+    // The user's labels of each job, each once per job whatever its case:
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS tags (
+            job_id INTEGER NOT NULL REFERENCES jobs(id),
+            tag TEXT NOT NULL COLLATE NOCASE,
+            PRIMARY KEY (job_id, tag)
+        )",
+        (),
+    )?;
+
+    // Every weekly application goal that was set, the latest is the current one:
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS goals (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            target INTEGER NOT NULL CHECK (target > 0),
+            set_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            user_id INTEGER REFERENCES users(id)
+        )",
+        (),
+    )?;
+    add_column_if_missing(
+        connection,
+        "goals",
+        "user_id",
+        "INTEGER REFERENCES users(id)",
+    )?;
+
+    // The saved (sanitized) copy of each job's posting page, one per job:
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS archives (
+            job_id INTEGER PRIMARY KEY REFERENCES jobs(id),
+            html TEXT NOT NULL,
+            source TEXT NOT NULL,
+            saved_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        (),
+    )?;
+
+    // The people who can log in, their login sessions and API tokens (see the auth module):
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            password_hash TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+        (),
+    )?;
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS sessions (
+            token_hash TEXT PRIMARY KEY,
+            user_id INTEGER NOT NULL REFERENCES users(id),
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            expires_at TEXT NOT NULL
+        )",
+        (),
+    )?;
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS api_tokens (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL REFERENCES users(id),
+            name TEXT NOT NULL,
+            token_hash TEXT NOT NULL UNIQUE,
+            scope TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            last_used_at TEXT
+        )",
+        (),
+    )?;
+
+    migrate(connection)?;
+    create_search_index(connection)?;
+    Ok(())
+}
+
+/// The version of the data in the database, kept in SQLite's `user_version`.
+const DATA_VERSION: i64 = 2;
+
+// Change the data of a database made by an older version, once: the version
+// reached is kept in the database, so the changes aren't run on every start.
+fn migrate(connection: &rusqlite::Connection) -> Result<(), DatabaseError> {
+    let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version >= DATA_VERSION {
+        return Ok(());
+    }
+
+    let transaction = connection.unchecked_transaction()?;
+    if version < 1 {
+        // Jobs without a link used to keep an empty or "No Link" placeholder, it is NULL now:
+        transaction.execute(
+            "UPDATE jobs SET link = NULL WHERE link = '' OR link = 'No Link'",
+            (),
+        )?;
+    }
+    if version < 2 {
+        // Jobs imported as applied used to get no status history, so they were
+        // missing from the funnel. They start it from when they were applied:
+        transaction.execute(
+            "INSERT INTO status_history (job_id, stage, changed_at)
+            SELECT id, 'applied', COALESCE(applied_at, created_at, CURRENT_TIMESTAMP) FROM jobs
+            WHERE applied = 1
+                AND NOT EXISTS (SELECT 1 FROM status_history h WHERE h.job_id = jobs.id)",
+            (),
+        )?;
+    }
+    // A pragma can't take a parameter, the version is a number:
+    transaction.execute_batch(&format!("PRAGMA user_version = {}", DATA_VERSION))?;
+    transaction.commit()?;
+    info!(
+        "Migrated the database from version {} to {}",
+        version, DATA_VERSION
+    );
+    Ok(())
+}
+
+/// The text that separates the notes of a job's events in the search index.
+const NOTE_SEPARATOR: &str = " / ";
+
+/// Create the full-text search index (an FTS5 table) of the jobs, and the
+/// triggers that keep it in sync with the `jobs` and `events` tables.
+///
+/// Each row of `jobs_fts` has the id of its job as its rowid, and holds the
+/// title, the company, the notes of every event and the description of the
+/// job. The index is filled from the existing jobs when it is first created,
+/// and created again when it is missing a column (from an older version).
+///
+/// # Arguments
+/// * `connection` - A reference to an active `rusqlite::Connection`.
+///
+/// # Returns
+/// * `Ok(())` if the index exists or was created.
+/// * `Err(DatabaseError)` if an SQL execution error occurs (also if SQLite has no FTS5).
+pub fn create_search_index(connection: &rusqlite::Connection) -> Result<(), DatabaseError> {
+    let columns: Vec<String> = connection
+        .prepare("PRAGMA table_info(jobs_fts)")?
+        .query_map([], |row| row.get(1))?
+        .collect::<Result<_, _>>()?;
+    let exists = !columns.is_empty();
+    let up_to_date = columns.iter().any(|column| column == "description");
+
+    // An index from an older version is dropped (with its triggers) and filled again:
+    if exists && !up_to_date {
+        connection.execute_batch(
+            "DROP TABLE jobs_fts;
+            DROP TRIGGER IF EXISTS jobs_fts_insert;
+            DROP TRIGGER IF EXISTS jobs_fts_update;
+            DROP TRIGGER IF EXISTS jobs_fts_delete;
+            DROP TRIGGER IF EXISTS events_fts_insert;
+            DROP TRIGGER IF EXISTS events_fts_update;
+            DROP TRIGGER IF EXISTS events_fts_delete;",
+        )?;
+    }
+
+    // The notes of a job, all in one text (used by the triggers below):
+    let notes = |job_id: &str| {
+        format!(
+            "(SELECT COALESCE(group_concat(note, '{}'), '') FROM events WHERE job_id = {})",
+            NOTE_SEPARATOR, job_id
+        )
+    };
+
+    connection.execute_batch(&format!(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS jobs_fts USING fts5(title, company, notes, description);
+
+        CREATE TRIGGER IF NOT EXISTS jobs_fts_insert AFTER INSERT ON jobs BEGIN
+            INSERT INTO jobs_fts (rowid, title, company, notes, description)
+            VALUES (new.id, new.job_title, COALESCE(new.company, ''), {job_notes},
+                COALESCE(new.description, ''));
+        END;
+        CREATE TRIGGER IF NOT EXISTS jobs_fts_update
+        AFTER UPDATE OF job_title, company, description ON jobs BEGIN
+            UPDATE jobs_fts SET title = new.job_title, company = COALESCE(new.company, ''),
+                description = COALESCE(new.description, '')
+            WHERE rowid = new.id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS jobs_fts_delete AFTER DELETE ON jobs BEGIN
+            DELETE FROM jobs_fts WHERE rowid = old.id;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS events_fts_insert AFTER INSERT ON events BEGIN
+            UPDATE jobs_fts SET notes = {new_notes} WHERE rowid = new.job_id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS events_fts_update AFTER UPDATE ON events BEGIN
+            UPDATE jobs_fts SET notes = {old_notes} WHERE rowid = old.job_id;
+            UPDATE jobs_fts SET notes = {new_notes} WHERE rowid = new.job_id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS events_fts_delete AFTER DELETE ON events BEGIN
+            UPDATE jobs_fts SET notes = {old_notes} WHERE rowid = old.job_id;
+        END;",
+        job_notes = notes("new.id"),
+        new_notes = notes("new.job_id"),
+        old_notes = notes("old.job_id"),
+    ))?;
+
+    if !up_to_date {
+        connection.execute(
+            &format!(
+                "INSERT INTO jobs_fts (rowid, title, company, notes, description)
+                SELECT id, job_title, COALESCE(company, ''), {}, COALESCE(description, '')
+                FROM jobs",
+                notes("jobs.id")
+            ),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+/// Search the jobs with the full-text search index, best matches first.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user whose jobs are searched.
+/// * `query` - An FTS5 query (see the search module to build one from what a user typed).
+/// * `open` / `close` - The text put around each matching word in the snippets.
+/// * `limit` - The most results to return.
+///
+/// # Returns
+/// * `Ok(Vec<(i64, String)>)` The id of each matching job and a snippet of its best matching column.
+/// * `Err(DatabaseError)` if an error occurs (also for a query with bad FTS5 syntax).
+pub fn search_jobs(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    query: &str,
+    open: &str,
+    close: &str,
+    limit: i64,
+) -> Result<Vec<(i64, String)>, DatabaseError> {
+    let mut statement = connection.prepare(
+        "SELECT jobs_fts.rowid, snippet(jobs_fts, -1, ?2, ?3, '...', 12) FROM jobs_fts
+        JOIN jobs ON jobs.id = jobs_fts.rowid
+        WHERE jobs_fts MATCH ?1 AND jobs.user_id = ?5
+        ORDER BY jobs_fts.rank
+        LIMIT ?4",
+    )?;
+    let rows = statement.query_map(
+        rusqlite::params![query, open, close, limit, user_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    rows.collect::<Result<_, _>>().map_err(DatabaseError::from)
+}
+
+/// Add a column to a table in a database created by an older version.
+///
+/// # Arguments
+/// * `connection` - A reference to an active `rusqlite::Connection`.
+/// * `table` - The table to add the column to.
+/// * `column` - The name of the new column.
+/// * `definition` - The type and constraints of the new column.
+///
+/// # Returns
+/// * `Ok(())` if the column exists or was added.
+/// * `Err(DatabaseError)` if an SQL execution error occurs.
+pub fn add_column_if_missing(
+    connection: &rusqlite::Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), DatabaseError> {
+    let mut statement = connection.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = statement
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(Result::ok)
+        .any(|name| name == column);
+
+    if !exists {
+        connection.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
     Ok(())
 }
 
@@ -37,33 +435,69 @@ pub fn create_table(connection: &rusqlite::Connection) -> Result<(), rusqlite::E
 ///
 /// # Arguments
 /// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user the job belongs to.
 /// * `a_job` - A reference to a `Job` struct containing job details.
 ///
 /// # Returns
-/// * `Ok(())` if the job was successfully inserted.
-/// * `Err(rusqlite::Error)` if an error occurs.
+/// * `Ok(i64)` with the id of the new job if it was successfully inserted.
+/// * `Err(DatabaseError)` if an error occurs.
 pub fn enter_data(
     connection: &rusqlite::Connection,
+    user_id: i64,
     a_job: &job::Job,
-) -> Result<(), rusqlite::Error> {
+) -> Result<i64, DatabaseError> {
     connection.execute(
-        "INSERT INTO jobs (job_title, hourly_rate, applied, link) VALUES (?1, ?2, ?3, ?4)",
+        "INSERT INTO jobs (job_title, hourly_rate, applied, link, company, applied_at,
+            created_at, updated_at, description, user_id)
+        VALUES (?1, ?2, ?3, ?4, ?5,
+            CASE WHEN CAST(?3 AS INTEGER) = 1 THEN CURRENT_TIMESTAMP END,
+            CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, ?6, ?7)",
         rusqlite::params![
             a_job.get_title(),
             a_job.get_hourly(),
             a_job.get_applied(),
-            a_job.get_link()
+            a_job.get_optional_link(),
+            a_job.get_company(),
+            a_job.get_description(),
+            user_id
         ], // Proper parameter format
     )?;
-    Ok(())
+
+    // A job added as applied starts its status history:
+    let job_id = connection.last_insert_rowid();
+    if a_job.get_applied() == "1" {
+        add_stage(connection, job_id, Stage::Applied)?;
+    }
+    Ok(job_id)
 }
 
-/// Remove a job from the `jobs` table.
+/// Remove a job from the `jobs` table, with its dates, status history, tags and archived page.
+///
+/// The deletes are not a transaction of their own, so that they can be part of the
+/// caller's: call it in a transaction (or use `remove_jobs`, which opens one).
 ///
 /// # Arguments
 /// * `connection` - Reference to the databse.
+/// * `user_id` - The user the job belongs to.
 /// * `id` - The jobs id to be removed.
-pub fn remove_data(connection: &rusqlite::Connection, id: i64) -> Result<bool, rusqlite::Error> {
+pub fn remove_data(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    id: i64,
+) -> Result<bool, DatabaseError> {
+    if !owns_job(connection, user_id, id)? {
+        return Ok(false); // No job of the user with that ID.
+    }
+    connection.execute("DELETE FROM events WHERE job_id = ?", rusqlite::params![id])?;
+    connection.execute(
+        "DELETE FROM status_history WHERE job_id = ?",
+        rusqlite::params![id],
+    )?;
+    connection.execute(
+        "DELETE FROM archives WHERE job_id = ?",
+        rusqlite::params![id],
+    )?;
+    connection.execute("DELETE FROM tags WHERE job_id = ?", rusqlite::params![id])?;
     let result = connection.execute("DELETE FROM jobs WHERE id = ?", rusqlite::params![id])?;
 
     if result > 0 {
@@ -73,58 +507,225 @@ pub fn remove_data(connection: &rusqlite::Connection, id: i64) -> Result<bool, r
     }
 }
 
-/// Retrieves all job records from the `jobs` table and prints them.
+/// Remove many jobs of a user at once, with their dates, status history and archived pages.
+///
+/// Everything happens in one transaction: if one of the jobs can't be removed, none are.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the jobs belong to, the ids of other users' jobs are skipped.
+/// * `ids` - The ids of the jobs to remove.
+///
+/// # Returns
+/// * `Ok(usize)` The number of jobs removed.
+/// * `Err(DatabaseError)` if an error occurs, and then no job is removed.
+pub fn remove_jobs(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    ids: &[i64],
+) -> Result<usize, DatabaseError> {
+    let transaction = connection.unchecked_transaction()?;
+    let mut removed = 0;
+    for &id in ids {
+        if remove_data(&transaction, user_id, id)? {
+            removed += 1;
+        }
+    }
+    transaction.commit()?;
+    info!("Removed {} of {} jobs", removed, ids.len());
+    Ok(removed)
+}
+
+/// Make the same change to many jobs of a user at once (see `BulkAction`).
+///
+/// Everything happens in one transaction: if one of the jobs can't be changed, none are.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the jobs belong to, the ids of other users' jobs are skipped.
+/// * `ids` - The ids of the jobs to change.
+/// * `action` - The change made to each of them.
+///
+/// # Returns
+/// * `Ok(usize)` The number of jobs changed.
+/// * `Err(DatabaseError)` if an error occurs, and then no job is changed.
+pub fn bulk_update(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    ids: &[i64],
+    action: &BulkAction,
+) -> Result<usize, DatabaseError> {
+    let transaction = connection.unchecked_transaction()?;
+    let mut changed = 0;
+    for &id in ids {
+        let done = match action {
+            BulkAction::NotApplied => update_applied(&transaction, user_id, false, id)?,
+            BulkAction::SetStage(stage) => move_to_stage(&transaction, user_id, id, *stage)?,
+            BulkAction::AddTag(tag) => {
+                let owned = owns_job(&transaction, user_id, id)?;
+                if owned {
+                    // A tag the job already has is kept once:
+                    transaction.execute(
+                        "INSERT OR IGNORE INTO tags (job_id, tag) VALUES (?1, ?2)",
+                        rusqlite::params![id, tag],
+                    )?;
+                }
+                owned
+            }
+            // Archiving a job again keeps the time it was first archived:
+            BulkAction::Archive | BulkAction::Unarchive => {
+                transaction.execute(
+                    "UPDATE jobs SET archived_at = CASE WHEN ?1
+                        THEN COALESCE(archived_at, CURRENT_TIMESTAMP) END
+                    WHERE id = ?2 AND user_id = ?3",
+                    rusqlite::params![*action == BulkAction::Archive, id, user_id],
+                )? > 0
+            }
+            BulkAction::Delete => remove_data(&transaction, user_id, id)?,
+        };
+        if done {
+            changed += 1;
+        }
+    }
+    transaction.commit()?;
+    info!(
+        "Bulk change of {} of {} jobs: {}",
+        changed,
+        ids.len(),
+        action.done()
+    );
+    Ok(changed)
+}
+
+/// Check that a job belongs to a user.
+///
+/// # Returns
+/// * `Ok(true)` if the job is the user's, `Ok(false)` if there is no such job or it is another user's.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn owns_job(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: i64,
+) -> Result<bool, DatabaseError> {
+    connection
+        .query_row(
+            "SELECT EXISTS (SELECT 1 FROM jobs WHERE id = ?1 AND user_id = ?2)",
+            [job_id, user_id],
+            |row| row.get(0),
+        )
+        .map_err(DatabaseError::from)
+}
+
+/// Retrieves all job records of a user from the `jobs` table and prints them.
 ///
 /// # Arguments
 /// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user whose jobs are returned.
 ///
 /// # Returns
 /// * `Ok(())` if the query executes successfully.
-/// * `Err(rusqlite::Error)` if an error occurs.
-pub fn get_jobs(connection: &rusqlite::Connection) -> Result<Vec<Job>, rusqlite::Error> {
-    let mut statement =
-        connection.prepare("SELECT id, job_title, hourly_rate, applied, link FROM jobs")?;
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn get_jobs(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+) -> Result<Vec<Job>, DatabaseError> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT {} FROM jobs WHERE user_id = ? ORDER BY id",
+        JOB_COLUMNS
+    ))?;
 
     // Iterate through the database and gather all the lines of data, creating the Job:
-    let job_iterator = statement.query_map([], |row| {
-        let id: i64 = row.get::<_, i64>(0)?; // id
-        let title: String = row.get::<_, String>(1)?; // title
-        let hourly: f32 = row.get::<_, f32>(2)?; // hourly
-
-        // Properly handle the Result and convert applied value to "Yes" or "No"
-        let applied: i64 = row.get::<_, i64>(3)?; // applied
-        let applied_status = if applied == 1 {
-            "Yes".to_string()
-        } else {
-            "No".to_string()
-        };
+    let job_iterator = statement.query_map([user_id], job_from_row)?;
 
-        // link
-        //let link: String = row.get::<_, String>(4).ok().unwrap_or("No Link".to_string());
-        let link = row.get::<_, String>(4).map(|s| s.to_string());
-
-        // Return a new Job instance with applied as "Yes"/"No" instead of "1/0":
-        Ok(Job::new(
-            Some(id),
-            title,
-            hourly,
-            applied_status,
-            Some(link.expect("No Link")),
-        ))
-    })?;
-
-    let mut jobs = Vec::new();
-    for job in job_iterator {
-        match job {
-            Ok(j) => jobs.push(j),
-            Err(e) => eprintln!("Error parsing job: {}", e),
-        }
-    }
+    let jobs = job_iterator.collect::<Result<Vec<Job>, _>>()?;
 
     info!("Fetched {} jobs", jobs.len());
     Ok(jobs)
 }
 
+/// Retrieves one job from the `jobs` table by its id.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the job belongs to.
+/// * `job_id` - The id of the job.
+///
+/// # Returns
+/// * `Ok(Some(Job))` with the job, or `Ok(None)` if the user has no job with that id.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn get_job(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: i64,
+) -> Result<Option<Job>, DatabaseError> {
+    connection
+        .query_row(
+            &format!(
+                "SELECT {} FROM jobs WHERE id = ?1 AND user_id = ?2",
+                JOB_COLUMNS
+            ),
+            [job_id, user_id],
+            job_from_row,
+        )
+        .optional()
+        .map_err(DatabaseError::from)
+}
+
+/// The columns read by `job_from_row`, in order. A job not applied to has no stage.
+const JOB_COLUMNS: &str = "id, job_title, hourly_rate, applied, link, company,
+    CASE WHEN applied = 1 THEN (SELECT stage FROM status_history h WHERE h.job_id = jobs.id
+        ORDER BY changed_at DESC, h.id DESC LIMIT 1) END,
+    created_at, updated_at, applied_at, description, link_status, link_checked_at,
+    (SELECT group_concat(tag, ',') FROM tags WHERE tags.job_id = jobs.id), archived_at";
+
+// Create a Job from a row of `JOB_COLUMNS`.
+fn job_from_row(row: &rusqlite::Row) -> Result<Job, rusqlite::Error> {
+    let id: i64 = row.get::<_, i64>(0)?; // id
+    let title: String = row.get::<_, String>(1)?; // title
+    let hourly: Option<f32> = row.get(2)?; // hourly, NULL (a NaN rate) is read as 0
+
+    // Properly handle the Result and convert applied value to "Yes" or "No"
+    let applied: i64 = row.get::<_, i64>(3)?; // applied
+    let applied_status = if applied == 1 {
+        "Yes".to_string()
+    } else {
+        "No".to_string()
+    };
+
+    let link: Option<String> = row.get(4)?; // link, empty links are NULL
+    let company: Option<String> = row.get(5)?; // company
+    let stage: Option<String> = row.get(6)?; // latest stage
+    let created_at: Option<NaiveDateTime> = row.get(7)?; // timestamps
+    let updated_at: Option<NaiveDateTime> = row.get(8)?;
+    let applied_at: Option<NaiveDateTime> = row.get(9)?;
+    let description: Option<String> = row.get(10)?; // description
+    let link_status: Option<u16> = row.get(11)?; // last link check
+    let link_checked_at: Option<NaiveDateTime> = row.get(12)?;
+    let tags: Option<String> = row.get(13)?; // tags, which can't have commas
+    let archived_at: Option<NaiveDateTime> = row.get(14)?;
+
+    // Return a new Job instance with applied as "Yes"/"No" instead of "1/0":
+    Ok(Job::new(
+        Some(id),
+        title,
+        hourly.unwrap_or_default(),
+        applied_status,
+        link,
+    )
+    .with_company(company)
+    .with_stage(stage.as_deref().and_then(Stage::from_name))
+    .with_timestamps(created_at, updated_at, applied_at)
+    .with_description(description)
+    .with_link_check(link_status, link_checked_at)
+    .with_tags(
+        tags.iter()
+            .flat_map(|tags| tags.split(','))
+            .map(str::to_string)
+            .collect(),
+    )
+    .with_archived_at(archived_at))
+}
+
 /// Drop the table_name from the database.
 ///
 /// # Arguments
@@ -133,48 +734,985 @@ pub fn get_jobs(connection: &rusqlite::Connection) -> Result<Vec<Job>, rusqlite:
 ///
 /// # Returns
 /// * `Ok(())` if query exists on success.
-/// * `Err(rusqlite::Error)` if an error occurs.
+/// * `Err(DatabaseError)` if an error occurs.
 pub fn _drop_table(
     connection: &rusqlite::Connection,
     table_name: &str,
-) -> Result<(), rusqlite::Error> {
+) -> Result<(), DatabaseError> {
     let query = format!("DROP TABLE IF EXISTS {}", table_name);
     connection.execute(&query, [])?;
-    println!("Table '{}' has been dropped.", table_name);
+    info!("Table '{}' has been dropped.", table_name);
+    Ok(())
+}
+
+/// Update the applied status for a job in the database.
+///
+/// The time the job was first marked applied is kept in `applied_at`,
+/// and cleared when the job is marked not applied. Marking a job applied
+/// starts its status history. Marking it not applied keeps the history,
+/// which the stages and the funnel skip until it is applied again.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the job belongs to.
+/// * `new_status` - A bool to signify yes or no that the application status is changed.
+/// * `job_id` - The id of the job being updated.
+///
+/// # Returns
+/// * `Ok(true)` if the job was updated, `Ok(false)` if the user has no job with that id.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn update_applied(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    new_status: bool,
+    job_id: i64,
+) -> Result<bool, DatabaseError> {
+    let updated = connection.execute(
+        "UPDATE jobs SET applied = ?1,
+            applied_at = CASE WHEN ?1 = 1 THEN COALESCE(applied_at, CURRENT_TIMESTAMP) END,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE id = ?2 AND user_id = ?3",
+        (new_status as i32, job_id, user_id),
+    )?;
+    if updated == 0 {
+        return Ok(false);
+    }
+    sync_status_history(connection, job_id, new_status)?;
+    Ok(true)
+}
+
+/// Keep the status history of a job in step with whether it was applied to: a job
+/// marked applied starts its history if it has none. A job marked not applied keeps
+/// its history, so a wrong click doesn't lose its dates: the stages and the funnel
+/// only read the history of the jobs marked applied.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `job_id` - The id of the job, already checked to be the user's.
+/// * `applied` - Whether the job is marked applied.
+///
+/// # Returns
+/// * `Ok(())` if the history is in step.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn sync_status_history(
+    connection: &rusqlite::Connection,
+    job_id: i64,
+    applied: bool,
+) -> Result<(), DatabaseError> {
+    if !applied {
+        return Ok(());
+    }
+    let started: bool = connection.query_row(
+        "SELECT EXISTS (SELECT 1 FROM status_history WHERE job_id = ?)",
+        [job_id],
+        |row| row.get(0),
+    )?;
+    if !started {
+        add_stage(connection, job_id, Stage::Applied)?;
+    }
+    Ok(())
+}
+
+// Add a stage to the status history of a job, at the current time.
+fn add_stage(
+    connection: &rusqlite::Connection,
+    job_id: i64,
+    stage: Stage,
+) -> Result<(), DatabaseError> {
+    connection.execute(
+        "INSERT INTO status_history (job_id, stage) VALUES (?1, ?2)",
+        rusqlite::params![job_id, stage.name()],
+    )?;
+    Ok(())
+}
+
+/// Move a job to a new stage, keeping the change in its status history.
+///
+/// Every stage after applying means the job was applied to, so the job
+/// is also marked applied.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the job belongs to.
+/// * `job_id` - The id of the job.
+/// * `stage` - The new stage.
+///
+/// # Returns
+/// * `Ok(true)` if the stage was changed, `Ok(false)` if the user has no job with that id.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn set_stage(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: i64,
+    stage: Stage,
+) -> Result<bool, DatabaseError> {
+    let transaction = connection.unchecked_transaction()?;
+    if !move_to_stage(&transaction, user_id, job_id, stage)? {
+        return Ok(false);
+    }
+    transaction.commit()?;
+    Ok(true)
+}
+
+// Move a job to a stage, within the transaction of the caller.
+fn move_to_stage(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: i64,
+    stage: Stage,
+) -> Result<bool, DatabaseError> {
+    if !update_applied(connection, user_id, true, job_id)? {
+        return Ok(false);
+    }
+    if stage != Stage::Applied {
+        add_stage(connection, job_id, stage)?;
+    }
+    Ok(true)
+}
+
+/// The first time each of a user's jobs marked applied reached each stage.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user whose jobs are used.
+///
+/// # Returns
+/// * `Ok(Vec<(i64, Stage, f64)>)` The job id, the stage and the time as a Julian day number.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn stage_history(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+) -> Result<Vec<(i64, Stage, f64)>, DatabaseError> {
+    let mut statement = connection.prepare(
+        "SELECT job_id, stage, MIN(julianday(changed_at)) FROM status_history
+        WHERE job_id IN (SELECT id FROM jobs WHERE user_id = ? AND applied = 1)
+        GROUP BY job_id, stage
+        ORDER BY job_id",
+    )?;
+    let rows = statement.query_map([user_id], |row| {
+        let stage: String = row.get(1)?;
+        let stage = Stage::from_name(&stage).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                1,
+                rusqlite::types::Type::Text,
+                format!("unknown stage \"{}\"", stage).into(),
+            )
+        })?;
+        Ok((row.get(0)?, stage, row.get(2)?))
+    })?;
+    rows.collect::<Result<_, _>>().map_err(DatabaseError::from)
+}
+
+/// Replace the details of an existing job with the details of `a_job`.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the job belongs to.
+/// * `job_id` - The id of the job being updated.
+/// * `a_job` - The new details for the job (its own id is ignored). Without a
+///   company or description, the job keeps its old one. A new link forgets the
+///   last check of the old one. The status history follows the applied status
+///   (see `sync_status_history`).
+///
+/// # Returns
+/// * `Ok(true)` if the job was updated, `Ok(false)` if the user has no job with that id.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn update_job(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: i64,
+    a_job: &Job,
+) -> Result<bool, DatabaseError> {
+    let result = connection.execute(
+        "UPDATE jobs SET job_title = ?1, hourly_rate = ?2, applied = ?3, link = ?4,
+            company = COALESCE(?5, company),
+            description = COALESCE(?7, description),
+            link_status = CASE WHEN link IS ?4 THEN link_status END,
+            link_checked_at = CASE WHEN link IS ?4 THEN link_checked_at END,
+            applied_at = CASE WHEN CAST(?3 AS INTEGER) = 1
+                THEN COALESCE(applied_at, CURRENT_TIMESTAMP) END,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE id = ?6 AND user_id = ?8",
+        rusqlite::params![
+            a_job.get_title(),
+            a_job.get_hourly(),
+            a_job.get_applied(),
+            a_job.get_optional_link(),
+            a_job.get_company(),
+            job_id,
+            a_job.get_description(),
+            user_id
+        ],
+    )?;
+    if result == 0 {
+        return Ok(false);
+    }
+    sync_status_history(connection, job_id, a_job.get_applied() == "1")?;
+    Ok(true)
+}
+
+/// Merge duplicate jobs into one job.
+///
+/// Missing details of the kept job (link, company, rate, description) are filled in from the
+/// duplicates, the kept job is marked applied if any duplicate was and keeps the
+/// earliest created time, the events, status history and tags of the duplicates are moved
+/// to it, and then the duplicates are removed. Everything happens in one transaction.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the jobs belong to, the ids of other users' jobs are skipped.
+/// * `keep_id` - The id of the job to keep.
+/// * `duplicate_ids` - The ids of the jobs merged into it and removed.
+///
+/// # Returns
+/// * `Ok(usize)` The number of duplicates removed.
+/// * `Err(DatabaseError)` if an error occurs, or `JobNotFound` if the user has no job `keep_id`.
+pub fn merge_jobs(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    keep_id: i64,
+    duplicate_ids: &[i64],
+) -> Result<usize, DatabaseError> {
+    let transaction = connection.unchecked_transaction()?;
+    transaction
+        .query_row(
+            "SELECT id FROM jobs WHERE id = ?1 AND user_id = ?2",
+            [keep_id, user_id],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?
+        .ok_or(DatabaseError::JobNotFound(keep_id))?;
+
+    let mut removed = 0;
+    for &duplicate_id in duplicate_ids.iter().filter(|&&id| id != keep_id) {
+        if !owns_job(&transaction, user_id, duplicate_id)? {
+            continue;
+        }
+        transaction.execute(
+            "UPDATE jobs SET
+                applied = MAX(applied, COALESCE((SELECT applied FROM jobs WHERE id = ?2), 0)),
+                applied_at = COALESCE(applied_at, (SELECT applied_at FROM jobs WHERE id = ?2)),
+                link = CASE WHEN link IS NULL
+                    THEN COALESCE((SELECT link FROM jobs WHERE id = ?2), link) ELSE link END,
+                link_status = CASE WHEN link IS NULL
+                    THEN (SELECT link_status FROM jobs WHERE id = ?2) ELSE link_status END,
+                link_checked_at = CASE WHEN link IS NULL
+                    THEN (SELECT link_checked_at FROM jobs WHERE id = ?2) ELSE link_checked_at END,
+                company = COALESCE(company, (SELECT company FROM jobs WHERE id = ?2)),
+                description = COALESCE(description, (SELECT description FROM jobs WHERE id = ?2)),
+                hourly_rate = COALESCE(hourly_rate, (SELECT hourly_rate FROM jobs WHERE id = ?2)),
+                created_at = COALESCE(
+                    MIN(created_at, (SELECT created_at FROM jobs WHERE id = ?2)),
+                    created_at, (SELECT created_at FROM jobs WHERE id = ?2)),
+                updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?1",
+            rusqlite::params![keep_id, duplicate_id],
+        )?;
+        transaction.execute(
+            "UPDATE events SET job_id = ?1 WHERE job_id = ?2",
+            [keep_id, duplicate_id],
+        )?;
+        transaction.execute(
+            "UPDATE status_history SET job_id = ?1 WHERE job_id = ?2",
+            [keep_id, duplicate_id],
+        )?;
+        // The kept job's archive wins, a duplicate's is only moved when it has none:
+        transaction.execute(
+            "UPDATE OR IGNORE archives SET job_id = ?1 WHERE job_id = ?2",
+            [keep_id, duplicate_id],
+        )?;
+        transaction.execute("DELETE FROM archives WHERE job_id = ?", [duplicate_id])?;
+        transaction.execute(
+            "UPDATE OR IGNORE tags SET job_id = ?1 WHERE job_id = ?2",
+            [keep_id, duplicate_id],
+        )?;
+        transaction.execute("DELETE FROM tags WHERE job_id = ?", [duplicate_id])?;
+        removed += transaction.execute("DELETE FROM jobs WHERE id = ?", [duplicate_id])?;
+    }
+
+    transaction.commit()?;
+    info!("Merged {} duplicates into job {}", removed, keep_id);
+    Ok(removed)
+}
+
+/// Record the result of checking a job's link. It doesn't change the job's updated time,
+/// since the job itself didn't change.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the job belongs to.
+/// * `job_id` - The job whose link was checked.
+/// * `status` - The HTTP status of the link (0 if there was no answer).
+/// * `checked_at` - When the link was checked (UTC).
+///
+/// # Returns
+/// * `Ok(true)` if the result was saved, `Ok(false)` if the user has no job with that id.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn set_link_status(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: i64,
+    status: u16,
+    checked_at: NaiveDateTime,
+) -> Result<bool, DatabaseError> {
+    let updated = connection.execute(
+        "UPDATE jobs SET link_status = ?1, link_checked_at = ?2 WHERE id = ?3 AND user_id = ?4",
+        rusqlite::params![status, checked_at, job_id, user_id],
+    )?;
+    Ok(updated > 0)
+}
+
+/// Save the archived copy of a job's posting page, replacing any older copy.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the job belongs to.
+/// * `job_id` - The job the page belongs to.
+/// * `html` - The sanitized HTML of the page.
+/// * `source` - Where the page came from (its url, or the name of the uploaded file).
+///
+/// # Returns
+/// * `Ok(true)` if the page was saved.
+/// * `Ok(false)` if the user has no job with that id.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn save_archive(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: i64,
+    html: &str,
+    source: &str,
+) -> Result<bool, DatabaseError> {
+    let saved = connection.execute(
+        "INSERT OR REPLACE INTO archives (job_id, html, source, saved_at)
+        SELECT ?1, ?2, ?3, CURRENT_TIMESTAMP
+        WHERE EXISTS (SELECT 1 FROM jobs WHERE id = ?1 AND user_id = ?4)",
+        rusqlite::params![job_id, html, source, user_id],
+    )?;
+    Ok(saved > 0)
+}
+
+/// Get the archived copy of a job's posting page.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the job belongs to.
+/// * `job_id` - The job to get the page of.
+///
+/// # Returns
+/// * `Ok(Some(Archive))` with the page, where it came from and when it was saved.
+/// * `Ok(None)` if the job has no archived page, or isn't the user's.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn get_archive(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: i64,
+) -> Result<Option<Archive>, DatabaseError> {
+    connection
+        .query_row(
+            "SELECT job_id, html, source, saved_at FROM archives
+            WHERE job_id = ?1 AND job_id IN (SELECT id FROM jobs WHERE user_id = ?2)",
+            [job_id, user_id],
+            |row| {
+                Ok(Archive {
+                    job_id: row.get(0)?,
+                    html: row.get(1)?,
+                    source: row.get(2)?,
+                    saved_at: row.get(3)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(DatabaseError::from)
+}
+
+/// Add a user who can log in.
+///
+/// The first user added gets the jobs and goals from before there were users.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `username` - The name to log in with (unique, ignoring case).
+/// * `password_hash` - The argon2 hash of the password (see `auth::hash_password`).
+///
+/// # Returns
+/// * `Ok(Some(id))` The id of the new user.
+/// * `Ok(None)` if the username is taken.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn add_user(
+    connection: &rusqlite::Connection,
+    username: &str,
+    password_hash: &str,
+) -> Result<Option<i64>, DatabaseError> {
+    let inserted = connection.execute(
+        "INSERT OR IGNORE INTO users (username, password_hash) VALUES (?1, ?2)",
+        [username, password_hash],
+    )?;
+    if inserted == 0 {
+        return Ok(None);
+    }
+
+    let id = connection.last_insert_rowid();
+    let users: i64 = connection.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))?;
+    if users == 1 {
+        let jobs = connection.execute("UPDATE jobs SET user_id = ? WHERE user_id IS NULL", [id])?;
+        connection.execute("UPDATE goals SET user_id = ? WHERE user_id IS NULL", [id])?;
+        info!(
+            "Gave the {} jobs from before there were users to {}",
+            jobs, username
+        );
+    }
+    Ok(Some(id))
+}
+
+/// Get a user and their password hash by username (ignoring case), to log them in.
+///
+/// # Returns
+/// * `Ok(Some((User, password_hash)))`, or `Ok(None)` if there is no such user.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn get_user_login(
+    connection: &rusqlite::Connection,
+    username: &str,
+) -> Result<Option<(User, String)>, DatabaseError> {
+    connection
+        .query_row(
+            "SELECT id, username, password_hash FROM users WHERE username = ?",
+            [username],
+            |row| {
+                Ok((
+                    User {
+                        id: row.get(0)?,
+                        username: row.get(1)?,
+                    },
+                    row.get(2)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(DatabaseError::from)
+}
+
+/// Get every user, by username.
+pub fn get_users(connection: &rusqlite::Connection) -> Result<Vec<User>, DatabaseError> {
+    let mut statement = connection.prepare("SELECT id, username FROM users ORDER BY username")?;
+    let users = statement
+        .query_map([], |row| {
+            Ok(User {
+                id: row.get(0)?,
+                username: row.get(1)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(users)
+}
+
+/// Change the password hash of a user, and log them out everywhere.
+///
+/// # Returns
+/// * `Ok(true)` if the password was changed, `Ok(false)` if there is no such user.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn set_password(
+    connection: &rusqlite::Connection,
+    username: &str,
+    password_hash: &str,
+) -> Result<bool, DatabaseError> {
+    let updated = connection.execute(
+        "UPDATE users SET password_hash = ?2 WHERE username = ?1",
+        [username, password_hash],
+    )?;
+    connection.execute(
+        "DELETE FROM sessions WHERE user_id IN (SELECT id FROM users WHERE username = ?)",
+        [username],
+    )?;
+    Ok(updated > 0)
+}
+
+/// Remove a user, with their sessions, API tokens, goals and jobs.
+///
+/// # Returns
+/// * `Ok(true)` if the user was removed, `Ok(false)` if there is no such user.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn remove_user(
+    connection: &rusqlite::Connection,
+    username: &str,
+) -> Result<bool, DatabaseError> {
+    let Some(user_id) = connection
+        .query_row(
+            "SELECT id FROM users WHERE username = ?",
+            [username],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?
+    else {
+        return Ok(false);
+    };
+
+    let transaction = connection.unchecked_transaction()?;
+    for job in get_jobs(&transaction, user_id)? {
+        if let Some(job_id) = job.get_id() {
+            remove_data(&transaction, user_id, job_id)?;
+        }
+    }
+    transaction.execute("DELETE FROM goals WHERE user_id = ?", [user_id])?;
+    transaction.execute("DELETE FROM sessions WHERE user_id = ?", [user_id])?;
+    transaction.execute("DELETE FROM api_tokens WHERE user_id = ?", [user_id])?;
+    transaction.execute("DELETE FROM users WHERE id = ?", [user_id])?;
+    transaction.commit()?;
+    Ok(true)
+}
+
+/// Start a login session for a user. Expired sessions are cleaned up at the same time.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `token_hash` - The hash of the session token kept in the user's cookie.
+/// * `user_id` - The user who logged in.
+/// * `now` - The current time (UTC).
+/// * `expires_at` - When the session ends (UTC).
+pub fn add_session(
+    connection: &rusqlite::Connection,
+    token_hash: &str,
+    user_id: i64,
+    now: NaiveDateTime,
+    expires_at: NaiveDateTime,
+) -> Result<(), DatabaseError> {
+    connection.execute("DELETE FROM sessions WHERE expires_at <= ?", [now])?;
+    connection.execute(
+        "INSERT INTO sessions (token_hash, user_id, created_at, expires_at) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![token_hash, user_id, now, expires_at],
+    )?;
+    Ok(())
+}
+
+/// Get the user of a session that hasn't expired.
+///
+/// # Returns
+/// * `Ok(Some(User))`, or `Ok(None)` if there is no such session or it expired.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn get_session_user(
+    connection: &rusqlite::Connection,
+    token_hash: &str,
+    now: NaiveDateTime,
+) -> Result<Option<User>, DatabaseError> {
+    connection
+        .query_row(
+            "SELECT users.id, users.username FROM sessions
+            JOIN users ON users.id = sessions.user_id
+            WHERE sessions.token_hash = ?1 AND sessions.expires_at > ?2",
+            rusqlite::params![token_hash, now],
+            |row| {
+                Ok(User {
+                    id: row.get(0)?,
+                    username: row.get(1)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(DatabaseError::from)
+}
+
+/// End a login session.
+///
+/// # Returns
+/// * `Ok(true)` if the session was ended, `Ok(false)` if there was no such session.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn remove_session(
+    connection: &rusqlite::Connection,
+    token_hash: &str,
+) -> Result<bool, DatabaseError> {
+    let removed = connection.execute("DELETE FROM sessions WHERE token_hash = ?", [token_hash])?;
+    Ok(removed > 0)
+}
+
+/// Add an API token for a user.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the token acts for.
+/// * `name` - What the token is for.
+/// * `token_hash` - The hash of the token (see `auth::token_hash`), the token itself isn't kept.
+/// * `scope` - What the token is allowed to do.
+/// * `now` - The current time (UTC).
+///
+/// # Returns
+/// * `Ok(id)` The id of the new token.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn add_api_token(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    name: &str,
+    token_hash: &str,
+    scope: TokenScope,
+    now: NaiveDateTime,
+) -> Result<i64, DatabaseError> {
+    connection.execute(
+        "INSERT INTO api_tokens (user_id, name, token_hash, scope, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![user_id, name, token_hash, scope.name(), now],
+    )?;
+    Ok(connection.last_insert_rowid())
+}
+
+/// Get the API tokens of a user, oldest first.
+pub fn get_api_tokens(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+) -> Result<Vec<ApiToken>, DatabaseError> {
+    let mut statement = connection.prepare(
+        "SELECT id, name, scope, created_at, last_used_at FROM api_tokens
+        WHERE user_id = ? ORDER BY id",
+    )?;
+    let tokens = statement
+        .query_map([user_id], |row| {
+            Ok(ApiToken {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                scope: token_scope(&row.get::<_, String>(2)?),
+                created_at: row.get(3)?,
+                last_used_at: row.get(4)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(tokens)
+}
+
+/// Revoke an API token of a user.
+///
+/// # Returns
+/// * `Ok(true)` if the token was revoked, `Ok(false)` if the user has no such token.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn remove_api_token(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    token_id: i64,
+) -> Result<bool, DatabaseError> {
+    let removed = connection.execute(
+        "DELETE FROM api_tokens WHERE id = ?1 AND user_id = ?2",
+        [token_id, user_id],
+    )?;
+    Ok(removed > 0)
+}
+
+/// Get the user and scope of an API token, and note that it was used.
+///
+/// # Returns
+/// * `Ok(Some((User, TokenScope)))`, or `Ok(None)` if there is no such token.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn get_token_user(
+    connection: &rusqlite::Connection,
+    token_hash: &str,
+    now: NaiveDateTime,
+) -> Result<Option<(User, TokenScope)>, DatabaseError> {
+    let found = connection
+        .query_row(
+            "SELECT users.id, users.username, api_tokens.scope FROM api_tokens
+            JOIN users ON users.id = api_tokens.user_id
+            WHERE api_tokens.token_hash = ?",
+            [token_hash],
+            |row| {
+                Ok((
+                    User {
+                        id: row.get(0)?,
+                        username: row.get(1)?,
+                    },
+                    token_scope(&row.get::<_, String>(2)?),
+                ))
+            },
+        )
+        .optional()?;
+    if found.is_some() {
+        connection.execute(
+            "UPDATE api_tokens SET last_used_at = ?2 WHERE token_hash = ?1",
+            rusqlite::params![token_hash, now],
+        )?;
+    }
+    Ok(found)
+}
+
+// The scope of a token row; an unknown scope only allows reading.
+fn token_scope(name: &str) -> TokenScope {
+    TokenScope::from_name(name).unwrap_or(TokenScope::Read)
+}
+
+/// Add a deadline, follow-up reminder or interview to a job.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the event's job belongs to.
+/// * `event` - The event to add (its id is ignored).
+///
+/// # Returns
+/// * `Ok(Some(id))` The id of the new event.
+/// * `Ok(None)` if the user has no job with the event's `job_id`.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn add_event(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    event: &JobEvent,
+) -> Result<Option<i64>, DatabaseError> {
+    let inserted = connection.execute(
+        "INSERT INTO events (job_id, kind, date, time, note)
+        SELECT ?1, ?2, ?3, ?4, ?5
+        WHERE EXISTS (SELECT 1 FROM jobs WHERE id = ?1 AND user_id = ?6)",
+        rusqlite::params![
+            event.job_id,
+            event.kind.name(),
+            event.date,
+            event.time.map(|t| t.format("%H:%M").to_string()),
+            event.note,
+            user_id
+        ],
+    )?;
+
+    if inserted > 0 {
+        Ok(Some(connection.last_insert_rowid()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Get the events of one job, or of every job of a user, ordered by date and time.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the jobs belong to.
+/// * `job_id` - The job to get the events of, or `None` for all the user's jobs.
+///
+/// # Returns
+/// * `Ok(Vec<JobEvent>)` with the events.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn get_events(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job_id: Option<i64>,
+) -> Result<Vec<JobEvent>, DatabaseError> {
+    let mut statement = connection.prepare(
+        "SELECT id, job_id, kind, date, time, note FROM events
+        WHERE (?1 IS NULL OR job_id = ?1)
+            AND job_id IN (SELECT id FROM jobs WHERE user_id = ?2)
+        ORDER BY date, time IS NOT NULL, time, id",
+    )?;
+
+    let events = statement.query_map(rusqlite::params![job_id, user_id], |row| {
+        let kind: String = row.get(2)?;
+        let kind = EventKind::from_name(&kind).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                2,
+                rusqlite::types::Type::Text,
+                format!("unknown event kind \"{}\"", kind).into(),
+            )
+        })?;
+        Ok(JobEvent {
+            id: Some(row.get(0)?),
+            job_id: row.get(1)?,
+            kind,
+            date: row.get(3)?,
+            time: row.get(4)?,
+            note: row.get(5)?,
+        })
+    })?;
+
+    events
+        .collect::<Result<_, _>>()
+        .map_err(DatabaseError::from)
+}
+
+/// Remove an event.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the event's job belongs to.
+/// * `id` - The id of the event to remove.
+///
+/// # Returns
+/// * `Ok(true)` if the event was removed, `Ok(false)` if the user has no event with that id.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn remove_event(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    id: i64,
+) -> Result<bool, DatabaseError> {
+    let result = connection.execute(
+        "DELETE FROM events WHERE id = ?1 AND job_id IN (SELECT id FROM jobs WHERE user_id = ?2)",
+        [id, user_id],
+    )?;
+    Ok(result > 0)
+}
+
+/// Count a user's jobs by application status.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user whose jobs are counted.
+///
+/// # Returns
+/// * `Ok((applied, not_applied))` The number of jobs applied to and not applied to.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn count_by_status(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+) -> Result<(i64, i64), DatabaseError> {
+    connection
+        .query_row(
+            "SELECT COALESCE(SUM(applied = 1), 0), COALESCE(SUM(applied = 0), 0) FROM jobs
+        WHERE user_id = ?",
+            [user_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(DatabaseError::from)
+}
+
+/// The average and median hourly rate of a user's jobs applied to, or not applied to.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user whose jobs are used.
+/// * `applied` - Whether to use the jobs applied to, or the jobs not applied to.
+///
+/// # Returns
+/// * `Ok((average, median))` The pay, or `None` for both if there are no such jobs with a rate.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn pay_by_status(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    applied: bool,
+) -> Result<(Option<f64>, Option<f64>), DatabaseError> {
+    let average: Option<f64> = connection.query_row(
+        "SELECT AVG(hourly_rate) FROM jobs
+        WHERE applied = ?1 AND hourly_rate IS NOT NULL AND user_id = ?2",
+        [applied as i64, user_id],
+        |row| row.get(0),
+    )?;
+
+    // The middle rate, or the average of the two middle rates for an even count:
+    let median: Option<f64> = connection.query_row(
+        "SELECT AVG(hourly_rate) FROM (
+            SELECT hourly_rate FROM jobs
+            WHERE applied = ?1 AND hourly_rate IS NOT NULL AND user_id = ?2
+            ORDER BY hourly_rate
+            LIMIT 2 - (SELECT COUNT(*) FROM jobs
+                WHERE applied = ?1 AND hourly_rate IS NOT NULL AND user_id = ?2) % 2
+            OFFSET ((SELECT COUNT(*) FROM jobs
+                WHERE applied = ?1 AND hourly_rate IS NOT NULL AND user_id = ?2) - 1) / 2
+        )",
+        [applied as i64, user_id],
+        |row| row.get(0),
+    )?;
+
+    Ok((average, median))
+}
+
+/// Count the applications a user made each week, from the time each job was marked applied.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user whose jobs are counted.
+/// * `weeks` - How many of the most recent weeks with applications to return.
+///
+/// # Returns
+/// * `Ok(Vec<(String, i64)>)` The Monday of each week (`YYYY-MM-DD`) and its count, oldest first.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn applications_per_week(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    weeks: i64,
+) -> Result<Vec<(String, i64)>, DatabaseError> {
+    let mut statement = connection.prepare(
+        "SELECT week, applications FROM (
+            SELECT date(applied_at, '-6 days', 'weekday 1') AS week, COUNT(*) AS applications
+            FROM jobs
+            WHERE applied = 1 AND applied_at IS NOT NULL AND user_id = ?1
+            GROUP BY week
+            ORDER BY week DESC
+            LIMIT ?2
+        ) ORDER BY week",
+    )?;
+    let rows = statement.query_map([user_id, weeks], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect::<Result<_, _>>().map_err(DatabaseError::from)
+}
+
+/// Set a new weekly application goal. The old goals are kept, so the
+/// weeks before the change are still compared to the goal of their time.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the goal is for.
+/// * `target` - The number of applications to make each week (more than 0).
+///
+/// # Returns
+/// * `Ok(())` if the goal was set.
+/// * `Err(DatabaseError)` if an error occurs (a target of 0 or less fails the table's check).
+pub fn set_goal(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    target: i64,
+) -> Result<(), DatabaseError> {
+    connection.execute(
+        "INSERT INTO goals (target, user_id) VALUES (?1, ?2)",
+        [target, user_id],
+    )?;
     Ok(())
 }
 
-/// Update the applied status for a job in the database.
+/// Every weekly application goal of a user, with the week it was set.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
+/// * `user_id` - The user the goals are for.
+///
+/// # Returns
+/// * `Ok(Vec<(String, i64)>)` The Monday of the week each goal was set (`YYYY-MM-DD`) and its target, oldest first.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn get_goals(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+) -> Result<Vec<(String, i64)>, DatabaseError> {
+    let mut statement = connection.prepare(
+        "SELECT date(set_at, '-6 days', 'weekday 1'), target FROM goals
+        WHERE user_id = ?
+        ORDER BY set_at, id",
+    )?;
+    let rows = statement.query_map([user_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect::<Result<_, _>>().map_err(DatabaseError::from)
+}
+
+/// The companies with the most jobs in a user's list.
 ///
 /// # Arguments
 /// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
-/// * `new_status` - A bool to signify yes or no that the application status is changed.
-/// * `job_id` - The id of the job being updated.
+/// * `user_id` - The user whose jobs are counted.
+/// * `limit` - How many companies to return.
 ///
 /// # Returns
-/// * `Ok(())` if query exists on success.
-/// * `Err(rusqlite::Error)` if an error occurs.
-pub fn update_applied(
+/// * `Ok(Vec<(String, i64, i64)>)` Each company with its number of jobs and applications, most jobs first.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn top_employers(
     connection: &rusqlite::Connection,
-    new_status: bool,
-    job_id: i64,
-) -> rusqlite::Result<()> {
-    connection.execute(
-        "UPDATE jobs SET applied = ? WHERE id = ?",
-        (new_status as i32, job_id),
+    user_id: i64,
+    limit: i64,
+) -> Result<Vec<(String, i64, i64)>, DatabaseError> {
+    let mut statement = connection.prepare(
+        "SELECT company, COUNT(*) AS jobs, SUM(applied = 1) AS applications
+        FROM jobs
+        WHERE company IS NOT NULL AND company != '' AND user_id = ?1
+        GROUP BY company
+        ORDER BY jobs DESC, applications DESC, company
+        LIMIT ?2",
     )?;
-    Ok(())
+    let rows = statement.query_map([user_id, limit], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })?;
+    rows.collect::<Result<_, _>>().map_err(DatabaseError::from)
 }
 
-/// Count all the rows in the database.
+/// Count all the rows in the database, the jobs of every user.
 ///
 /// # Arguments
 /// * `connection` - A reference to an active SQLite connection (rusqlite::Connection).
 ///
 /// # Returns
 /// * `i64` The total rows counted in the database.
-/// * `Err(rusqlite::Error)` if an error occurs.
-pub fn count_rows(connection: &rusqlite::Connection) -> Result<i64, rusqlite::Error> {
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn count_rows(connection: &rusqlite::Connection) -> Result<i64, DatabaseError> {
     // Prepare the query to count rows in the 'jobs' table:
     let mut statement = connection.prepare("SELECT COUNT(*) FROM jobs")?;
 
@@ -191,8 +1729,8 @@ pub fn count_rows(connection: &rusqlite::Connection) -> Result<i64, rusqlite::Er
 ///
 /// # Returns
 /// * `bool` The true or false value depending on the total rows in the database. 0 is true, any rows is false.
-/// * `Err(rusqlite::Error)` if an error occurs.
-pub fn database_empty(connection: &rusqlite::Connection) -> Result<bool, rusqlite::Error> {
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn database_empty(connection: &rusqlite::Connection) -> Result<bool, DatabaseError> {
     let row_count = count_rows(connection)?;
     Ok(row_count == 0)
 }
@@ -207,8 +1745,12 @@ pub fn database_empty(connection: &rusqlite::Connection) -> Result<bool, rusqlit
 mod tests {
     use super::*;
     use crate::job::Job;
+    use chrono::Datelike;
     use rusqlite::Connection;
 
+    // The user the test jobs belong to:
+    const USER: i64 = 1;
+
     // Helper function to set up an in-memory database and create the jobs table:
     fn setup_database() -> Connection {
         let connection = Connection::open_in_memory().expect("Failed to open in-memory database");
@@ -218,12 +1760,12 @@ mod tests {
 
     // Helper function to insert a job:
     fn insert_job(connection: &Connection, job: &Job) {
-        enter_data(&connection, &job).expect("Failed to insert job");
+        enter_data(connection, USER, job).expect("Failed to insert job");
     }
 
     // Helper function to count the number of jobs in the database:
     fn count_jobs(connection: &Connection) -> i64 {
-        count_rows(&connection).expect("Failed to count rows")
+        count_rows(connection).expect("Failed to count rows")
     }
 
     // Create an empty database and check that it returns the Ok().
@@ -276,7 +1818,7 @@ mod tests {
         );
 
         // Step 4: Call the enter_data method to insert the job into the database:
-        let result = enter_data(&connection, &job);
+        let result = enter_data(&connection, USER, &job);
 
         // Step 5: Ensure the insertion was successful:
         assert!(result.is_ok(), "Failed to insert data: {:?}", result);
@@ -335,7 +1877,6 @@ mod tests {
         );
 
         // Insert the job into the database:
-        //enter_data(&connection, &job).expect("Failed to insert job");
         insert_job(&connection, &job);
 
         // Step 4: Verify the job was inserted:
@@ -350,7 +1891,7 @@ mod tests {
         assert_eq!(count, 1, "Job should be in the database.");
 
         // Step 5: Call remove_data to remove the job by its id (assuming id is 1 here):
-        let result = remove_data(&connection, 1);
+        let result = remove_data(&connection, USER, 1);
 
         // Step 6: Assert the job was removed successfully:
         assert!(result.is_ok(), "Failed to remove job: {:?}", result);
@@ -370,7 +1911,7 @@ mod tests {
         );
 
         // Step 8: Attempt to remove a non-existent job (id 99999):
-        let result_non_existent = remove_data(&connection, 99999);
+        let result_non_existent = remove_data(&connection, USER, 99999);
 
         // Assert that no job was removed (returns false):
         assert!(
@@ -383,6 +1924,119 @@ mod tests {
         );
     }
 
+    // Removing many jobs skips the ids of other users, and removes nothing if one fails:
+    #[test]
+    fn test_remove_jobs() {
+        let connection = setup_database();
+        let job = |title: &str| Job::new(None, title.to_string(), 20.0, "0".to_string(), None);
+        let cook = enter_data(&connection, USER, &job("Cook")).unwrap();
+        let host = enter_data(&connection, USER, &job("Host")).unwrap();
+        let welder = enter_data(&connection, USER, &job("Welder")).unwrap();
+        let other = enter_data(&connection, 2, &job("Barista")).unwrap();
+        let event = JobEvent::parse(cook, "interview", "2025-03-14", None, None).unwrap();
+        add_event(&connection, USER, &event).unwrap();
+
+        let removed = remove_jobs(&connection, USER, &[cook, welder, other, 99]).unwrap();
+        assert_eq!(removed, 2);
+        let titles: Vec<String> = get_jobs(&connection, USER)
+            .unwrap()
+            .iter()
+            .map(|job| job.get_title())
+            .collect();
+        assert_eq!(titles, vec!["Host"]);
+        assert_eq!(get_jobs(&connection, 2).unwrap().len(), 1);
+        assert!(get_events(&connection, USER, None).unwrap().is_empty());
+
+        // A job that can't be removed keeps the others of the batch:
+        let nurse = enter_data(&connection, USER, &job("Nurse")).unwrap();
+        connection
+            .execute(
+                &format!(
+                    "CREATE TRIGGER keep_nurse BEFORE DELETE ON jobs WHEN old.id = {}
+                    BEGIN SELECT RAISE(ABORT, 'kept'); END",
+                    nurse
+                ),
+                [],
+            )
+            .unwrap();
+        assert!(remove_jobs(&connection, USER, &[host, nurse]).is_err());
+        assert_eq!(get_jobs(&connection, USER).unwrap().len(), 2);
+    }
+
+    // Each bulk action changes only the user's checked jobs, and nothing if one fails:
+    #[test]
+    fn test_bulk_update() {
+        let connection = setup_database();
+        let job = |title: &str| Job::new(None, title.to_string(), 20.0, "0".to_string(), None);
+        let cook = enter_data(&connection, USER, &job("Cook")).unwrap();
+        let host = enter_data(&connection, USER, &job("Host")).unwrap();
+        let other = enter_data(&connection, 2, &job("Barista")).unwrap();
+        let get = |id| get_job(&connection, USER, id).unwrap().unwrap();
+
+        let interview = BulkAction::SetStage(Stage::Interview);
+        assert_eq!(
+            bulk_update(&connection, USER, &[cook, host, other], &interview).unwrap(),
+            2
+        );
+        assert_eq!(get(cook).get_stage(), Some(Stage::Interview));
+        assert_eq!(get(host).get_applied(), "Yes");
+        assert_eq!(
+            get_job(&connection, 2, other).unwrap().unwrap().get_stage(),
+            None
+        );
+        bulk_update(&connection, USER, &[host], &BulkAction::NotApplied).unwrap();
+        assert_eq!(get(host).get_stage(), None);
+
+        // Tags are kept once per job, whatever their case, and read back sorted:
+        for tag in ["remote", "Contract", "Remote"] {
+            let action = BulkAction::AddTag(tag.to_string());
+            bulk_update(&connection, USER, &[cook, other], &action).unwrap();
+        }
+        assert_eq!(get(cook).get_tags(), ["Contract", "remote"]);
+        assert!(get(host).get_tags().is_empty());
+        assert!(get_job(&connection, 2, other)
+            .unwrap()
+            .unwrap()
+            .get_tags()
+            .is_empty());
+
+        bulk_update(&connection, USER, &[cook], &BulkAction::Archive).unwrap();
+        let archived_at = get(cook).get_archived_at();
+        assert!(archived_at.is_some());
+        bulk_update(&connection, USER, &[cook], &BulkAction::Archive).unwrap();
+        assert_eq!(get(cook).get_archived_at(), archived_at);
+        bulk_update(&connection, USER, &[cook], &BulkAction::Unarchive).unwrap();
+        assert_eq!(get(cook).get_archived_at(), None);
+
+        // A job that can't be changed keeps the others of the batch as they were:
+        connection
+            .execute(
+                &format!(
+                    "CREATE TRIGGER keep_host BEFORE UPDATE ON jobs WHEN old.id = {}
+                    BEGIN SELECT RAISE(ABORT, 'kept'); END",
+                    host
+                ),
+                [],
+            )
+            .unwrap();
+        assert!(bulk_update(&connection, USER, &[cook, host], &BulkAction::Archive).is_err());
+        assert_eq!(get(cook).get_archived_at(), None);
+
+        // Deleting removes the tags with the job:
+        assert_eq!(
+            bulk_update(&connection, USER, &[cook], &BulkAction::Delete).unwrap(),
+            1
+        );
+        let tags: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM tags WHERE job_id = ?",
+                [cook],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tags, 0);
+    }
+
     // Create database, insert jobs, and get the jobs:
     #[test]
     fn test_get_jobs() {
@@ -410,11 +2064,11 @@ mod tests {
         );
 
         // Insert jobs into the database:
-        enter_data(&connection, &job1).expect("Failed to insert job1");
-        enter_data(&connection, &job2).expect("Failed to insert job2");
+        enter_data(&connection, USER, &job1).expect("Failed to insert job1");
+        enter_data(&connection, USER, &job2).expect("Failed to insert job2");
 
         // Step 4: Call get_jobs to fetch all jobs from the database:
-        let jobs_result = get_jobs(&connection);
+        let jobs_result = get_jobs(&connection, USER);
 
         // Step 5: Assert that get_jobs was successful and jobs are returned:
         assert!(
@@ -436,7 +2090,7 @@ mod tests {
         assert!(applied_status.contains(&"No".to_string()));
 
         // Step 8: Verify the links:
-        let links: Vec<String> = jobs.iter().filter_map(|j| Some(j.get_link())).collect();
+        let links: Vec<String> = jobs.iter().filter_map(|j| j.get_optional_link()).collect();
         assert!(links.contains(&"https://example1.com".to_string()));
         assert!(links.contains(&"https://example2.com".to_string()));
     }
@@ -469,9 +2123,9 @@ mod tests {
         );
         insert_job(&connection, &job);
 
-        update_applied(&connection, true, 1).expect("Failed to update applied status");
+        update_applied(&connection, USER, true, 1).expect("Failed to update applied status");
 
-        let jobs = get_jobs(&connection).expect("Failed to fetch jobs");
+        let jobs = get_jobs(&connection, USER).expect("Failed to fetch jobs");
         assert_eq!(jobs.len(), 1, "There should be 1 job in the result.");
         assert_eq!(
             jobs[0].get_applied(),
@@ -529,4 +2183,761 @@ mod tests {
             "The database should not be empty after insertion."
         );
     }
+
+    // Update every detail of a job by id:
+    #[test]
+    fn test_update_job() {
+        let connection = setup_database();
+        let job = Job::new(
+            None,
+            "Barista".to_string(),
+            16.0,
+            "0".to_string(),
+            Some("https://example.com".to_string()),
+        );
+        insert_job(&connection, &job);
+
+        let edited = Job::new(
+            None,
+            "Head Barista".to_string(),
+            19.5,
+            "1".to_string(),
+            Some("https://example.com/head".to_string()),
+        )
+        .with_company(Some("Black Rock".to_string()));
+        assert!(update_job(&connection, USER, 1, &edited).expect("Failed to update job"));
+        assert!(!update_job(&connection, USER, 99, &edited).expect("Failed to update job"));
+
+        let jobs = get_jobs(&connection, USER).expect("Failed to fetch jobs");
+        assert_eq!(jobs[0].get_title(), "Head Barista");
+        assert_eq!(jobs[0].get_hourly(), 19.5);
+        assert_eq!(jobs[0].get_applied(), "Yes");
+        assert_eq!(jobs[0].get_company(), Some("Black Rock".to_string()));
+    }
+
+    // Merging fills in missing details and removes the duplicates:
+    #[test]
+    fn test_merge_jobs() {
+        let connection = setup_database();
+        let keep = Job::new(None, "Barista".to_string(), 16.0, "0".to_string(), None);
+        let duplicate = Job::new(
+            None,
+            "Barista".to_string(),
+            16.0,
+            "1".to_string(),
+            Some("https://example.com".to_string()),
+        )
+        .with_company(Some("Black Rock".to_string()));
+        insert_job(&connection, &keep);
+        insert_job(&connection, &duplicate);
+        for (ids, tag) in [(&[1, 2][..], "remote"), (&[2][..], "contract")] {
+            bulk_update(&connection, USER, ids, &BulkAction::AddTag(tag.to_string())).unwrap();
+        }
+
+        let removed = merge_jobs(&connection, USER, 1, &[1, 2]).expect("Failed to merge jobs");
+        assert_eq!(removed, 1, "The kept job is never removed.");
+
+        let jobs = get_jobs(&connection, USER).expect("Failed to fetch jobs");
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(
+            jobs[0].get_optional_link().as_deref(),
+            Some("https://example.com")
+        );
+        assert_eq!(jobs[0].get_company(), Some("Black Rock".to_string()));
+        assert_eq!(jobs[0].get_applied(), "Yes");
+        assert_eq!(jobs[0].get_tags(), ["contract", "remote"]);
+
+        assert!(
+            matches!(
+                merge_jobs(&connection, USER, 42, &[1]),
+                Err(DatabaseError::JobNotFound(42))
+            ),
+            "The kept job must exist."
+        );
+    }
+
+    // Jobs without a link keep none, and old placeholders become none, once:
+    #[test]
+    fn test_optional_link() {
+        let connection = setup_database();
+        insert_job(
+            &connection,
+            &Job::new(None, "Barista".to_string(), 16.0, "0".to_string(), None),
+        );
+        connection
+            .execute(
+                "INSERT INTO jobs (job_title, hourly_rate, applied, link, user_id)
+                VALUES ('Cook', 18.0, 0, 'No Link', ?1), ('Host', 15.0, 0, '', ?1)",
+                [USER],
+            )
+            .expect("Failed to insert old jobs");
+        // A database made before the placeholders were NULL:
+        connection
+            .execute_batch("PRAGMA user_version = 0")
+            .expect("Failed to set the version");
+        create_table(&connection).expect("Failed to upgrade table");
+
+        let jobs = get_jobs(&connection, USER).expect("Failed to fetch jobs");
+        assert_eq!(jobs.len(), 3);
+        assert!(jobs.iter().all(|job| job.get_optional_link().is_none()));
+        let job = get_job(&connection, USER, 1).expect("Failed to fetch job");
+        assert_eq!(job.and_then(|job| job.get_id()), Some(1));
+
+        // The upgraded database isn't changed again on the next start:
+        connection
+            .execute("UPDATE jobs SET link = '' WHERE id = 1", [])
+            .expect("Failed to change the link");
+        create_table(&connection).expect("Failed to open table again");
+        let link: Option<String> = connection
+            .query_row("SELECT link FROM jobs WHERE id = 1", [], |row| row.get(0))
+            .expect("Failed to read the link");
+        assert_eq!(link.as_deref(), Some(""));
+    }
+
+    // A job without an hourly rate (a NaN rate is stored as NULL) doesn't break the list:
+    #[test]
+    fn test_missing_hourly_rate() {
+        let connection = setup_database();
+        insert_job(
+            &connection,
+            &Job::new(None, "Barista".to_string(), 16.0, "0".to_string(), None),
+        );
+        connection
+            .execute(
+                "INSERT INTO jobs (job_title, hourly_rate, applied, user_id)
+                VALUES ('Cook', NULL, 0, ?1)",
+                [USER],
+            )
+            .expect("Failed to insert the job");
+
+        let jobs = get_jobs(&connection, USER).expect("Failed to fetch jobs");
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[1].get_title(), "Cook");
+        assert_eq!(jobs[1].get_hourly(), 0.0);
+    }
+
+    // Jobs imported as applied by an older version get the status history they missed:
+    #[test]
+    fn test_migrate_status_history() {
+        let connection = setup_database();
+        connection
+            .execute(
+                "INSERT INTO jobs (job_title, hourly_rate, applied, applied_at, user_id)
+                VALUES ('Cook', 18.0, 1, '2025-03-14 09:00:00', ?1),
+                    ('Host', 15.0, 0, NULL, ?1)",
+                [USER],
+            )
+            .expect("Failed to insert old jobs");
+        connection
+            .execute_batch("PRAGMA user_version = 1")
+            .expect("Failed to set the version");
+        create_table(&connection).expect("Failed to upgrade table");
+
+        let history = stage_history(&connection, USER).expect("Failed to read the history");
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].0, history[0].1), (1, Stage::Applied));
+        let applied_at = NaiveDateTime::parse_from_str("2025-03-14 09:00:00", "%Y-%m-%d %H:%M:%S");
+        let day = applied_at.unwrap().and_utc().timestamp() as f64 / 86400.0 + 2440587.5;
+        assert!(
+            (history[0].2 - day).abs() < 1e-6,
+            "Applied when the job was."
+        );
+    }
+
+    // A row that can't be read is an error, not a job that goes missing:
+    #[test]
+    fn test_bad_value() {
+        let connection = setup_database();
+        connection
+            .execute(
+                "INSERT INTO jobs (job_title, hourly_rate, applied, user_id)
+                VALUES ('Barista', 'sixteen', 0, ?)",
+                [USER],
+            )
+            .expect("Failed to insert job");
+
+        assert!(matches!(
+            get_jobs(&connection, USER),
+            Err(DatabaseError::BadValue(_))
+        ));
+        assert!(matches!(
+            get_job(&connection, USER, 1),
+            Err(DatabaseError::BadValue(_))
+        ));
+    }
+
+    // Columns are added to tables created by older versions:
+    #[test]
+    fn test_add_column_if_missing() {
+        let connection = Connection::open_in_memory().expect("Failed to open in-memory database");
+        connection
+            .execute(
+                "CREATE TABLE jobs (id INTEGER PRIMARY KEY, job_title TEXT NOT NULL,
+                    hourly_rate REAL, applied INTEGER NOT NULL, link TEXT)",
+                [],
+            )
+            .expect("Failed to create old table");
+
+        create_table(&connection).expect("Failed to upgrade table");
+        create_table(&connection).expect("Upgrading twice should do nothing");
+
+        let result = connection.prepare("SELECT company FROM jobs");
+        assert!(result.is_ok(), "The company column should be added.");
+    }
+
+    // Add, list and remove the events of a job:
+    #[test]
+    fn test_events() {
+        let connection = setup_database();
+        let job = Job::new(
+            None,
+            "Barista".to_string(),
+            16.0,
+            "0".to_string(),
+            Some("https://example.com".to_string()),
+        );
+        insert_job(&connection, &job);
+        insert_job(&connection, &job);
+
+        let interview = JobEvent::parse(1, "interview", "2025-03-14", Some("09:30"), None).unwrap();
+        let deadline = JobEvent::parse(1, "deadline", "2025-03-14", None, Some("Apply")).unwrap();
+        let other = JobEvent::parse(2, "follow_up", "2025-03-01", None, None).unwrap();
+        let missing = JobEvent::parse(99, "deadline", "2025-03-01", None, None).unwrap();
+
+        let interview_id = add_event(&connection, USER, &interview).unwrap();
+        assert!(interview_id.is_some());
+        add_event(&connection, USER, &deadline).unwrap();
+        add_event(&connection, USER, &other).unwrap();
+        assert_eq!(
+            add_event(&connection, USER, &missing).unwrap(),
+            None,
+            "Events need a job."
+        );
+
+        let events = get_events(&connection, USER, Some(1)).expect("Failed to get events");
+        let kinds: Vec<EventKind> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![EventKind::Deadline, EventKind::Interview],
+            "All-day events come first."
+        );
+        assert_eq!(events[1].time, interview.time);
+        assert_eq!(get_events(&connection, USER, None).unwrap().len(), 3);
+
+        assert!(remove_event(&connection, USER, interview_id.unwrap()).unwrap());
+        assert!(remove_data(&connection, USER, 1).unwrap());
+        assert_eq!(
+            get_events(&connection, USER, None).unwrap().len(),
+            1,
+            "Removing a job removes its events."
+        );
+    }
+
+    // Helper function to insert a job with a rate, status and company:
+    fn insert_stats_job(connection: &Connection, rate: f32, applied: &str, company: Option<&str>) {
+        let job = Job::new(
+            None,
+            "Job".to_string(),
+            rate,
+            applied.to_string(),
+            Some("https://example.com".to_string()),
+        )
+        .with_company(company.map(str::to_string));
+        insert_job(connection, &job);
+    }
+
+    // Count by status and compute the average and median pay:
+    #[test]
+    fn test_status_and_pay_stats() {
+        let connection = setup_database();
+        assert_eq!(count_by_status(&connection, USER).unwrap(), (0, 0));
+        assert_eq!(
+            pay_by_status(&connection, USER, true).unwrap(),
+            (None, None)
+        );
+
+        insert_stats_job(&connection, 10.0, "1", None);
+        insert_stats_job(&connection, 20.0, "1", None);
+        insert_stats_job(&connection, 60.0, "1", None);
+        insert_stats_job(&connection, 15.0, "0", None);
+        insert_stats_job(&connection, 25.0, "0", None);
+
+        assert_eq!(count_by_status(&connection, USER).unwrap(), (3, 2));
+        assert_eq!(
+            pay_by_status(&connection, USER, true).unwrap(),
+            (Some(30.0), Some(20.0))
+        );
+        assert_eq!(
+            pay_by_status(&connection, USER, false).unwrap(),
+            (Some(20.0), Some(20.0)),
+            "The median of an even count averages the middle two."
+        );
+    }
+
+    // Applications are grouped by the Monday of the week they were made:
+    #[test]
+    fn test_applications_per_week() {
+        let connection = setup_database();
+        for _ in 0..3 {
+            insert_stats_job(&connection, 20.0, "0", None);
+        }
+        for (id, applied_at) in [
+            (1, "2025-03-03 09:00:00"), // Monday
+            (2, "2025-03-09 23:00:00"), // Sunday of the same week
+            (3, "2025-03-10 08:00:00"), // The next Monday
+        ] {
+            update_applied(&connection, USER, true, id).unwrap();
+            connection
+                .execute(
+                    "UPDATE jobs SET applied_at = ?1 WHERE id = ?2",
+                    rusqlite::params![applied_at, id],
+                )
+                .unwrap();
+        }
+
+        let weeks = applications_per_week(&connection, USER, 10).expect("Failed to count weeks");
+        assert_eq!(
+            weeks,
+            vec![("2025-03-03".to_string(), 2), ("2025-03-10".to_string(), 1)]
+        );
+        assert_eq!(
+            applications_per_week(&connection, USER, 1).unwrap().len(),
+            1
+        );
+
+        update_applied(&connection, USER, false, 3).unwrap();
+        assert_eq!(
+            applications_per_week(&connection, USER, 10).unwrap().len(),
+            1,
+            "Unapplying clears the application time."
+        );
+    }
+
+    // Companies are ranked by their number of jobs:
+    #[test]
+    fn test_top_employers() {
+        let connection = setup_database();
+        insert_stats_job(&connection, 20.0, "1", Some("TriMet"));
+        insert_stats_job(&connection, 20.0, "0", Some("TriMet"));
+        insert_stats_job(&connection, 20.0, "1", Some("Papa Johns"));
+        insert_stats_job(&connection, 20.0, "1", None);
+
+        let employers = top_employers(&connection, USER, 5).expect("Failed to rank employers");
+        assert_eq!(
+            employers,
+            vec![
+                ("TriMet".to_string(), 2, 1),
+                ("Papa Johns".to_string(), 1, 1)
+            ]
+        );
+    }
+
+    // Stages are kept in the status history and the latest is on the job:
+    #[test]
+    fn test_set_stage() {
+        let connection = setup_database();
+        insert_stats_job(&connection, 20.0, "0", None);
+        assert_eq!(get_jobs(&connection, USER).unwrap()[0].get_stage(), None);
+
+        assert!(set_stage(&connection, USER, 1, Stage::Screen).unwrap());
+        assert!(!set_stage(&connection, USER, 99, Stage::Screen).unwrap());
+
+        let jobs = get_jobs(&connection, USER).unwrap();
+        assert_eq!(jobs[0].get_stage(), Some(Stage::Screen));
+        assert_eq!(
+            jobs[0].get_applied(),
+            "Yes",
+            "A screen means it was applied to."
+        );
+
+        let history = stage_history(&connection, USER).expect("Failed to read history");
+        let stages: Vec<Stage> = history.iter().map(|(_, stage, _)| *stage).collect();
+        assert_eq!(stages.len(), 2);
+        assert!(stages.contains(&Stage::Applied) && stages.contains(&Stage::Screen));
+
+        // Unapplying leaves the job out of the history, without losing its dates:
+        update_applied(&connection, USER, false, 1).unwrap();
+        assert!(stage_history(&connection, USER).unwrap().is_empty());
+        assert_eq!(get_jobs(&connection, USER).unwrap()[0].get_stage(), None);
+        let kept: i64 = connection
+            .query_row("SELECT COUNT(*) FROM status_history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(kept, 2);
+
+        update_applied(&connection, USER, true, 1).unwrap();
+        assert_eq!(stage_history(&connection, USER).unwrap().len(), 2);
+        assert_eq!(
+            get_jobs(&connection, USER).unwrap()[0].get_stage(),
+            Some(Stage::Screen)
+        );
+    }
+
+    // Goals are kept in order with the week they were set, and must be positive:
+    #[test]
+    fn test_goals() {
+        let connection = setup_database();
+        assert!(get_goals(&connection, USER).unwrap().is_empty());
+
+        set_goal(&connection, USER, 5).expect("Failed to set goal");
+        set_goal(&connection, USER, 3).expect("Failed to set goal");
+        assert!(
+            set_goal(&connection, USER, 0).is_err(),
+            "A goal must be more than 0."
+        );
+
+        let goals = get_goals(&connection, USER).unwrap();
+        let targets: Vec<i64> = goals.iter().map(|(_, target)| *target).collect();
+        assert_eq!(targets, vec![5, 3]);
+        let monday = chrono::NaiveDate::parse_from_str(&goals[0].0, "%Y-%m-%d").unwrap();
+        assert_eq!(monday.weekday(), chrono::Weekday::Mon);
+    }
+
+    // The database keeps when each job was added, last changed and first applied to:
+    #[test]
+    fn test_timestamps() {
+        let connection = setup_database();
+        insert_stats_job(&connection, 20.0, "0", None);
+
+        let job = &get_jobs(&connection, USER).unwrap()[0];
+        assert!(job.get_created_at().is_some());
+        assert_eq!(job.get_updated_at(), job.get_created_at());
+        assert_eq!(job.get_applied_at(), None);
+
+        // Pretend the job was added a day ago, to see the times change:
+        connection
+            .execute(
+                "UPDATE jobs SET created_at = datetime('now', '-1 day'),
+                    updated_at = datetime('now', '-1 day')",
+                [],
+            )
+            .unwrap();
+        update_applied(&connection, USER, true, 1).unwrap();
+
+        let job = &get_jobs(&connection, USER).unwrap()[0];
+        assert!(job.get_applied_at().is_some());
+        assert!(job.get_updated_at() > job.get_created_at());
+
+        update_applied(&connection, USER, false, 1).unwrap();
+        assert_eq!(
+            get_jobs(&connection, USER).unwrap()[0].get_applied_at(),
+            None
+        );
+    }
+
+    // The search index follows the jobs and the notes of their events:
+    #[test]
+    fn test_search_jobs() {
+        let connection = setup_database();
+        insert_stats_job(&connection, 20.0, "0", Some("TriMet"));
+        insert_stats_job(&connection, 30.0, "0", Some("Intel"));
+        let search = |query: &str| -> Vec<i64> {
+            search_jobs(&connection, USER, query, "[", "]", 10)
+                .expect("Failed to search")
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect()
+        };
+
+        assert_eq!(search("trimet"), vec![1]);
+        assert_eq!(search("job"), vec![1, 2], "Both are titled Job.");
+
+        let event = JobEvent::parse(
+            2,
+            "interview",
+            "2025-03-14",
+            None,
+            Some("Bring the portfolio"),
+        )
+        .unwrap();
+        add_event(&connection, USER, &event).unwrap();
+        assert_eq!(search("portfolio"), vec![2]);
+        let (_, snippet) = &search_jobs(&connection, USER, "portfolio", "[", "]", 10).unwrap()[0];
+        assert!(snippet.contains("[portfolio]"));
+
+        // Merging moves the notes, and removing a job removes it from the index:
+        merge_jobs(&connection, USER, 1, &[2]).unwrap();
+        assert_eq!(search("portfolio"), vec![1]);
+        remove_data(&connection, USER, 1).unwrap();
+        assert!(search("portfolio").is_empty());
+
+        // Changing the title changes the index:
+        insert_stats_job(&connection, 40.0, "0", None);
+        connection
+            .execute("UPDATE jobs SET job_title = 'Welder' WHERE id = 3", [])
+            .unwrap();
+        assert_eq!(search("welder"), vec![3]);
+        assert!(search_jobs(&connection, USER, "bad \"syntax", "", "", 10).is_err());
+    }
+
+    // An index created on an older database is filled with its jobs:
+    #[test]
+    fn test_search_index_of_old_database() {
+        let connection = setup_database();
+        insert_stats_job(&connection, 20.0, "0", Some("TriMet"));
+        connection
+            .execute_batch("DROP TABLE jobs_fts; DROP TRIGGER jobs_fts_insert;")
+            .unwrap();
+
+        create_table(&connection).expect("Failed to create the index again");
+        assert_eq!(
+            search_jobs(&connection, USER, "trimet", "", "", 10)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // An index without the description column is made again with it:
+        connection
+            .execute_batch(
+                "UPDATE jobs SET description = 'Drive the MAX train';
+                DROP TABLE jobs_fts;
+                CREATE VIRTUAL TABLE jobs_fts USING fts5(title, company, notes);",
+            )
+            .unwrap();
+        create_table(&connection).expect("Failed to update the index");
+        assert_eq!(
+            search_jobs(&connection, USER, "max", "", "", 10)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            search_jobs(&connection, USER, "trimet", "", "", 10)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    // Get one job by id, with its description:
+    #[test]
+    fn test_get_job() {
+        let connection = setup_database();
+        let job = Job::new(
+            None,
+            "Engineer".to_string(),
+            40.0,
+            "0".to_string(),
+            Some("https://example.com/jobs/1".to_string()),
+        )
+        .with_description(Some("Build bridges.\nMust love rivers.".to_string()));
+        insert_job(&connection, &job);
+
+        let found = get_job(&connection, USER, 1)
+            .unwrap()
+            .expect("The job exists");
+        assert_eq!(found.get_title(), "Engineer");
+        assert_eq!(
+            found.get_description().as_deref(),
+            Some("Build bridges.\nMust love rivers.")
+        );
+        assert!(get_job(&connection, USER, 2).unwrap().is_none());
+
+        // An edit without a description keeps the old one:
+        let edit = Job::new(
+            None,
+            "Lead Engineer".to_string(),
+            45.0,
+            "0".to_string(),
+            Some("https://example.com/jobs/1".to_string()),
+        );
+        assert!(update_job(&connection, USER, 1, &edit).unwrap());
+        let found = get_job(&connection, USER, 1).unwrap().unwrap();
+        assert_eq!(found.get_title(), "Lead Engineer");
+        assert!(found.get_description().is_some());
+    }
+
+    // Archives are replaced, follow a merge, and are removed with their job:
+    #[test]
+    fn test_archives() {
+        let connection = setup_database();
+        for title in ["Engineer", "Engineer (copy)", "Welder"] {
+            let job = Job::new(
+                None,
+                title.to_string(),
+                40.0,
+                "0".to_string(),
+                Some("https://example.com".to_string()),
+            );
+            insert_job(&connection, &job);
+        }
+
+        assert!(save_archive(&connection, USER, 1, "<p>First</p>", "https://example.com").unwrap());
+        assert!(save_archive(&connection, USER, 1, "<p>Second</p>", "page.html").unwrap());
+        assert!(!save_archive(&connection, USER, 9, "<p>None</p>", "page.html").unwrap());
+        let archive = get_archive(&connection, USER, 1)
+            .unwrap()
+            .expect("Job 1 has an archive");
+        assert_eq!(
+            (archive.html.as_str(), archive.source.as_str()),
+            ("<p>Second</p>", "page.html")
+        );
+        assert_eq!(get_archive(&connection, USER, 3).unwrap(), None);
+
+        // The kept job's archive wins over the duplicate's:
+        save_archive(&connection, USER, 2, "<p>Copy</p>", "copy.html").unwrap();
+        merge_jobs(&connection, USER, 1, &[2]).unwrap();
+        assert_eq!(
+            get_archive(&connection, USER, 1).unwrap().unwrap().html,
+            "<p>Second</p>"
+        );
+        assert_eq!(get_archive(&connection, USER, 2).unwrap(), None);
+
+        // A duplicate's archive moves to a kept job without one:
+        merge_jobs(&connection, USER, 3, &[1]).unwrap();
+        assert_eq!(
+            get_archive(&connection, USER, 3).unwrap().unwrap().html,
+            "<p>Second</p>"
+        );
+
+        remove_data(&connection, USER, 3).unwrap();
+        assert_eq!(get_archive(&connection, USER, 3).unwrap(), None);
+    }
+
+    // A user never sees or changes the jobs of another user, even with their ids:
+    #[test]
+    fn test_users_own_their_jobs() {
+        let connection = setup_database();
+        let job = |title: &str| {
+            Job::new(
+                None,
+                title.to_string(),
+                30.0,
+                "0".to_string(),
+                Some("https://example.com/rust".to_string()),
+            )
+        };
+
+        // Jobs from before there were users go to the first user added:
+        let old = enter_data(&connection, USER, &job("Old Job")).unwrap();
+        connection
+            .execute("UPDATE jobs SET user_id = NULL", [])
+            .unwrap();
+        set_goal(&connection, USER, 3).unwrap();
+        connection
+            .execute("UPDATE goals SET user_id = NULL", [])
+            .unwrap();
+        let alice = add_user(&connection, "alice", "hash").unwrap().unwrap();
+        let bob = add_user(&connection, "bob", "hash").unwrap().unwrap();
+        assert_eq!(get_jobs(&connection, alice).unwrap().len(), 1);
+        assert_eq!(get_goals(&connection, alice).unwrap().len(), 1);
+        assert!(get_jobs(&connection, bob).unwrap().is_empty());
+        assert!(get_goals(&connection, bob).unwrap().is_empty());
+
+        let mine = enter_data(&connection, alice, &job("Rust Developer")).unwrap();
+        let theirs = enter_data(&connection, bob, &job("Rust Developer")).unwrap();
+        let event = JobEvent {
+            id: None,
+            job_id: mine,
+            kind: EventKind::Interview,
+            date: "2025-04-01".parse().unwrap(),
+            time: None,
+            note: Some("Rust questions".to_string()),
+        };
+        let event_id = add_event(&connection, alice, &event).unwrap().unwrap();
+        save_archive(&connection, alice, mine, "<p>Posting</p>", "posting.html").unwrap();
+
+        // Bob can't read Alice's job, its dates, archive or search matches:
+        assert!(!owns_job(&connection, bob, mine).unwrap());
+        assert!(get_job(&connection, bob, mine).unwrap().is_none());
+        assert!(get_events(&connection, bob, Some(mine)).unwrap().is_empty());
+        assert!(get_events(&connection, bob, None).unwrap().is_empty());
+        assert_eq!(get_archive(&connection, bob, mine).unwrap(), None);
+        let found = search_jobs(&connection, bob, "rust*", "[", "]", 10).unwrap();
+        assert_eq!(
+            found.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![theirs]
+        );
+        assert_eq!(count_by_status(&connection, bob).unwrap(), (0, 1));
+
+        // ...nor change or remove any of it:
+        assert!(!update_applied(&connection, bob, true, mine).unwrap());
+        assert!(!set_stage(&connection, bob, mine, Stage::Offer).unwrap());
+        assert!(!update_job(&connection, bob, mine, &job("Taken")).unwrap());
+        assert!(!set_link_status(&connection, bob, mine, 404, NaiveDateTime::default()).unwrap());
+        assert!(!save_archive(&connection, bob, mine, "<p>Fake</p>", "fake.html").unwrap());
+        assert_eq!(
+            add_event(
+                &connection,
+                bob,
+                &JobEvent {
+                    job_id: mine,
+                    ..event.clone()
+                }
+            )
+            .unwrap(),
+            None
+        );
+        assert!(!remove_event(&connection, bob, event_id).unwrap());
+        assert_eq!(merge_jobs(&connection, bob, theirs, &[mine]).unwrap(), 0);
+        assert!(merge_jobs(&connection, bob, mine, &[theirs]).is_err());
+        assert!(!remove_data(&connection, bob, mine).unwrap());
+
+        let kept = get_job(&connection, alice, mine).unwrap().unwrap();
+        assert_eq!(kept.get_title(), "Rust Developer");
+        assert_eq!(kept.get_applied(), "No");
+        assert_eq!(kept.get_link_status(), None);
+        assert_eq!(get_events(&connection, alice, Some(mine)).unwrap().len(), 1);
+        assert_eq!(
+            get_archive(&connection, alice, mine).unwrap().unwrap().html,
+            "<p>Posting</p>"
+        );
+
+        // Removing a user removes their jobs, and only theirs:
+        assert!(remove_user(&connection, "alice").unwrap());
+        assert!(get_job(&connection, alice, old).unwrap().is_none());
+        assert!(get_events(&connection, alice, None).unwrap().is_empty());
+        assert_eq!(get_jobs(&connection, bob).unwrap().len(), 1);
+    }
+
+    // API tokens are kept as hashes, and only their user can see or revoke them:
+    #[test]
+    fn test_api_tokens() {
+        let connection = setup_database();
+        let alice = add_user(&connection, "alice", "hash").unwrap().unwrap();
+        let bob = add_user(&connection, "bob", "hash").unwrap().unwrap();
+        let made = NaiveDateTime::parse_from_str("2025-03-12 10:00", "%Y-%m-%d %H:%M").unwrap();
+        let used = NaiveDateTime::parse_from_str("2025-03-13 08:30", "%Y-%m-%d %H:%M").unwrap();
+        let id = add_api_token(
+            &connection,
+            alice,
+            "backup script",
+            "abc123",
+            TokenScope::Write,
+            made,
+        )
+        .unwrap();
+        add_api_token(&connection, bob, "stats", "def456", TokenScope::Read, made).unwrap();
+
+        let tokens = get_api_tokens(&connection, alice).unwrap();
+        assert_eq!(
+            tokens,
+            vec![ApiToken {
+                id,
+                name: "backup script".to_string(),
+                scope: TokenScope::Write,
+                created_at: made,
+                last_used_at: None,
+            }]
+        );
+
+        let (user, scope) = get_token_user(&connection, "abc123", used)
+            .unwrap()
+            .unwrap();
+        assert_eq!((user.id, scope), (alice, TokenScope::Write));
+        assert_eq!(get_token_user(&connection, "abc", used).unwrap(), None);
+        assert_eq!(
+            get_api_tokens(&connection, alice).unwrap()[0].last_used_at,
+            Some(used)
+        );
+
+        // Bob can't revoke Alice's token:
+        assert!(!remove_api_token(&connection, bob, id).unwrap());
+        assert!(remove_api_token(&connection, alice, id).unwrap());
+        assert_eq!(get_token_user(&connection, "abc123", used).unwrap(), None);
+
+        // Removing a user removes their tokens:
+        assert!(remove_user(&connection, "bob").unwrap());
+        assert_eq!(get_token_user(&connection, "def456", used).unwrap(), None);
+    }
 }
@@ -0,0 +1,339 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Duplicates Module
+//!
+//! Finds jobs that are the same posting. Two jobs are duplicates when
+//! their links point to the same posting once normalized (case, `www.`,
+//! tracking parameters and trailing slashes are ignored), or when they
+//! are at the same company and their titles are nearly the same.
+
+use crate::database_methods::{get_jobs, DatabaseError};
+use crate::job::Job;
+use serde::Serialize;
+use std::fmt;
+
+/// How similar two normalized titles must be (0.0 to 1.0) to match.
+pub const TITLE_THRESHOLD: f64 = 0.85;
+
+/// Why two jobs are considered duplicates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchReason {
+    Link,         // The links point to the same posting.
+    TitleCompany, // Same company and nearly the same title.
+}
+
+impl fmt::Display for MatchReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatchReason::Link => write!(f, "same link"),
+            MatchReason::TitleCompany => write!(f, "same title and company"),
+        }
+    }
+}
+
+/// An existing job that matches a new one.
+#[derive(Clone, Debug, Serialize)]
+pub struct Duplicate {
+    pub id: i64,
+    pub title: String,
+    pub company: Option<String>,
+    pub reason: MatchReason,
+}
+
+/// Normalize a job link so that links to the same posting compare equal.
+///
+/// The scheme, `www.`, fragment, tracking parameters (`utm_*`, `gclid`,
+/// `fbclid`) and trailing slashes are removed, the host is lowercased and
+/// the remaining query parameters are sorted.
+///
+/// # Returns
+/// * `None` if there is no link (empty or "No Link").
+pub fn normalize_link(link: &str) -> Option<String> {
+    let link = link.trim();
+    if link.is_empty() || link.eq_ignore_ascii_case("No Link") {
+        return None;
+    }
+
+    // Drop the fragment and the scheme:
+    let link = link.split('#').next().unwrap_or_default();
+    let link = link.split_once("://").map_or(link, |(_scheme, rest)| rest);
+
+    let (address, query) = link.split_once('?').unwrap_or((link, ""));
+    let (host, path) = address.split_once('/').unwrap_or((address, ""));
+    let host = host.to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    let path = path.trim_end_matches('/');
+
+    let mut params: Vec<&str> = query
+        .split('&')
+        .filter(|param| {
+            let name = param.split('=').next().unwrap_or_default().to_lowercase();
+            !(name.is_empty() || name.starts_with("utm_") || name == "gclid" || name == "fbclid")
+        })
+        .collect();
+    params.sort_unstable();
+
+    let mut normalized = format!("{}/{}", host, path);
+    if !params.is_empty() {
+        normalized.push('?');
+        normalized.push_str(&params.join("&"));
+    }
+    Some(normalized)
+}
+
+/// Normalize text for comparing: lowercase words with punctuation removed.
+pub fn normalize_text(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Normalize a company name, also dropping suffixes like "Inc." and "LLC".
+fn normalize_company(company: &str) -> String {
+    const SUFFIXES: [&str; 7] = ["inc", "llc", "ltd", "co", "corp", "corporation", "company"];
+    normalize_text(company)
+        .split(' ')
+        .filter(|word| !SUFFIXES.contains(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The similarity of two strings from 0.0 (different) to 1.0 (equal),
+/// based on their Levenshtein edit distance.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    // One row of the edit distance table at a time:
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+/// Check if two jobs are the same posting.
+///
+/// # Returns
+/// * `Some(MatchReason)` if they are duplicates, `None` otherwise.
+pub fn is_duplicate(a: &Job, b: &Job) -> Option<MatchReason> {
+    // A bare domain is a careers home page shared by many jobs, not a posting:
+    let posting = |job: &Job| {
+        job.get_optional_link()
+            .and_then(|l| normalize_link(&l))
+            .filter(|l| !l.ends_with('/'))
+    };
+    let link_a = posting(a);
+    if link_a.is_some() && link_a == posting(b) {
+        return Some(MatchReason::Link);
+    }
+
+    let (company_a, company_b) = match (a.get_company(), b.get_company()) {
+        (Some(ca), Some(cb)) => (normalize_company(&ca), normalize_company(&cb)),
+        _ => return None, // Titles alone are too common to match on.
+    };
+    if company_a.is_empty() || company_a != company_b {
+        return None;
+    }
+
+    let title_a = normalize_text(&a.get_title());
+    let title_b = normalize_text(&b.get_title());
+    if similarity(&title_a, &title_b) >= TITLE_THRESHOLD {
+        Some(MatchReason::TitleCompany)
+    } else {
+        None
+    }
+}
+
+/// Find the jobs of a user that are duplicates of `job`.
+///
+/// A job with an id is never reported as a duplicate of itself.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user whose jobs are searched.
+/// * `job` - The job to look for.
+///
+/// # Returns
+/// * `Ok(Vec<Duplicate>)` with every matching job.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn find_duplicates(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    job: &Job,
+) -> Result<Vec<Duplicate>, DatabaseError> {
+    let duplicates = get_jobs(connection, user_id)?
+        .into_iter()
+        .filter(|existing| existing.get_id() != job.get_id() || job.get_id().is_none())
+        .filter_map(|existing| {
+            is_duplicate(job, &existing).map(|reason| Duplicate {
+                id: existing.get_id().unwrap_or_default(),
+                title: existing.get_title(),
+                company: existing.get_company(),
+                reason,
+            })
+        })
+        .collect();
+    Ok(duplicates)
+}
+
+/// Group all the jobs of a user that are duplicates of each other.
+///
+/// # Returns
+/// * `Ok(Vec<Vec<Job>>)` with one group (ordered by id) per posting found more than once.
+/// * `Err(DatabaseError)` if an error occurs.
+pub fn duplicate_groups(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+) -> Result<Vec<Vec<Job>>, DatabaseError> {
+    let jobs = get_jobs(connection, user_id)?;
+
+    // Union-find over the job indexes, joining every duplicate pair:
+    let mut parent: Vec<usize> = (0..jobs.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..jobs.len() {
+        for j in (i + 1)..jobs.len() {
+            if is_duplicate(&jobs[i], &jobs[j]).is_some() {
+                let (ri, rj) = (root(&mut parent, i), root(&mut parent, j));
+                parent[ri.max(rj)] = ri.min(rj);
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<Job>> = vec![Vec::new(); jobs.len()];
+    for (i, job) in jobs.into_iter().enumerate() {
+        let r = root(&mut parent, i);
+        groups[r].push(job);
+    }
+    let mut groups: Vec<Vec<Job>> = groups.into_iter().filter(|g| g.len() > 1).collect();
+    for group in &mut groups {
+        group.sort_by_key(|job| job.get_id());
+    }
+    Ok(groups)
+}
+
+/// Testing the duplicate detection.
+/// The normalizing and matching functions are tested on their own,
+/// then the database functions are tested on an in-memory database.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database_methods::{create_table, enter_data};
+    use rusqlite::Connection;
+
+    // The user the test jobs belong to:
+    const USER: i64 = 1;
+
+    // Helper function to make a job with a link and company:
+    fn job(title: &str, link: &str, company: Option<&str>) -> Job {
+        Job::new(
+            None,
+            title.to_string(),
+            20.0,
+            "0".to_string(),
+            Some(link.to_string()),
+        )
+        .with_company(company.map(str::to_string))
+    }
+
+    #[test]
+    fn test_normalize_link() {
+        assert_eq!(
+            normalize_link("https://WWW.Example.com/jobs/42/?utm_source=google&b=2&a=1#apply"),
+            Some("example.com/jobs/42?a=1&b=2".to_string())
+        );
+        assert_eq!(
+            normalize_link("http://example.com/jobs/42"),
+            normalize_link("https://www.example.com/jobs/42/")
+        );
+        assert_eq!(
+            normalize_link("https://example.com"),
+            Some("example.com/".to_string())
+        );
+        assert_eq!(normalize_link("No Link"), None);
+        assert_eq!(normalize_link("  "), None);
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("barista", "barista"), 1.0);
+        assert!(similarity("coffee barista", "coffee baristas") >= TITLE_THRESHOLD);
+        assert!(similarity("bus driver", "train driver") < TITLE_THRESHOLD);
+    }
+
+    #[test]
+    fn test_is_duplicate() {
+        let a = job("Barista", "https://example.com/1", Some("Black Rock, Inc."));
+        let same_link = job("Coffee Maker", "http://www.example.com/1/", None);
+        let same_company = job("barista!", "https://other.com/2", Some("black rock"));
+        let other_company = job("Barista", "https://other.com/3", Some("Starbucks"));
+        let no_company = job("Barista", "https://other.com/4", None);
+        let home_page = job("Barista", "https://other.com", None);
+
+        assert_eq!(is_duplicate(&a, &same_link), Some(MatchReason::Link));
+        assert_eq!(
+            is_duplicate(&a, &same_company),
+            Some(MatchReason::TitleCompany)
+        );
+        assert_eq!(is_duplicate(&a, &other_company), None);
+        assert_eq!(is_duplicate(&a, &no_company), None);
+        assert_eq!(is_duplicate(&home_page, &home_page), None);
+    }
+
+    #[test]
+    fn test_find_duplicates_and_groups() {
+        let connection = Connection::open_in_memory().expect("Failed to open in-memory database");
+        create_table(&connection).expect("Failed to create table");
+        enter_data(
+            &connection,
+            USER,
+            &job("Barista", "https://example.com/1", None),
+        )
+        .unwrap();
+        enter_data(
+            &connection,
+            USER,
+            &job("Barista", "https://example.com/1?utm_x=1", None),
+        )
+        .unwrap();
+        enter_data(
+            &connection,
+            USER,
+            &job("Bus Driver", "https://example.com/2", None),
+        )
+        .unwrap();
+
+        let new_job = job("Barista", "http://example.com/1", None);
+        let found =
+            find_duplicates(&connection, USER, &new_job).expect("Failed to find duplicates");
+        let ids: Vec<i64> = found.iter().map(|d| d.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+
+        let groups = duplicate_groups(&connection, USER).expect("Failed to group duplicates");
+        assert_eq!(groups.len(), 1, "Only the two baristas are duplicates.");
+        assert_eq!(groups[0].len(), 2);
+    }
+}
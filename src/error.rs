@@ -0,0 +1,214 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Error Module
+//!
+//! The errors of the server's handlers. A handler returns `Result<_, AppError>`
+//! and uses `?` on the database methods, and actix turns the error into a
+//! response: an HTML error page for the pages of the site, or JSON like
+//! `{"success": false, "error": "..."}` when the handler returns `ApiError`
+//! (the routes used by JavaScript and API tokens).
+//!
+//! Database and template errors are logged with their details, the user only
+//! sees what went wrong, not the SQL.
+
+use crate::archive::{ArchiveError, FetchError};
+use crate::auth::AuthError;
+use crate::database_methods::DatabaseError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use log::error;
+use std::fmt;
+
+/// Why a page could not be shown or a change could not be made.
+#[derive(Debug)]
+pub enum AppError {
+    Database(DatabaseError), // The database could not be read or written.
+    Template(tera::Error),   // A page could not be rendered.
+    NotFound(String),        // No such job (or token, or chart) for this user.
+    BadRequest(String),      // The request can't be done as sent, like a bad form.
+    BadGateway(String),      // Another site did not answer, like a posting page being fetched.
+    Internal(String),        // Anything else that went wrong on the server.
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::Database(DatabaseError::JobNotFound(id)) => {
+                write!(f, "No job with id {}.", id)
+            }
+            AppError::Database(_) => write!(f, "Error reading or writing the database."),
+            AppError::Template(_) => write!(f, "Error rendering the page."),
+            AppError::Internal(_) => write!(f, "Something went wrong on the server."),
+            AppError::NotFound(message)
+            | AppError::BadRequest(message)
+            | AppError::BadGateway(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl AppError {
+    // Log the details the user doesn't see.
+    fn log(&self) {
+        match self {
+            AppError::Database(DatabaseError::JobNotFound(_)) => {}
+            AppError::Database(err) => error!("{}", err),
+            AppError::Template(err) => error!("Template rendering error: {:?}", err),
+            AppError::Internal(err) => error!("Server error: {}", err),
+            _ => {}
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Database(DatabaseError::JobNotFound(_)) | AppError::NotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            AppError::Database(_) | AppError::Template(_) | AppError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// The error page, with the message and a link back to the job list.
+    fn error_response(&self) -> HttpResponse {
+        self.log();
+        let status = self.status_code();
+        let mut context = tera::Context::new();
+        context.insert("status", &status.as_u16());
+        context.insert("reason", status.canonical_reason().unwrap_or("Error"));
+        context.insert("message", &self.to_string());
+        match tera::Tera::one_off(include_str!("../templates/error.html"), &context, true) {
+            Ok(page) => HttpResponse::build(status)
+                .content_type("text/html")
+                .body(page),
+            Err(err) => {
+                error!("Template rendering error: {:?}", err);
+                HttpResponse::build(status).body(self.to_string())
+            }
+        }
+    }
+}
+
+impl From<DatabaseError> for AppError {
+    fn from(err: DatabaseError) -> Self {
+        AppError::Database(err)
+    }
+}
+
+impl From<tera::Error> for AppError {
+    fn from(err: tera::Error) -> Self {
+        AppError::Template(err)
+    }
+}
+
+impl From<ArchiveError> for AppError {
+    fn from(err: ArchiveError) -> Self {
+        match err {
+            ArchiveError::NoJob(_) => AppError::NotFound(err.to_string()),
+            ArchiveError::NoLink(_) => AppError::BadRequest(err.to_string()),
+            ArchiveError::Fetch(FetchError::Private(_)) => AppError::BadRequest(err.to_string()),
+            ArchiveError::Fetch(_) => AppError::BadGateway(err.to_string()),
+            ArchiveError::Database(err) => AppError::Database(err),
+        }
+    }
+}
+
+impl From<AuthError> for AppError {
+    fn from(err: AuthError) -> Self {
+        match err {
+            AuthError::Database(err) => AppError::Database(err),
+            AuthError::Hash(_) => AppError::Internal(err.to_string()),
+            _ => AppError::BadRequest(err.to_string()),
+        }
+    }
+}
+
+// A worker thread (`web::block`) that stopped before it was done:
+impl From<actix_web::error::BlockingError> for AppError {
+    fn from(err: actix_web::error::BlockingError) -> Self {
+        AppError::Internal(err.to_string())
+    }
+}
+
+/// An `AppError` of a JSON route, answered as JSON instead of a page.
+#[derive(Debug)]
+pub struct ApiError(pub AppError);
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.0.status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.0.log();
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "success": false,
+            "error": self.0.to_string(),
+        }))
+    }
+}
+
+impl From<AppError> for ApiError {
+    fn from(err: AppError) -> Self {
+        ApiError(err)
+    }
+}
+
+impl From<DatabaseError> for ApiError {
+    fn from(err: DatabaseError) -> Self {
+        ApiError(AppError::Database(err))
+    }
+}
+
+/// Testing the responses of the errors.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    // Pages get an HTML error page, with the details of database errors kept out of it:
+    #[actix_web::test]
+    async fn test_error_page() {
+        let err = AppError::Database(DatabaseError::BadValue("hourly_rate".to_string()));
+        let response = err.error_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let page = to_bytes(response.into_body()).await.unwrap();
+        let page = String::from_utf8_lossy(&page);
+        assert!(page.contains("Error reading or writing the database."));
+        assert!(!page.contains("hourly_rate"));
+
+        let err = AppError::from(DatabaseError::JobNotFound(7));
+        assert_eq!(err.status_code(), StatusCode::NOT_FOUND);
+        let page = to_bytes(err.error_response().into_body()).await.unwrap();
+        assert!(String::from_utf8_lossy(&page).contains("No job with id 7."));
+
+        // Messages are escaped like the rest of the pages:
+        let err = AppError::BadRequest("<script>".to_string());
+        let page = to_bytes(err.error_response().into_body()).await.unwrap();
+        assert!(String::from_utf8_lossy(&page).contains("&lt;script&gt;"));
+    }
+
+    // JSON routes get JSON errors:
+    #[actix_web::test]
+    async fn test_api_error() {
+        let err = ApiError::from(DatabaseError::JobNotFound(7));
+        let response = err.error_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["success"], false);
+        assert_eq!(json["error"], "No job with id 7.");
+    }
+}
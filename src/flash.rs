@@ -0,0 +1,182 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Flash Module
+//!
+//! Flash messages: a change made by a form ends with a redirect, so the page
+//! shown after it can't know what happened. The handler leaves a message in a
+//! short-lived cookie ("Removed job 3.", "No job with id 7."), the next page
+//! shows it once as a banner and removes the cookie.
+//!
+//! The cookie is signed with a hash of the session token, like the CSRF token,
+//! so only this server can leave a message for a user's session.
+
+use crate::auth::{token_hash, SESSION_COOKIE};
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{FromRequest, HttpRequest, HttpResponse};
+use serde::Serialize;
+use std::future::{ready, Ready};
+
+/// The name of the cookie that holds the flash message.
+pub const FLASH_COOKIE: &str = "flash";
+
+/// Whether a flash message tells of a change that was made, or one that failed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlashKind {
+    Success,
+    Error,
+}
+
+impl FlashKind {
+    /// The name of the kind, as kept in the cookie and used as a class in the page.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FlashKind::Success => "success",
+            FlashKind::Error => "error",
+        }
+    }
+
+    /// The kind with this name, if there is one.
+    pub fn from_name(name: &str) -> Option<FlashKind> {
+        match name {
+            "success" => Some(FlashKind::Success),
+            "error" => Some(FlashKind::Error),
+            _ => None,
+        }
+    }
+}
+
+/// A message left for the next page.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FlashMessage {
+    pub kind: FlashKind,
+    pub text: String,
+}
+
+/// The flash message of a request, and the session it belongs to.
+///
+/// Handlers that redirect leave a message with `redirect`. The page shown after
+/// it gets the message with `message`, and calls `clear` so it is only shown once.
+/// A message that wasn't signed for the session is ignored.
+pub struct Flash {
+    session: Option<String>,
+    message: Option<FlashMessage>,
+}
+
+impl FromRequest for Flash {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let session = request
+            .cookie(SESSION_COOKIE)
+            .map(|cookie| cookie.value().to_string());
+        let message = match (&session, request.cookie(FLASH_COOKIE)) {
+            (Some(session), Some(cookie)) => read_flash(session, cookie.value()),
+            _ => None,
+        };
+        ready(Ok(Flash { session, message }))
+    }
+}
+
+impl Flash {
+    /// The message left by the last change, if there is one.
+    pub fn message(&self) -> Option<&FlashMessage> {
+        self.message.as_ref()
+    }
+
+    /// Redirect to `location`, leaving a message for the page there.
+    ///
+    /// Without a session there is no page to show it on, so only the redirect is sent.
+    pub fn redirect(&self, location: &str, kind: FlashKind, text: &str) -> HttpResponse {
+        let mut response = HttpResponse::Found();
+        response.append_header((header::LOCATION, location));
+        if let Some(session) = &self.session {
+            let cookie = flash_cookie(write_flash(session, kind, text));
+            // Encoded, since the message can have spaces and semicolons:
+            response.append_header((header::SET_COOKIE, cookie.encoded().to_string()));
+        }
+        response.finish()
+    }
+
+    /// Remove the flash cookie once its message is in a page.
+    pub fn clear(&self, response: &mut HttpResponse) {
+        if self.message.is_some() {
+            let mut removal = flash_cookie(String::new());
+            removal.make_removal();
+            // The cookie is only removed if the header can be made, the message is shown anyway:
+            let _ = response.add_cookie(&removal);
+        }
+    }
+}
+
+// The cookie holding a flash message, for every page until it is shown.
+fn flash_cookie(value: String) -> Cookie<'static> {
+    Cookie::build(FLASH_COOKIE, value)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .finish()
+}
+
+// The value of a flash cookie: "<kind>:<signature>:<text>".
+fn write_flash(session: &str, kind: FlashKind, text: &str) -> String {
+    format!(
+        "{}:{}:{}",
+        kind.name(),
+        flash_signature(session, kind, text),
+        text
+    )
+}
+
+// The message of a flash cookie, if it was signed for this session.
+fn read_flash(session: &str, value: &str) -> Option<FlashMessage> {
+    let mut parts = value.splitn(3, ':');
+    let kind = FlashKind::from_name(parts.next()?)?;
+    let signature = parts.next()?;
+    let text = parts.next()?;
+    (signature == flash_signature(session, kind, text)).then(|| FlashMessage {
+        kind,
+        text: text.to_string(),
+    })
+}
+
+// The signature of a message: a hash of it with the session token, that only
+// the server (and the browser holding the HttpOnly session cookie) knows.
+fn flash_signature(session: &str, kind: FlashKind, text: &str) -> String {
+    token_hash(&format!("flash {} {} {}", session, kind.name(), text))
+}
+
+/// Testing the signing of flash messages.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION: &str = "session-token";
+
+    // A message reads back only with the session it was signed for, and unchanged:
+    #[test]
+    fn test_flash_signature() {
+        let value = write_flash(SESSION, FlashKind::Success, "Removed job 3: Welder; Acme.");
+        assert_eq!(
+            read_flash(SESSION, &value),
+            Some(FlashMessage {
+                kind: FlashKind::Success,
+                text: "Removed job 3: Welder; Acme.".to_string(),
+            })
+        );
+        assert_eq!(read_flash("other-session", &value), None);
+
+        // Changing the text or the kind breaks the signature:
+        let forged = value.replace("Removed", "Deleted");
+        assert_eq!(read_flash(SESSION, &forged), None);
+        let forged = value.replacen("success", "error", 1);
+        assert_eq!(read_flash(SESSION, &forged), None);
+        assert_eq!(read_flash(SESSION, "success::Hello"), None);
+        assert_eq!(read_flash(SESSION, ""), None);
+    }
+}
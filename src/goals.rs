@@ -0,0 +1,198 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Goals Module
+//!
+//! Compares the applications made each week (from the time each job was
+//! marked applied) with the weekly application goal, for the progress
+//! widget on the job list and the `goal status` command. Weeks start on
+//! Monday, and each week is compared with the goal set at the time.
+
+use crate::database_methods::{applications_per_week, get_goals, DatabaseError};
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::Serialize;
+
+/// The progress of this week towards the weekly goal, and the streaks of
+/// weeks that met their goal.
+///
+/// # Fields
+/// - `week`: The Monday of this week.
+/// - `target`: The current goal (applications per week).
+/// - `applications`: The applications made this week.
+/// - `remaining`: The applications left to meet the goal (0 once it is met).
+/// - `percent`: How much of the goal is done, up to 100.
+/// - `current_streak`: The weeks in a row that met their goal, up to this week.
+///   This week only counts once its goal is met, but doesn't break the streak before.
+/// - `best_streak`: The most weeks in a row that ever met their goal.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GoalProgress {
+    pub week: NaiveDate,
+    pub target: i64,
+    pub applications: i64,
+    pub remaining: i64,
+    pub percent: f64,
+    pub current_streak: i64,
+    pub best_streak: i64,
+}
+
+/// The Monday of the week of a date.
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+/// Compare the applications a user made each week with their weekly goal.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user whose goals and jobs are used.
+/// * `today` - The current date (UTC, like the application times).
+///
+/// # Returns
+/// * `Ok(Some(GoalProgress))` with the progress of this week.
+/// * `Ok(None)` if no goal was ever set.
+/// * `Err(DatabaseError)` if a query fails.
+pub fn goal_progress(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    today: NaiveDate,
+) -> Result<Option<GoalProgress>, DatabaseError> {
+    let parse = |rows: Vec<(String, i64)>| -> Vec<(NaiveDate, i64)> {
+        rows.into_iter()
+            .filter_map(|(week, count)| Some((week.parse().ok()?, count)))
+            .collect()
+    };
+    let goals = parse(get_goals(connection, user_id)?);
+    let weeks = parse(applications_per_week(connection, user_id, i64::MAX)?);
+    Ok(progress(&goals, &weeks, today))
+}
+
+/// Compute the progress from the goals and the applications of each week.
+///
+/// # Arguments
+/// * `goals` - The week each goal was set and its target, oldest first.
+/// * `weeks` - The Monday of each week with applications and its count.
+/// * `today` - The current date.
+///
+/// # Returns
+/// * The progress, or `None` without any goal.
+pub fn progress(
+    goals: &[(NaiveDate, i64)],
+    weeks: &[(NaiveDate, i64)],
+    today: NaiveDate,
+) -> Option<GoalProgress> {
+    let target = goals.last()?.1;
+    let this_week = week_start(today);
+
+    // The goal of a week is the last one set by then (the first goal for older weeks):
+    let goal_of = |week: NaiveDate| {
+        goals
+            .iter()
+            .rev()
+            .find(|(set, _)| *set <= week)
+            .unwrap_or(&goals[0])
+            .1
+    };
+    let count_of = |week: NaiveDate| {
+        weeks
+            .iter()
+            .find(|(w, _)| *w == week)
+            .map_or(0, |(_, count)| *count)
+    };
+    let met = |week: NaiveDate| count_of(week) >= goal_of(week);
+
+    let applications = count_of(this_week);
+
+    // Count back from this week (if met) or last week, while each week met its goal:
+    let mut current_streak = 0;
+    let mut week = if met(this_week) {
+        this_week
+    } else {
+        this_week - Duration::weeks(1)
+    };
+    while met(week) {
+        current_streak += 1;
+        week -= Duration::weeks(1);
+    }
+
+    // The best streak, from the first week with applications to this week:
+    let mut best_streak = 0;
+    let mut streak = 0;
+    if let Some(first) = weeks.iter().map(|(week, _)| *week).min() {
+        let mut week = first;
+        while week <= this_week {
+            streak = if met(week) { streak + 1 } else { 0 };
+            best_streak = best_streak.max(streak);
+            week += Duration::weeks(1);
+        }
+    }
+
+    Some(GoalProgress {
+        week: this_week,
+        target,
+        applications,
+        remaining: (target - applications).max(0),
+        percent: (100.0 * applications as f64 / target as f64).min(100.0),
+        current_streak,
+        best_streak,
+    })
+}
+
+/// Testing the goal progress on hand-made weeks.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Make a date from a string, for short tests.
+    fn date(text: &str) -> NaiveDate {
+        text.parse().expect("Bad test date")
+    }
+
+    #[test]
+    fn test_week_start() {
+        assert_eq!(week_start(date("2025-03-12")), date("2025-03-10")); // A Wednesday.
+        assert_eq!(week_start(date("2025-03-10")), date("2025-03-10")); // A Monday.
+        assert_eq!(week_start(date("2025-03-16")), date("2025-03-10")); // A Sunday.
+    }
+
+    // Without a goal there is no progress:
+    #[test]
+    fn test_no_goal() {
+        assert_eq!(
+            progress(&[], &[(date("2025-03-10"), 4)], date("2025-03-12")),
+            None
+        );
+    }
+
+    // This week is in progress, so it doesn't break the streak of the weeks before:
+    #[test]
+    fn test_progress_and_streaks() {
+        let goals = [(date("2025-02-10"), 3)];
+        let weeks = [
+            (date("2025-02-10"), 3), // Met.
+            (date("2025-02-17"), 4), // Met.
+            (date("2025-02-24"), 5), // Met.
+            (date("2025-03-03"), 1), // Missed.
+            (date("2025-03-10"), 3), // Met.
+            (date("2025-03-17"), 2), // Met (after the goal changed to 2 below).
+            (date("2025-03-24"), 1), // This week, not met yet.
+        ];
+        let goals_changed = [goals[0], (date("2025-03-17"), 2)];
+
+        let p = progress(&goals_changed, &weeks, date("2025-03-26")).unwrap();
+        assert_eq!(p.week, date("2025-03-24"));
+        assert_eq!((p.target, p.applications, p.remaining), (2, 1, 1));
+        assert_eq!(p.percent, 50.0);
+        assert_eq!(p.current_streak, 2, "Mar 10 and Mar 17, not this week yet.");
+        assert_eq!(p.best_streak, 3, "Feb 10 to Feb 24.");
+
+        // With the old goal of 3, Mar 17 is missed:
+        let p = progress(&goals, &weeks, date("2025-03-26")).unwrap();
+        assert_eq!(p.current_streak, 0);
+
+        // Meeting the goal this week adds it to the streak:
+        let p = progress(&goals_changed, &weeks, date("2025-03-18")).unwrap();
+        assert_eq!((p.applications, p.remaining, p.percent), (2, 0, 100.0));
+        assert_eq!(p.current_streak, 2);
+    }
+}
@@ -7,6 +7,12 @@
 //! This is the public jobs module. It contains the
 //! Job struct and its implementations for a job. Each
 //! Job object is encapsulated by the application object.
+//!
+//! The dates for a job (deadlines, follow-up reminders and
+//! interviews) are `JobEvent` objects stored alongside the jobs, and
+//! the saved copy of a job's posting page is an `Archive`. The add and
+//! edit forms are read into a `JobForm`, which checks every field before
+//! it becomes a `Job`.
 
 /// Represting a Job in the application.
 ///
@@ -17,6 +23,17 @@
 /// - `hourly`: The hourly rate (pay) in floating point of the job.
 /// - `applied`: The number (1 or 0) for whether or not the job has been applied to.
 /// - `link`: The link to the job application.
+/// - `company`: The optional name of the employer.
+/// - `stage`: The latest stage of the application (see `Stage`), if any.
+/// - `created_at` / `updated_at`: When the job was added and last changed (UTC).
+/// - `applied_at`: When the job was first marked applied (UTC).
+/// - `description`: The text of the job posting, kept after the posting is gone.
+/// - `link_status`: The HTTP status of the link when it was last checked (0 if there was no answer).
+/// - `link_checked_at`: When the link was last checked (UTC).
+/// - `tags`: The user's labels for the job, like "remote", in order.
+/// - `archived_at`: When the job was archived (UTC): it is hidden from the job list.
+use actix_multipart::form::{bytes::Bytes, text::Text, MultipartForm}; // For the csv upload form.
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use serde::{Deserialize, Serialize}; // Serialize trait to pass a job into tera in main.
 
 // Clone trait to make copied of a Job object, and Deserialize/Serialize for tera.
@@ -27,6 +44,26 @@ pub struct Job {
     hourly: f32,
     applied: String,
     link: Option<String>, // link is an optional field.
+    #[serde(default)]
+    company: Option<String>, // company is optional, and missing from older forms.
+    #[serde(default)]
+    stage: Option<Stage>, // Read from the status history, never from a form.
+    #[serde(default)]
+    created_at: Option<NaiveDateTime>, // The timestamps are set by the database, never from a form.
+    #[serde(default)]
+    updated_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    applied_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    description: Option<String>, // The posting's text, can be long.
+    #[serde(default)]
+    link_status: Option<u16>, // The last check of the link (see the links module), never from a form.
+    #[serde(default)]
+    link_checked_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    tags: Vec<String>, // Added with a bulk action, never from a form.
+    #[serde(default)]
+    archived_at: Option<NaiveDateTime>,
 }
 
 // Struct with only the id. Its purpose is to
@@ -36,6 +73,153 @@ pub struct JobRemovalForm {
     pub id: i64,
 }
 
+/// The jobs picked with the checkboxes of the job list.
+///
+/// The form sends one `ids` field per checked job (`ids=1&ids=3`), which
+/// `web::Form` can't read into a list, so the body is parsed with `from_form`.
+#[derive(Debug, Default, PartialEq)]
+pub struct JobSelection {
+    pub ids: Vec<i64>,
+}
+
+impl JobSelection {
+    /// Read the picked job ids of a posted form (each once), skipping its other fields.
+    ///
+    /// # Arguments
+    /// * `body` - The url-encoded body of the form.
+    ///
+    /// # Returns
+    /// * `Ok(JobSelection)` with the ids in the order they were sent.
+    /// * `Err(String)` if an id is not a number.
+    pub fn from_form(body: &[u8]) -> Result<JobSelection, String> {
+        let mut ids = Vec::new();
+        for (name, value) in form_urlencoded::parse(body) {
+            if name == "ids" {
+                let id = value
+                    .parse()
+                    .map_err(|_| format!("\"{}\" is not a job id.", value))?;
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+        Ok(JobSelection { ids })
+    }
+}
+
+/// The longest tag, in characters.
+pub const MAX_TAG_LENGTH: usize = 30;
+
+/// A change made to every checked job at once, with the bulk form of the job
+/// list or the `bulk` command.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BulkAction {
+    NotApplied,      // Mark the jobs not applied, clearing their status history.
+    SetStage(Stage), // Move the jobs to a stage (which marks them applied).
+    AddTag(String),  // Add a tag to the jobs.
+    Archive,         // Hide the jobs from the job list.
+    Unarchive,       // Put archived jobs back in the job list.
+    Delete,          // Remove the jobs.
+}
+
+impl BulkAction {
+    /// Find an action by its name, with the stage or tag it needs.
+    ///
+    /// # Arguments
+    /// * `name` - One of `not-applied`, `stage`, `tag`, `archive`, `unarchive` or `delete`.
+    /// * `value` - The stage name for `stage`, the tag for `tag`.
+    ///
+    /// # Returns
+    /// * `Ok(BulkAction)` if the action and its value are good.
+    /// * `Err(String)` telling what is wrong with them.
+    pub fn parse(name: &str, value: Option<&str>) -> Result<BulkAction, String> {
+        let value = value.map(str::trim).filter(|v| !v.is_empty());
+        match name.trim().to_lowercase().replace('_', "-").as_str() {
+            "not-applied" => Ok(BulkAction::NotApplied),
+            "stage" => {
+                let value = value.ok_or("Pick the stage to move the jobs to.")?;
+                Stage::from_name(value)
+                    .map(BulkAction::SetStage)
+                    .ok_or_else(|| format!("\"{}\" is not a stage.", value))
+            }
+            "tag" => {
+                let tag = value.ok_or("Type the tag to add to the jobs.")?;
+                if tag.chars().count() > MAX_TAG_LENGTH {
+                    Err(format!(
+                        "A tag can't be longer than {} characters.",
+                        MAX_TAG_LENGTH
+                    ))
+                } else if tag.contains(',') {
+                    Err("A tag can't have a comma in it.".to_string())
+                } else {
+                    Ok(BulkAction::AddTag(tag.to_string()))
+                }
+            }
+            "archive" => Ok(BulkAction::Archive),
+            "unarchive" => Ok(BulkAction::Unarchive),
+            "delete" => Ok(BulkAction::Delete),
+            _ => Err(format!("\"{}\" is not a bulk action.", name)),
+        }
+    }
+
+    /// Read the action of the posted bulk form: its `action` field, which is
+    /// `stage:<name>` for a stage, and the `tag` field for `tag`.
+    ///
+    /// # Arguments
+    /// * `body` - The url-encoded body of the form.
+    ///
+    /// # Returns
+    /// * `Ok(BulkAction)` if the form has a good action.
+    /// * `Err(String)` telling what is wrong with it.
+    pub fn from_form(body: &[u8]) -> Result<BulkAction, String> {
+        let mut action = None;
+        let mut tag = None;
+        for (name, value) in form_urlencoded::parse(body) {
+            match name.as_ref() {
+                "action" => action = Some(value.into_owned()),
+                "tag" => tag = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+        let action = action
+            .filter(|a| !a.is_empty())
+            .ok_or("Pick what to do with the checked jobs.")?;
+        match action.split_once(':') {
+            Some((name, value)) => BulkAction::parse(name, Some(value)),
+            None => BulkAction::parse(&action, tag.as_deref()),
+        }
+    }
+
+    /// What was done to the jobs, as in "3 jobs moved to interview".
+    pub fn done(&self) -> String {
+        match self {
+            BulkAction::NotApplied => "marked not applied".to_string(),
+            BulkAction::SetStage(stage) => format!("moved to {}", stage.name()),
+            BulkAction::AddTag(tag) => format!("tagged \"{}\"", tag),
+            BulkAction::Archive => "archived".to_string(),
+            BulkAction::Unarchive => "put back in the job list".to_string(),
+            BulkAction::Delete => "removed".to_string(),
+        }
+    }
+}
+
+/// The add and edit forms of a job, as typed. Every field is text and may be
+/// missing, so any post can be checked with `validate` and sent back with
+/// an error next to each bad field.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobForm {
+    pub title: String,
+    pub company: String,
+    pub hourly: String,
+    pub applied: String,
+    pub link: String,
+    pub description: String,
+}
+
+/// The error message of each bad field of a form, by field name.
+pub type FieldErrors = std::collections::BTreeMap<&'static str, String>;
+
 // Define the structure to accept the job ID and new status.
 // Used to update the application status of a job:
 #[derive(serde::Deserialize)]
@@ -44,12 +228,106 @@ pub struct JobStatusUpdate {
     pub applied: bool,
 }
 
+// The options of the job list page, from the url (`/?sort=created&q=rust&archived=1`):
+#[derive(Default, serde::Deserialize)]
+pub struct JobListQuery {
+    pub sort: Option<String>,
+    pub q: Option<String>,        // What was typed in the search box.
+    pub archived: Option<String>, // "1" lists the archived jobs instead of the others.
+}
+
 // Used to get and post JSON for the javascript onclick method.
 #[derive(Serialize)]
 pub struct ApiResponse {
     pub success: bool,
 }
 
+// The add form's details, sent by javascript to check for duplicates
+// before the job is added:
+#[derive(serde::Deserialize)]
+pub struct DuplicateQuery {
+    pub title: String,
+    pub company: Option<String>,
+    pub link: Option<String>,
+}
+
+// The csv file uploaded from the import form, with its import options.
+// The file is only previewed, nothing is imported yet:
+#[derive(MultipartForm)]
+pub struct CsvUploadForm {
+    #[multipart(limit = "5MB")]
+    pub file: Bytes,
+    pub delimiter: Option<Text<String>>,
+    pub on_conflict: Option<Text<String>>,
+}
+
+// A saved posting page uploaded from the web forms, to archive it
+// or to read the job's details out of it:
+#[derive(MultipartForm)]
+pub struct HtmlUploadForm {
+    #[multipart(limit = "5MB")]
+    pub file: Bytes,
+}
+
+// The previewed csv data sent back from the preview page to import it:
+#[derive(serde::Deserialize)]
+pub struct CsvImportForm {
+    pub data: String,
+    pub delimiter: Option<String>,
+    pub on_conflict: Option<String>,
+    pub atomic: Option<String>, // Checkbox, present when checked.
+}
+
+// Used by the javascript stage selector to move a job to a new stage:
+#[derive(serde::Deserialize)]
+pub struct JobStageUpdate {
+    pub id: i64,
+    pub stage: Stage,
+}
+
+// The date form used to add a deadline, reminder or interview to a job:
+#[derive(serde::Deserialize)]
+pub struct EventForm {
+    pub job_id: i64,
+    pub kind: String,
+    pub date: String,
+    pub time: Option<String>,
+    pub note: Option<String>,
+}
+
+// The login form, with the page to go back to after logging in:
+#[derive(serde::Deserialize)]
+pub struct LoginForm {
+    pub username: String,
+    pub password: String,
+    pub next: Option<String>,
+}
+
+// The page asked for before the login page, from the query string:
+#[derive(serde::Deserialize)]
+pub struct LoginQuery {
+    pub next: Option<String>,
+}
+
+// The form of the tokens page that makes a new API token:
+#[derive(serde::Deserialize)]
+pub struct TokenForm {
+    pub name: String,
+    pub scope: TokenScope,
+}
+
+// The form used to set the weekly application goal:
+#[derive(serde::Deserialize)]
+pub struct GoalForm {
+    pub target: i64,
+}
+
+// The jobs that match a DuplicateQuery, returned as JSON:
+#[derive(Serialize)]
+pub struct DuplicatesResponse {
+    pub duplicates: Vec<crate::duplicates::Duplicate>,
+}
+
 /// The Job struct creates a job with all relavant fields:
 impl Job {
     // Constructor:
@@ -66,16 +344,34 @@ impl Job {
             hourly,
             applied,
             link,
+            company: None,
+            stage: None,
+            created_at: None,
+            updated_at: None,
+            applied_at: None,
+            description: None,
+            link_status: None,
+            link_checked_at: None,
+            tags: Vec::new(),
+            archived_at: None,
         } // Return self
     }
 
+    /// Set the company of the job, ignoring an empty name.
+    pub fn with_company(mut self, company: Option<String>) -> Self {
+        self.company = company
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty());
+        self
+    }
+
     /// Getter methods becuase all data in each Job object is private:
-    pub fn _get_id(&self) -> i64 {
-        self.id.expect("Failed to create job id.")
+    pub fn get_id(&self) -> Option<i64> {
+        self.id
     }
 
-    pub fn get_link(&self) -> String {
-        self.link.clone().expect("No Link.")
+    pub fn get_optional_link(&self) -> Option<String> {
+        self.link.clone()
     }
 
     pub fn get_title(&self) -> String {
@@ -89,6 +385,492 @@ impl Job {
     pub fn get_applied(&self) -> String {
         self.applied.clone()
     }
+
+    pub fn get_company(&self) -> Option<String> {
+        self.company.clone()
+    }
+
+    /// Set the latest stage of the application.
+    pub fn with_stage(mut self, stage: Option<Stage>) -> Self {
+        self.stage = stage;
+        self
+    }
+
+    pub fn get_stage(&self) -> Option<Stage> {
+        self.stage
+    }
+
+    /// Set when the job was added, last changed and first applied to.
+    pub fn with_timestamps(
+        mut self,
+        created_at: Option<NaiveDateTime>,
+        updated_at: Option<NaiveDateTime>,
+        applied_at: Option<NaiveDateTime>,
+    ) -> Self {
+        self.created_at = created_at;
+        self.updated_at = updated_at;
+        self.applied_at = applied_at;
+        self
+    }
+
+    pub fn get_created_at(&self) -> Option<NaiveDateTime> {
+        self.created_at
+    }
+
+    pub fn get_updated_at(&self) -> Option<NaiveDateTime> {
+        self.updated_at
+    }
+
+    pub fn get_applied_at(&self) -> Option<NaiveDateTime> {
+        self.applied_at
+    }
+
+    /// Set the description of the job, ignoring an empty one.
+    pub fn with_description(mut self, description: Option<String>) -> Self {
+        self.description = description
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty());
+        self
+    }
+
+    pub fn get_description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    /// Set the result of the last check of the link.
+    pub fn with_link_check(
+        mut self,
+        link_status: Option<u16>,
+        link_checked_at: Option<NaiveDateTime>,
+    ) -> Self {
+        self.link_status = link_status;
+        self.link_checked_at = link_checked_at;
+        self
+    }
+
+    pub fn get_link_status(&self) -> Option<u16> {
+        self.link_status
+    }
+
+    /// Set the tags of the job, sorted and without duplicates.
+    pub fn with_tags(mut self, mut tags: Vec<String>) -> Self {
+        tags.sort_by_key(|tag| tag.to_lowercase());
+        tags.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+        self.tags = tags;
+        self
+    }
+
+    pub fn get_tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Set when the job was archived (`None` if it is in the job list).
+    pub fn with_archived_at(mut self, archived_at: Option<NaiveDateTime>) -> Self {
+        self.archived_at = archived_at;
+        self
+    }
+
+    pub fn get_archived_at(&self) -> Option<NaiveDateTime> {
+        self.archived_at
+    }
+}
+
+/// How long ago a time was, in words ("just now", "5 minutes ago", "3 days ago").
+///
+/// # Arguments
+/// * `then` - The time in the past.
+/// * `now` - The current time, in the same time zone.
+///
+/// # Returns
+/// * The age in the largest whole unit, or "just now" under a minute (and for future times).
+pub fn relative_age(then: NaiveDateTime, now: NaiveDateTime) -> String {
+    let seconds = (now - then).num_seconds();
+    let (count, unit) = match seconds {
+        s if s < 60 => return "just now".to_string(),
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 86400 => (s / 3600, "hour"),
+        s if s < 7 * 86400 => (s / 86400, "day"),
+        s if s < 30 * 86400 => (s / (7 * 86400), "week"),
+        s if s < 365 * 86400 => (s / (30 * 86400), "month"),
+        s => (s / (365 * 86400), "year"),
+    };
+    format!(
+        "{} {}{} ago",
+        count,
+        unit,
+        if count == 1 { "" } else { "s" }
+    )
+}
+
+/// The stages of an application, in order. Each change of stage is kept
+/// in the status history of the job.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Applied,   // The application was sent.
+    Screen,    // A recruiter or phone screen.
+    Interview, // An interview.
+    Offer,     // An offer was made.
+    Rejected,  // The application was turned down (not part of the funnel).
+}
+
+impl Stage {
+    /// The stages of the funnel, in order.
+    pub const FUNNEL: [Stage; 4] = [
+        Stage::Applied,
+        Stage::Screen,
+        Stage::Interview,
+        Stage::Offer,
+    ];
+
+    /// Every stage, in order.
+    pub const ALL: [Stage; 5] = [
+        Stage::Applied,
+        Stage::Screen,
+        Stage::Interview,
+        Stage::Offer,
+        Stage::Rejected,
+    ];
+
+    /// The name of the stage stored in the database.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Applied => "applied",
+            Stage::Screen => "screen",
+            Stage::Interview => "interview",
+            Stage::Offer => "offer",
+            Stage::Rejected => "rejected",
+        }
+    }
+
+    /// Find a stage by its name.
+    pub fn from_name(name: &str) -> Option<Stage> {
+        Stage::ALL
+            .into_iter()
+            .find(|stage| stage.name() == name.trim().to_lowercase())
+    }
+}
+
+/// The kinds of dates stored for a job.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Deadline,  // The last day to apply.
+    FollowUp,  // A reminder to follow up on an application.
+    Interview, // An interview.
+}
+
+impl EventKind {
+    /// The name of the kind stored in the database.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Deadline => "deadline",
+            EventKind::FollowUp => "follow_up",
+            EventKind::Interview => "interview",
+        }
+    }
+
+    /// Find a kind by its name ("follow-up" is accepted for "follow_up").
+    pub fn from_name(name: &str) -> Option<EventKind> {
+        match name.trim().to_lowercase().replace('-', "_").as_str() {
+            "deadline" => Some(EventKind::Deadline),
+            "follow_up" => Some(EventKind::FollowUp),
+            "interview" => Some(EventKind::Interview),
+            _ => None,
+        }
+    }
+
+    /// The kind as shown to the user.
+    pub fn label(&self) -> &'static str {
+        match self {
+            EventKind::Deadline => "Application deadline",
+            EventKind::FollowUp => "Follow up",
+            EventKind::Interview => "Interview",
+        }
+    }
+}
+
+/// A date for a job: a deadline, follow-up reminder or interview.
+///
+/// # Fields
+/// - `id`: The id of the event in the database (`None` before it is added).
+/// - `job_id`: The id of the job the event belongs to.
+/// - `kind`: The `EventKind` of the event.
+/// - `date`: The day of the event.
+/// - `time`: The optional start time; events without one last all day.
+/// - `note`: An optional note, like who the interview is with.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct JobEvent {
+    pub id: Option<i64>,
+    pub job_id: i64,
+    pub kind: EventKind,
+    pub date: NaiveDate,
+    pub time: Option<NaiveTime>,
+    pub note: Option<String>,
+}
+
+/// A person who can log in to the server.
+///
+/// # Fields
+/// - `id`: The id of the user in the database.
+/// - `username`: The name they log in with.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+}
+
+/// What an API token is allowed to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    Read,  // Only reading the jobs and statistics.
+    Write, // Reading, and changing the jobs too.
+}
+
+impl TokenScope {
+    /// The name of the scope stored in the database.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+        }
+    }
+
+    /// Find a scope by its name.
+    pub fn from_name(name: &str) -> Option<TokenScope> {
+        match name.trim().to_lowercase().as_str() {
+            "read" => Some(TokenScope::Read),
+            "write" => Some(TokenScope::Write),
+            _ => None,
+        }
+    }
+
+    /// Check if a token with this scope may do what needs `needed` (write tokens can also read).
+    pub fn allows(&self, needed: TokenScope) -> bool {
+        *self == TokenScope::Write || needed == TokenScope::Read
+    }
+}
+
+/// A personal API token, used by scripts instead of logging in.
+///
+/// # Fields
+/// - `id`: The id of the token in the database.
+/// - `name`: What the token is for, like "backup script".
+/// - `scope`: What the token is allowed to do.
+/// - `created_at`: When the token was made (UTC).
+/// - `last_used_at`: When the token was last used (UTC), if ever.
+///
+/// The token itself is only shown once, when it is made; only its hash is kept.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ApiToken {
+    pub id: i64,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+/// The saved copy of a job's posting page, so it isn't lost when the link stops working.
+///
+/// # Fields
+/// - `job_id`: The id of the job the page belongs to.
+/// - `html`: The sanitized HTML of the page.
+/// - `source`: The url it was fetched from, or the name of the uploaded file.
+/// - `saved_at`: When the page was saved (UTC).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Archive {
+    pub job_id: i64,
+    pub html: String,
+    pub source: String,
+    pub saved_at: NaiveDateTime,
+}
+
+impl JobEvent {
+    /// Create an event from text, as entered in a form or on the command line.
+    ///
+    /// # Arguments
+    /// * `job_id` - The id of the job.
+    /// * `kind` - The kind name: deadline, follow_up (or follow-up) or interview.
+    /// * `date` - The date as `YYYY-MM-DD`.
+    /// * `time` - The optional time as `HH:MM` (empty means all day).
+    /// * `note` - The optional note (empty means none).
+    ///
+    /// # Returns
+    /// * `Err(String)` describing the first invalid value.
+    pub fn parse(
+        job_id: i64,
+        kind: &str,
+        date: &str,
+        time: Option<&str>,
+        note: Option<&str>,
+    ) -> Result<JobEvent, String> {
+        let kind = EventKind::from_name(kind)
+            .ok_or_else(|| format!("unknown kind \"{}\" (deadline, follow_up, interview)", kind))?;
+        let date = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("invalid date \"{}\" (use YYYY-MM-DD)", date))?;
+        let time = match time.map(str::trim).filter(|t| !t.is_empty()) {
+            Some(t) => Some(
+                NaiveTime::parse_from_str(t, "%H:%M")
+                    .map_err(|_| format!("invalid time \"{}\" (use HH:MM)", t))?,
+            ),
+            None => None,
+        };
+        let note = note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+
+        Ok(JobEvent {
+            id: None,
+            job_id,
+            kind,
+            date,
+            time,
+            note,
+        })
+    }
+}
+
+/// The longest job title or company name allowed.
+pub const MAX_NAME_LENGTH: usize = 200;
+
+/// The longest link allowed.
+pub const MAX_LINK_LENGTH: usize = 2000;
+
+/// The longest description allowed, room for the text of any posting.
+pub const MAX_DESCRIPTION_LENGTH: usize = 50_000;
+
+/// The highest hourly rate allowed, to catch a yearly salary typed as the rate.
+pub const MAX_HOURLY: f32 = 1000.0;
+
+impl JobForm {
+    /// Check every field of the form, and make the job.
+    ///
+    /// # Returns
+    /// * `Ok(Job)` The job (without an id), with `applied` as "1" or "0" and
+    ///   the empty optional fields as `None`.
+    /// * `Err(FieldErrors)` with a message for each bad field.
+    pub fn validate(&self) -> Result<Job, FieldErrors> {
+        let mut errors = FieldErrors::new();
+
+        let title = self.title.trim();
+        if title.is_empty() {
+            errors.insert("title", "Enter the job title.".to_string());
+        } else if title.chars().count() > MAX_NAME_LENGTH {
+            errors.insert("title", too_long("title", MAX_NAME_LENGTH));
+        }
+
+        let company = self.company.trim();
+        if company.chars().count() > MAX_NAME_LENGTH {
+            errors.insert("company", too_long("company name", MAX_NAME_LENGTH));
+        }
+
+        let hourly = match self.hourly.trim().trim_start_matches('$').parse::<f32>() {
+            Ok(hourly) => check_hourly(hourly).unwrap_or_else(|message| {
+                errors.insert("hourly", message);
+                0.0
+            }),
+            Err(_) => {
+                errors.insert(
+                    "hourly",
+                    "Enter the hourly rate as a number, like 28.50.".to_string(),
+                );
+                0.0
+            }
+        };
+
+        let applied = match self.applied.as_str() {
+            "Yes" => "1",
+            "No" => "0",
+            _ => {
+                errors.insert("applied", "Pick Yes or No.".to_string());
+                "0"
+            }
+        };
+
+        let link = self.link.trim();
+        if link.chars().count() > MAX_LINK_LENGTH {
+            errors.insert("link", too_long("link", MAX_LINK_LENGTH));
+        } else if !link.is_empty() && !is_web_address(link) {
+            errors.insert(
+                "link",
+                "The link must be a web address starting with http:// or https://.".to_string(),
+            );
+        }
+
+        if self.description.trim().chars().count() > MAX_DESCRIPTION_LENGTH {
+            errors.insert(
+                "description",
+                too_long("description", MAX_DESCRIPTION_LENGTH),
+            );
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(Job::new(
+            None,
+            title.to_string(),
+            hourly,
+            applied.to_string(),
+            Some(link.to_string()).filter(|link| !link.is_empty()),
+        )
+        .with_company(Some(company.to_string()))
+        .with_description(Some(self.description.clone())))
+    }
+}
+
+/// Fill in the edit form with the details of a job.
+impl From<&Job> for JobForm {
+    fn from(job: &Job) -> Self {
+        JobForm {
+            title: job.get_title(),
+            company: job.get_company().unwrap_or_default(),
+            hourly: job.get_hourly().to_string(),
+            applied: if job.get_applied() == "Yes" {
+                "Yes"
+            } else {
+                "No"
+            }
+            .to_string(),
+            link: job.get_optional_link().unwrap_or_default(),
+            description: job.get_description().unwrap_or_default(),
+        }
+    }
+}
+
+/// Check an hourly rate: a number from 0 to `MAX_HOURLY` (`parse` also reads "NaN" and "inf").
+///
+/// # Returns
+/// * `Ok(hourly)` The rate, unchanged.
+/// * `Err(message)` saying what is wrong with it.
+pub fn check_hourly(hourly: f32) -> Result<f32, String> {
+    if !hourly.is_finite() {
+        Err("Enter the hourly rate as a number.".to_string())
+    } else if hourly < 0.0 {
+        Err("The hourly rate can't be negative.".to_string())
+    } else if hourly > MAX_HOURLY {
+        Err(format!(
+            "The hourly rate must be at most ${}, not a yearly salary.",
+            MAX_HOURLY
+        ))
+    } else {
+        Ok(hourly)
+    }
+}
+
+// The message of a field that is too long.
+fn too_long(field: &str, max: usize) -> String {
+    format!("The {} must be at most {} characters.", field, max)
+}
+
+// Check that a link is an http or https address with a host, and no spaces.
+fn is_web_address(link: &str) -> bool {
+    let rest = link
+        .strip_prefix("https://")
+        .or_else(|| link.strip_prefix("http://"));
+    rest.is_some_and(|rest| {
+        let host = rest.split(['/', '?', '#']).next().unwrap_or("");
+        !host.is_empty() && !link.contains(char::is_whitespace)
+    })
 }
 
 /// Testing the Job struct from objects.
@@ -100,13 +882,13 @@ mod tests {
 
     #[test]
     fn test_create_new_object() {
-        let job = Job {
-            id: Some(1),
-            title: "Train Driver".to_string(),
-            hourly: 30.50,
-            applied: "1".to_string(),
-            link: Some("http://example.com".to_string()),
-        };
+        let job = Job::new(
+            Some(1),
+            "Train Driver".to_string(),
+            30.50,
+            "1".to_string(),
+            Some("http://example.com".to_string()),
+        );
 
         assert_eq!(job.id, Some(1));
         assert_eq!(job.title, "Train Driver");
@@ -117,34 +899,29 @@ mod tests {
 
     #[test]
     fn test_get_title() {
-        let job = Job {
-            id: Some(2),
-            title: "Engineer".to_string(),
-            hourly: 25.0,
-            applied: "0".to_string(),
-            link: Some("http://job.com".to_string()),
-        };
+        let job = Job::new(
+            Some(2),
+            "Engineer".to_string(),
+            25.0,
+            "0".to_string(),
+            Some("http://job.com".to_string()),
+        );
 
         assert_eq!(job.title, "Engineer");
     }
 
     #[test]
     fn test_applied_status() {
-        let job_applied = Job {
-            id: Some(3),
-            title: "Programmer".to_string(),
-            hourly: 50.0,
-            applied: "1".to_string(),
-            link: None,
-        };
+        let job_applied = Job::new(
+            Some(3),
+            "Programmer".to_string(),
+            50.0,
+            "1".to_string(),
+            None,
+        );
 
-        let job_not_applied = Job {
-            id: Some(4),
-            title: "Designer".to_string(),
-            hourly: 40.0,
-            applied: "0".to_string(),
-            link: None,
-        };
+        let job_not_applied =
+            Job::new(Some(4), "Designer".to_string(), 40.0, "0".to_string(), None);
 
         assert_eq!(job_applied.applied, "1");
         assert_eq!(job_not_applied.applied, "0");
@@ -152,23 +929,199 @@ mod tests {
 
     #[test]
     fn test_optional_link() {
-        let job_with_link = Job {
-            id: Some(5),
-            title: "Pilot".to_string(),
-            hourly: 60.0,
-            applied: "0".to_string(),
-            link: Some("http://pilot.com".to_string()),
-        };
+        let job_with_link = Job::new(
+            Some(5),
+            "Pilot".to_string(),
+            60.0,
+            "0".to_string(),
+            Some("http://pilot.com".to_string()),
+        );
 
-        let job_without_link = Job {
-            id: Some(6),
-            title: "Chef".to_string(),
-            hourly: 20.0,
-            applied: "1".to_string(),
-            link: None,
-        };
+        let job_without_link = Job::new(Some(6), "Chef".to_string(), 20.0, "1".to_string(), None);
 
         assert_eq!(job_with_link.link, Some("http://pilot.com".to_string()));
         assert_eq!(job_without_link.link, None);
     }
+
+    #[test]
+    fn test_with_company() {
+        let job = Job::new(Some(7), "Barista".to_string(), 16.0, "0".to_string(), None)
+            .with_company(Some("  Black Rock Coffee ".to_string()));
+        assert_eq!(job.get_company(), Some("Black Rock Coffee".to_string()));
+
+        let job = job.with_company(Some("   ".to_string()));
+        assert_eq!(job.get_company(), None, "An empty company is no company.");
+    }
+
+    // The checked jobs are read from the form, with its other fields skipped:
+    #[test]
+    fn test_job_selection() {
+        let selection =
+            JobSelection::from_form(b"csrf_token=abc&ids=3&ids=12&ids=7&ids=3").unwrap();
+        assert_eq!(selection.ids, vec![3, 12, 7]);
+        assert_eq!(
+            JobSelection::from_form(b"csrf_token=abc").unwrap(),
+            JobSelection::default()
+        );
+        assert!(JobSelection::from_form(b"ids=3&ids=three").is_err());
+    }
+
+    // The bulk form and the bulk command name their actions the same way:
+    #[test]
+    fn test_bulk_action() {
+        assert_eq!(
+            BulkAction::from_form(b"ids=3&action=stage%3Ainterview&tag=").unwrap(),
+            BulkAction::SetStage(Stage::Interview)
+        );
+        assert_eq!(
+            BulkAction::from_form(b"ids=3&action=tag&tag=+Remote+").unwrap(),
+            BulkAction::AddTag("Remote".to_string())
+        );
+        assert_eq!(
+            BulkAction::parse("not_applied", None).unwrap(),
+            BulkAction::NotApplied
+        );
+        assert_eq!(
+            BulkAction::parse("Archive", None).unwrap(),
+            BulkAction::Archive
+        );
+
+        // A missing or bad action, stage or tag is an error:
+        assert!(BulkAction::from_form(b"ids=3").is_err());
+        assert!(BulkAction::from_form(b"ids=3&action=tag&tag=+").is_err());
+        assert!(BulkAction::parse("stage", Some("hired")).is_err());
+        assert!(BulkAction::parse("tag", Some("a,b")).is_err());
+        assert!(BulkAction::parse("tag", Some(&"x".repeat(MAX_TAG_LENGTH + 1))).is_err());
+        assert!(BulkAction::parse("promote", None).is_err());
+    }
+
+    // Every bad field of the add form gets its own message, and nothing panics:
+    #[test]
+    fn test_validate_job_form() {
+        let form = JobForm {
+            title: " Welder ".to_string(),
+            company: "Daimler".to_string(),
+            hourly: "$28.50".to_string(),
+            applied: "Yes".to_string(),
+            link: "".to_string(),
+            description: "  ".to_string(),
+        };
+        let job = form.validate().expect("The form is valid");
+        assert_eq!(job.get_title(), "Welder");
+        assert_eq!(job.get_hourly(), 28.5);
+        assert_eq!(job.get_applied(), "1");
+        assert_eq!(job.get_optional_link(), None, "An empty link is no link.");
+        assert_eq!(job.get_company(), Some("Daimler".to_string()));
+        assert_eq!(job.get_description(), None);
+
+        let errors = JobForm {
+            title: "   ".to_string(),
+            company: "x".repeat(MAX_NAME_LENGTH + 1),
+            hourly: "-5".to_string(),
+            applied: "Maybe".to_string(),
+            link: "javascript:alert(1)".to_string(),
+            description: String::new(),
+        }
+        .validate()
+        .unwrap_err();
+        assert_eq!(
+            errors.keys().copied().collect::<Vec<_>>(),
+            vec!["applied", "company", "hourly", "link", "title"]
+        );
+
+        let hourly_error = |hourly: &str| {
+            JobForm {
+                hourly: hourly.to_string(),
+                ..form.clone()
+            }
+            .validate()
+            .err()
+            .and_then(|errors| errors.get("hourly").cloned())
+        };
+        assert!(hourly_error("twenty").is_some());
+        assert!(hourly_error("NaN").is_some());
+        assert!(hourly_error("inf").is_some());
+        assert!(hourly_error("85000").is_some());
+        assert!(hourly_error("").is_some());
+        assert_eq!(hourly_error("0"), None);
+        assert_eq!(check_hourly(28.5), Ok(28.5));
+        assert!(check_hourly(f32::NAN).is_err());
+        assert!(check_hourly(-5.0).is_err());
+
+        // A pasted posting fits, but not one past the limit, or an endless link:
+        let long = |description: String, link: String| {
+            JobForm {
+                description,
+                link,
+                ..form.clone()
+            }
+            .validate()
+            .err()
+            .map(|errors| errors.keys().copied().collect::<Vec<_>>())
+        };
+        let fits = "x".repeat(MAX_DESCRIPTION_LENGTH);
+        assert_eq!(long(fits, String::new()), None);
+        let too_long = "x".repeat(MAX_DESCRIPTION_LENGTH + 1);
+        assert_eq!(long(too_long, String::new()), Some(vec!["description"]));
+        let link = format!("https://example.com/{}", "a".repeat(MAX_LINK_LENGTH));
+        assert_eq!(long(String::new(), link), Some(vec!["link"]));
+
+        assert!(is_web_address("https://example.com/jobs?id=3"));
+        assert!(is_web_address("http://localhost:8000"));
+        assert!(!is_web_address("https://"));
+        assert!(!is_web_address("example.com"));
+        assert!(!is_web_address("https://example.com/a b"));
+
+        // A job fills in the edit form, and comes back the same:
+        let job = Job::new(None, "Chef".to_string(), 20.0, "No".to_string(), None);
+        let form = JobForm::from(&job);
+        assert_eq!((form.hourly.as_str(), form.link.as_str()), ("20", ""));
+        assert_eq!(form.validate().unwrap().get_applied(), "0");
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let event = JobEvent::parse(3, "follow-up", "2025-03-14", Some("09:30"), Some(" Call "))
+            .expect("Failed to parse event");
+        assert_eq!(event.kind, EventKind::FollowUp);
+        assert_eq!(event.date, NaiveDate::from_ymd_opt(2025, 3, 14).unwrap());
+        assert_eq!(event.time, NaiveTime::from_hms_opt(9, 30, 0));
+        assert_eq!(event.note, Some("Call".to_string()));
+
+        let all_day = JobEvent::parse(3, "deadline", "2025-03-14", Some(""), None).unwrap();
+        assert_eq!(all_day.time, None);
+
+        assert!(JobEvent::parse(3, "party", "2025-03-14", None, None).is_err());
+        assert!(JobEvent::parse(3, "interview", "14/03/2025", None, None).is_err());
+        assert!(JobEvent::parse(3, "interview", "2025-03-14", Some("9am"), None).is_err());
+    }
+
+    #[test]
+    fn test_stage_names() {
+        for stage in Stage::ALL {
+            assert_eq!(Stage::from_name(stage.name()), Some(stage));
+        }
+        assert_eq!(Stage::from_name(" Screen "), Some(Stage::Screen));
+        assert_eq!(Stage::from_name("hired"), None);
+        assert!(Stage::Applied < Stage::Offer, "Stages are ordered.");
+    }
+
+    #[test]
+    fn test_relative_age() {
+        let now: NaiveDateTime = "2025-03-14T12:00:00".parse().unwrap();
+        let ago = |text: &str| relative_age(text.parse().unwrap(), now);
+
+        assert_eq!(ago("2025-03-14T11:59:30"), "just now");
+        assert_eq!(ago("2025-03-14T11:59:00"), "1 minute ago");
+        assert_eq!(ago("2025-03-14T09:00:00"), "3 hours ago");
+        assert_eq!(ago("2025-03-12T12:00:00"), "2 days ago");
+        assert_eq!(ago("2025-02-28T12:00:00"), "2 weeks ago");
+        assert_eq!(ago("2024-12-01T12:00:00"), "3 months ago");
+        assert_eq!(ago("2023-03-01T12:00:00"), "2 years ago");
+        assert_eq!(
+            ago("2025-03-15T12:00:00"),
+            "just now",
+            "A time in the future."
+        );
+    }
 }
@@ -0,0 +1,297 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Links Module
+//!
+//! Checks that the link of every job still works, and records the HTTP
+//! status and the time of each check on the job. The job list flags the
+//! dead links, and `list check-links` checks them all. The requests go
+//! through the `HttpClient` trait, so the tests can point a client at a
+//! local stub server instead of the internet.
+
+use crate::archive::web_url;
+use crate::database_methods::{get_jobs, set_link_status, DatabaseError};
+use chrono::NaiveDateTime;
+use std::time::Duration;
+
+/// The status recorded when a link gets no answer (no such host, refused, timed out).
+pub const NO_ANSWER: u16 = 0;
+
+/// Something that can request a url and tell its HTTP status.
+pub trait HttpClient {
+    /// Request `url` (following redirects) and return the final HTTP status.
+    ///
+    /// # Returns
+    /// * `Ok(status)` for any answer, error statuses included.
+    /// * `Err(message)` if there was no answer.
+    fn status(&self, url: &str) -> Result<u16, String>;
+}
+
+/// Requests urls over HTTP(S) with ureq.
+pub struct UreqClient {
+    agent: ureq::Agent,
+}
+
+impl Default for UreqClient {
+    fn default() -> Self {
+        UreqClient {
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(15))
+                .user_agent("Mozilla/5.0 (job list link checker)")
+                .build(),
+        }
+    }
+}
+
+impl HttpClient for UreqClient {
+    fn status(&self, url: &str) -> Result<u16, String> {
+        // GET rather than HEAD, which many job sites answer wrongly. The body is never read:
+        match self.agent.get(url).call() {
+            Ok(response) => Ok(response.status()),
+            Err(ureq::Error::Status(code, _)) => Ok(code),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+/// What the status of a link says about it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkHealth {
+    Ok,      // A success or a redirect.
+    Blocked, // 401, 403 or 429: the site refuses checks by a program, the posting may be fine.
+    Dead,    // Any other error, or no answer.
+}
+
+impl LinkHealth {
+    /// The health of a link from its status.
+    pub fn of(status: u16) -> LinkHealth {
+        match status {
+            200..=399 => LinkHealth::Ok,
+            401 | 403 | 429 => LinkHealth::Blocked,
+            _ => LinkHealth::Dead,
+        }
+    }
+
+    /// The name of the health, as shown on the job list.
+    pub fn name(self) -> &'static str {
+        match self {
+            LinkHealth::Ok => "ok",
+            LinkHealth::Blocked => "blocked",
+            LinkHealth::Dead => "dead",
+        }
+    }
+}
+
+/// The result of checking one job's link.
+///
+/// # Fields
+/// - `job_id`, `title`, `link`: The job and the link checked.
+/// - `status`: The HTTP status (`NO_ANSWER` if there was none).
+/// - `error`: Why there was no answer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkCheck {
+    pub job_id: i64,
+    pub title: String,
+    pub link: String,
+    pub status: u16,
+    pub error: Option<String>,
+}
+
+impl LinkCheck {
+    pub fn health(&self) -> LinkHealth {
+        LinkHealth::of(self.status)
+    }
+}
+
+/// Check the link of every job of a user that has a web link, and record the results.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user whose links are checked.
+/// * `client` - How the links are requested.
+/// * `now` - The time of the check (UTC).
+///
+/// # Returns
+/// * `Ok(Vec<LinkCheck>)` with the result of each link, in the order of the jobs.
+/// * `Err(DatabaseError)` if the jobs can't be read or a result can't be saved.
+pub fn check_links(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    client: &dyn HttpClient,
+    now: NaiveDateTime,
+) -> Result<Vec<LinkCheck>, DatabaseError> {
+    let mut checks = Vec::new();
+    for job in get_jobs(connection, user_id)? {
+        let (Some(job_id), Some(url)) = (
+            job.get_id(),
+            job.get_optional_link().as_deref().and_then(web_url),
+        ) else {
+            continue; // No link to check ("No Link" or not a web address).
+        };
+
+        let (status, error) = match client.status(url.as_str()) {
+            Ok(status) => (status, None),
+            Err(error) => (NO_ANSWER, Some(error)),
+        };
+        set_link_status(connection, user_id, job_id, status, now)?;
+        checks.push(LinkCheck {
+            job_id,
+            title: job.get_title(),
+            link: url.to_string(),
+            status,
+            error,
+        });
+    }
+    Ok(checks)
+}
+
+/// Testing the link checks against a stub server on this computer.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database_methods::{create_table, enter_data, get_job, update_job};
+    use crate::job::Job;
+    use rusqlite::Connection;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    // The user the test jobs belong to:
+    const USER: i64 = 1;
+
+    // Start a stub HTTP server that answers `/<status>` with that status, and
+    // `/moved` with a redirect to `/200`. Returns its address.
+    fn stub_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to start the stub server");
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap_or_default();
+                // Read the rest of the request headers:
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                    line.clear();
+                }
+
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+                let response = match path {
+                    "/moved" => "HTTP/1.1 301 Moved\r\nLocation: /200\r\n".to_string(),
+                    _ => format!("HTTP/1.1 {} Stub\r\n", path.trim_start_matches('/')),
+                };
+                let _ = (&stream).write_all(
+                    format!("{}Content-Length: 0\r\nConnection: close\r\n\r\n", response)
+                        .as_bytes(),
+                );
+            }
+        });
+        address
+    }
+
+    // An address where nothing answers:
+    fn closed_address() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    #[test]
+    fn test_link_health() {
+        assert_eq!(LinkHealth::of(200), LinkHealth::Ok);
+        assert_eq!(LinkHealth::of(301), LinkHealth::Ok);
+        assert_eq!(LinkHealth::of(403), LinkHealth::Blocked);
+        assert_eq!(LinkHealth::of(404), LinkHealth::Dead);
+        assert_eq!(LinkHealth::of(503), LinkHealth::Dead);
+        assert_eq!(LinkHealth::of(NO_ANSWER), LinkHealth::Dead);
+    }
+
+    // The real client follows redirects and reports error statuses:
+    #[test]
+    fn test_ureq_client() {
+        let server = stub_server();
+        let client = UreqClient::default();
+        assert_eq!(client.status(&format!("{}/200", server)), Ok(200));
+        assert_eq!(client.status(&format!("{}/moved", server)), Ok(200));
+        assert_eq!(client.status(&format!("{}/404", server)), Ok(404));
+        assert_eq!(client.status(&format!("{}/503", server)), Ok(503));
+        assert!(client.status(&closed_address()).is_err());
+    }
+
+    // Every web link is checked and its result is saved on the job:
+    #[test]
+    fn test_check_links() {
+        let connection = Connection::open_in_memory().expect("Failed to open in-memory database");
+        create_table(&connection).expect("Failed to create table");
+        let server = stub_server();
+        let links = [
+            format!("{}/200", server),
+            format!("{}/404", server),
+            "No Link".to_string(),
+            closed_address(),
+        ];
+        for (i, link) in links.iter().enumerate() {
+            let job = Job::new(
+                None,
+                format!("Job {}", i + 1),
+                20.0,
+                "0".to_string(),
+                Some(link.clone()),
+            );
+            enter_data(&connection, USER, &job).expect("Failed to insert job");
+        }
+
+        let now =
+            NaiveDateTime::parse_from_str("2025-03-12 10:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let checks =
+            check_links(&connection, USER, &UreqClient::default(), now).expect("Failed to check");
+        let statuses: Vec<(i64, u16)> = checks.iter().map(|c| (c.job_id, c.status)).collect();
+        assert_eq!(statuses, vec![(1, 200), (2, 404), (4, NO_ANSWER)]);
+        assert!(checks[2].error.is_some());
+        assert_eq!(checks[1].health(), LinkHealth::Dead);
+
+        let job = get_job(&connection, USER, 2).unwrap().unwrap();
+        assert_eq!(job.get_link_status(), Some(404));
+        assert!(get_job(&connection, USER, 3)
+            .unwrap()
+            .unwrap()
+            .get_link_status()
+            .is_none());
+
+        // A new link forgets the check of the old one:
+        let edited = Job::new(
+            None,
+            "Job 2".to_string(),
+            20.0,
+            "0".to_string(),
+            Some(format!("{}/200", server)),
+        );
+        update_job(&connection, USER, 2, &edited).unwrap();
+        assert_eq!(
+            get_job(&connection, USER, 2)
+                .unwrap()
+                .unwrap()
+                .get_link_status(),
+            None
+        );
+        update_job(
+            &connection,
+            USER,
+            1,
+            &Job::new(
+                None,
+                "Job 1".to_string(),
+                25.0,
+                "0".to_string(),
+                Some(links[0].clone()),
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            get_job(&connection, USER, 1)
+                .unwrap()
+                .unwrap()
+                .get_link_status(),
+            Some(200)
+        );
+    }
+}
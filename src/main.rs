@@ -8,19 +8,34 @@
 //! to manipulate the database are found in the
 //! server module which is included.
 
+mod archive;
+mod auth;
+mod calendar;
+mod charts;
+mod cli;
+mod csrf;
 mod csv_reader;
 mod database_methods;
+mod duplicates;
+mod error;
+mod flash;
+mod goals;
 mod job; // References job.rs file
+mod links;
+mod posting;
+mod search;
 mod server;
+mod stats;
 
 // Logging used for the server side to
 // see GET and POST requests:
-use crate::database_methods::{create_table, database_empty};
+use crate::csv_reader::ImportOptions;
+use crate::database_methods::{create_table, database_empty, get_users, Database};
 use actix_files::Files;
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
 use env_logger::Builder;
 use log::{error, info, LevelFilter};
-use rusqlite::Connection;
 use std::env;
 use std::io::Write;
 use std::str::FromStr;
@@ -37,18 +52,48 @@ fn is_valid_port(port: &str) -> bool {
 /// The main entry point for the Actix Web server.
 ///
 /// This function:
+/// - Runs a CLI command (see the `cli` module) instead of the server when the first argument is a command.
 /// - Initializes the SQLite database, opening it or creating it if necessary.
 /// - Creates the required table in the database if it doesn't exist.
-/// - Checks if the database is empty and populates it with data from a CSV file if it is.
+/// - Checks if the database is empty and populates it with data from a CSV file for the first user if it is.
 /// - Sets up logging configuration to only display relevant log messages (suppresses unnecessary internal Actix logs).
 /// - Initializes the Tera template engine for rendering HTML files.
 /// - Configures an Actix Web server with routes to handle jobs listing, adding, removing, and updating jobs.
-/// - Binds the server to `127.0.0.1:<port>` (where `<port>` is a command line arg) and starts it.
+///   Every route but the login page and the static files needs a login (see the `auth` module),
+///   the JSON endpoints also take API tokens. The forms and JavaScript send a CSRF token
+///   with each change (see the `csrf` module).
+/// - Binds the server to `<host>:<port>` and starts it, where `<port>` is a command line arg and
+///   `<host>` is the `JOBS_HOST` environment variable (`127.0.0.1` by default, `0.0.0.0` to share
+///   the server on the network).
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Get command line arguments for the port:
+    // Get command line arguments for the port or a CLI command:
     let args: Vec<String> = env::args().collect();
 
+    // Job application database file:
+    let database = Database::new("jobs_data.db");
+
+    // Create an SQLite database file. Open the database.
+    // file if it already exists.
+    let connection = match database.open() {
+        Ok(conn) => conn,
+        Err(err) => {
+            eprintln!("Error opening the database: {}", err);
+            std::process::exit(1); // Stop execution if the table fails to create
+        }
+    };
+
+    // Create the table if it doesn't exist:
+    if let Err(err) = create_table(&connection) {
+        error!("Error creating table: {}", err);
+        std::process::exit(1); // Stop execution if the table fails to create.
+    }
+
+    // Run a CLI command instead of the server if one was given:
+    if args.len() > 1 && cli::is_command(&args[1]) {
+        std::process::exit(cli::run(&args[1..], &connection));
+    }
+
     // Set default port in case no command line args are given:
     let default_port = "8000";
 
@@ -63,38 +108,43 @@ async fn main() -> std::io::Result<()> {
         default_port // Use the default port if invalid or not provided
     };
 
-    let host: &str = "127.0.0.1"; // localhost
-    let url: &str = &(host.to_owned() + ":" + port); // The URL the server will bind to
-
-    // Job application database file:
-    let database_file: &str = "jobs_data.db";
+    // Only this computer by default, JOBS_HOST=0.0.0.0 shares the server on the network:
+    let host = env::var("JOBS_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+    let url: &str = &(host + ":" + port); // The URL the server will bind to
 
-    // Create an SQLite database file. Open the database.
-    // file if it already exists.
-    let connection = match Connection::open(database_file) {
-        Ok(conn) => conn,
-        Err(err) => {
-            eprintln!("Error opening the database: {}", err);
-            std::process::exit(1); // Stop execution if the table fails to create
+    // Nobody can log in until a user is added:
+    let first_user = match get_users(&connection) {
+        Ok(users) => users.into_iter().min_by_key(|user| user.id),
+        Err(e) => {
+            eprintln!("Error checking the users: {}", e);
+            None
         }
     };
-
-    // Create the table if it doesn't exist:
-    if let Err(err) = create_table(&connection) {
-        error!("Error creating table: {}", err);
-        std::process::exit(1); // Stop execution if the table fails to create.
+    if first_user.is_none() {
+        eprintln!("There are no users yet, add one to log in with: ./run.sh user add <name>");
     }
 
-    // Check if the database is empty, if it is, add the csv data to the jobs table:
+    // Check if the database is empty, if it is, add the csv data to the jobs table
+    // of the first user (once there is one):
     // Now, we can safely use the connection:
     match database_empty(&connection) {
         Ok(is_empty) => {
-            if is_empty {
-                println!("The database is empty, adding CSV data...");
-                match csv_reader::read_csv_file("application.csv", &connection) {
-                    Ok(_) => println!("CSV data successfully added."),
+            if let (true, Some(user)) = (is_empty, &first_user) {
+                println!(
+                    "The database is empty, adding CSV data for {}...",
+                    user.username
+                );
+                match csv_reader::read_csv_file(
+                    "application.csv",
+                    &connection,
+                    user.id,
+                    &ImportOptions::default(),
+                ) {
+                    Ok(report) => print!("CSV data added: {}", report),
                     Err(e) => eprintln!("Error reading CSV file: {}", e),
                 }
+            } else if is_empty {
+                println!("The database is empty, the CSV data is added once there is a user.");
             } else {
                 println!("The database is not empty.");
             }
@@ -124,18 +174,72 @@ async fn main() -> std::io::Result<()> {
     info!("Server listening on \"{}\"", url);
 
     // Initialize Tera template engine where the html files are located:
-    let tera = Tera::new("templates/**/*").unwrap();
+    let mut tera = Tera::new("templates/**/*").unwrap();
+    tera.register_filter("age", server::age_filter); // Timestamps as "3 days ago".
+    tera.register_filter("link_health", server::link_health_filter); // Checked links as "dead".
 
     let server = HttpServer::new(move || {
         App::new()
             // When url: http://localhost:8000/jobs, call list_jobs() method that connects.
             // to database of displays jobs in html:
             .app_data(web::Data::new(tera.clone())) // Add Tera to Actix app data.
+            .app_data(web::Data::new(database.clone())) // The database for the handlers.
+            .app_data(server::form_config()) // The size limit of the forms (csv imports, descriptions).
+            .app_data(server::upload_config()) // The size limits of the uploaded files.
             .service(Files::new("/static", "./static").show_files_listing()) // Serve the static style.css files.
-            .route("/", web::get().to(server::list_jobs))
-            .route("/add", web::post().to(server::add_job)) // POST for adding jobs.
-            .route("/rem", web::post().to(server::rem_job)) // POST for removing jobs.
-            .route("/update", web::post().to(server::update))
+            .service(
+                // The login page, and POST for logging in, only from this site's page:
+                web::resource("/login")
+                    .wrap(from_fn(csrf::verify_origin))
+                    .route(web::get().to(server::login_page))
+                    .route(web::post().to(server::login)),
+            )
+            .service(
+                // POST for logging out, only from the user's own page:
+                web::resource("/logout")
+                    .wrap(from_fn(csrf::verify_csrf))
+                    .route(web::post().to(server::logout)),
+            )
+            .service(
+                // Every other route needs a login, and a CSRF token to change anything
+                // (the last middleware added runs first):
+                web::scope("")
+                    .wrap(from_fn(csrf::verify_csrf))
+                    .wrap(from_fn(auth::require_login))
+                    .route("/", web::get().to(server::list_jobs))
+                    .route("/add", web::post().to(server::add_job)) // POST for adding jobs.
+                    .route("/rem", web::post().to(server::rem_job)) // POST for removing jobs.
+                    .route("/rem/selected", web::post().to(server::remove_selected)) // POST for removing the checked jobs.
+                    .route("/bulk", web::post().to(server::bulk_jobs)) // POST for changing the checked jobs.
+                    .route("/update", web::post().to(server::update))
+                    .route("/jobs/{id}", web::get().to(server::job_detail)) // Details of a job.
+                    .route("/jobs/{id}/edit", web::post().to(server::edit_job)) // POST for editing a job.
+                    .route("/jobs/{id}/archive", web::get().to(server::job_archive)) // The saved posting page.
+                    .route("/jobs/{id}/archive", web::post().to(server::upload_archive)) // Upload the page.
+                    .route(
+                        "/jobs/{id}/archive/fetch",
+                        web::post().to(server::fetch_archive),
+                    ) // Fetch the page.
+                    .route("/stage", web::post().to(server::update_stage)) // Change the stage of a job.
+                    .route("/goal", web::post().to(server::set_goal_form)) // Set the weekly goal.
+                    .route("/posting", web::post().to(server::parse_posting_upload)) // Details from a page.
+                    .route("/duplicates", web::post().to(server::check_duplicates))
+                    .route("/upload", web::post().to(server::upload_preview)) // Preview a csv file.
+                    .route("/import", web::post().to(server::upload_import)) // Import a previewed csv file.
+                    .route("/events", web::post().to(server::add_event_form)) // POST for adding dates.
+                    .route("/calendar.ics", web::get().to(server::calendar_feed)) // Calendar of every date.
+                    .route(
+                        "/jobs/{id}/calendar.ics",
+                        web::get().to(server::job_calendar),
+                    )
+                    .route("/stats", web::get().to(server::stats_page)) // Statistics page.
+                    .route("/api/stats", web::get().to(server::api_stats)) // Statistics as JSON.
+                    .route("/api/jobs", web::get().to(server::api_jobs)) // The jobs as JSON.
+                    .route("/tokens", web::get().to(server::tokens_page)) // The user's API tokens.
+                    .route("/tokens", web::post().to(server::create_token)) // POST for a new token.
+                    .route("/tokens/{id}/revoke", web::post().to(server::revoke_token)) // POST for revoking a token.
+                    .route("/charts/{name}.svg", web::get().to(server::chart_svg)), // Charts as SVG images.
+            )
     });
 
     // Properly handle the `.bind()` result
@@ -0,0 +1,414 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Posting Module
+//!
+//! Reads the details of a job out of a saved posting page, to pre-fill
+//! the add form or the `add --from-html` command. Most job sites put a
+//! schema.org `JobPosting` in the page as JSON-LD, with the title, the
+//! employer, the pay, the location and the description. Anything it
+//! doesn't have is taken from the page's meta tags (`og:title` and the
+//! like) and its `<title>`.
+
+use scraper::{Html, Node, Selector};
+use serde::Serialize;
+use serde_json::Value;
+
+/// The hours worked in a year, a month, a week and a day, to turn a salary into an hourly rate.
+const HOURS_PER_YEAR: f64 = 2080.0;
+const HOURS_PER_MONTH: f64 = HOURS_PER_YEAR / 12.0;
+const HOURS_PER_WEEK: f64 = 40.0;
+const HOURS_PER_DAY: f64 = 8.0;
+
+/// Pay without a unit above this is taken as a yearly salary, below it as an hourly rate.
+const MAX_UNLABELED_HOURLY: f64 = 500.0;
+
+/// The details of a job read from a posting page. Each is `None` when the page doesn't have it.
+///
+/// # Fields
+/// - `title`: The job title.
+/// - `company`: The name of the employer.
+/// - `hourly`: The pay as an hourly rate (the lowest of a range, salaries are divided by 2080 hours).
+/// - `location`: Where the job is ("Portland, OR", or "Remote").
+/// - `link`: The url of the posting.
+/// - `description`: The text of the posting, without its HTML.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Posting {
+    pub title: Option<String>,
+    pub company: Option<String>,
+    pub hourly: Option<f32>,
+    pub location: Option<String>,
+    pub link: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Posting {
+    /// Check if nothing was found in the page.
+    pub fn is_empty(&self) -> bool {
+        *self == Posting::default()
+    }
+
+    /// The description with the location on its first line, since a job has no location of its own.
+    pub fn full_description(&self) -> Option<String> {
+        match (&self.location, &self.description) {
+            (Some(location), Some(description)) => {
+                Some(format!("Location: {}\n\n{}", location, description))
+            }
+            (Some(location), None) => Some(format!("Location: {}", location)),
+            (None, description) => description.clone(),
+        }
+    }
+}
+
+/// Read the details of a job out of the HTML of its posting page.
+///
+/// # Arguments
+/// * `html` - The HTML of the page.
+///
+/// # Returns
+/// * The details found, from the JSON-LD `JobPosting` first and then the meta tags.
+pub fn parse_posting(html: &str) -> Posting {
+    let document = Html::parse_document(html);
+    let mut posting = json_ld_postings(&document)
+        .first()
+        .map(from_json_ld)
+        .unwrap_or_default();
+
+    // Fill in what the JSON-LD didn't have from the meta tags:
+    let meta = |names: &[&str]| names.iter().find_map(|name| meta_content(&document, name));
+    posting.title = posting
+        .title
+        .or_else(|| meta(&["og:title", "twitter:title"]))
+        .or_else(|| select_text(&document, "title"));
+    posting.company = posting.company.or_else(|| meta(&["og:site_name"]));
+    posting.link = posting
+        .link
+        .or_else(|| meta(&["og:url"]))
+        .or_else(|| select_attr(&document, "link[rel=\"canonical\"]", "href"));
+    posting.description = posting
+        .description
+        .or_else(|| meta(&["og:description", "description", "twitter:description"]));
+    posting
+}
+
+// Every JobPosting in the JSON-LD scripts of a page, in order.
+fn json_ld_postings(document: &Html) -> Vec<Value> {
+    let selector = Selector::parse("script[type=\"application/ld+json\"]").expect("Valid selector");
+    let mut postings = Vec::new();
+    for script in document.select(&selector) {
+        let text: String = script.text().collect();
+        // Some sites leave raw line breaks in the strings, which JSON doesn't allow:
+        let value = serde_json::from_str::<Value>(&text)
+            .or_else(|_| serde_json::from_str::<Value>(&text.replace(['\n', '\r', '\t'], " ")));
+        if let Ok(value) = value {
+            collect_postings(&value, &mut postings);
+        }
+    }
+    postings
+}
+
+// Find the JobPostings in a JSON-LD value: itself, a list, or an `@graph`.
+fn collect_postings(value: &Value, postings: &mut Vec<Value>) {
+    match value {
+        Value::Array(items) => items.iter().for_each(|v| collect_postings(v, postings)),
+        Value::Object(object) => {
+            let is_posting = match object.get("@type") {
+                Some(Value::String(kind)) => kind == "JobPosting",
+                Some(Value::Array(kinds)) => kinds.iter().any(|k| k == "JobPosting"),
+                _ => false,
+            };
+            if is_posting {
+                postings.push(value.clone());
+            } else if let Some(graph) = object.get("@graph") {
+                collect_postings(graph, postings);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Read the details from a JobPosting.
+fn from_json_ld(posting: &Value) -> Posting {
+    Posting {
+        title: text(&posting["title"]).or_else(|| text(&posting["name"])),
+        company: text(&posting["hiringOrganization"]),
+        hourly: hourly_rate(&posting["baseSalary"])
+            .or_else(|| hourly_rate(&posting["estimatedSalary"])),
+        location: location(posting),
+        link: text(&posting["url"]),
+        description: posting["description"]
+            .as_str()
+            .map(html_to_text)
+            .filter(|d| !d.is_empty()),
+    }
+}
+
+// A string, or the name of an object ("hiringOrganization": {"name": "Mozilla"}).
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(html_to_text(text)).filter(|t| !t.is_empty()),
+        Value::Object(object) => object.get("name").and_then(text),
+        Value::Array(items) => items.first().and_then(text),
+        _ => None,
+    }
+}
+
+// A number, or a number in a string ("$1,200.50").
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.replace([',', '$'], "").trim().parse().ok(),
+        _ => None,
+    }
+}
+
+// Where the job is, from its places and whether it is remote.
+fn location(posting: &Value) -> Option<String> {
+    let places = match &posting["jobLocation"] {
+        Value::Array(places) => places.iter().collect(),
+        Value::Null => Vec::new(),
+        place => vec![place],
+    };
+    let names: Vec<String> = places
+        .into_iter()
+        .filter_map(|place| match &place["address"] {
+            Value::Object(_) => {
+                let address = &place["address"];
+                let parts: Vec<String> = ["addressLocality", "addressRegion", "addressCountry"]
+                    .iter()
+                    .filter_map(|key| text(&address[*key]))
+                    .collect();
+                Some(parts.join(", ")).filter(|p| !p.is_empty())
+            }
+            address => text(address).or_else(|| text(place)),
+        })
+        .collect();
+
+    let remote = posting["jobLocationType"]
+        .as_str()
+        .is_some_and(|kind| kind.eq_ignore_ascii_case("TELECOMMUTE"));
+    match (names.is_empty(), remote) {
+        (true, true) => Some("Remote".to_string()),
+        (true, false) => None,
+        (false, true) => Some(format!("{} or Remote", names.join("; "))),
+        (false, false) => Some(names.join("; ")),
+    }
+}
+
+/// Turn the `baseSalary` of a JobPosting into an hourly rate, rounded to cents.
+///
+/// The salary is a `MonetaryAmount` whose value is a number or a `QuantitativeValue`
+/// with a `value` or a `minValue`/`maxValue` range, per the `unitText` (HOUR, DAY,
+/// WEEK, MONTH or YEAR). The lowest pay of a range is used.
+fn hourly_rate(salary: &Value) -> Option<f32> {
+    let amount = &salary["value"];
+    let pay = number(amount)
+        .or_else(|| number(&amount["value"]))
+        .or_else(|| number(&amount["minValue"]))
+        .or_else(|| number(&amount["maxValue"]))?;
+    let unit = amount["unitText"]
+        .as_str()
+        .or_else(|| salary["unitText"].as_str())
+        .map(|unit| unit.to_ascii_uppercase());
+
+    let hourly = match unit.as_deref() {
+        Some("HOUR") => pay,
+        Some("DAY") => pay / HOURS_PER_DAY,
+        Some("WEEK") => pay / HOURS_PER_WEEK,
+        Some("MONTH") => pay / HOURS_PER_MONTH,
+        Some("YEAR") => pay / HOURS_PER_YEAR,
+        _ if pay > MAX_UNLABELED_HOURLY => pay / HOURS_PER_YEAR,
+        _ => pay,
+    };
+    (hourly > 0.0).then(|| ((hourly * 100.0).round() / 100.0) as f32)
+}
+
+// The content of a `<meta>` tag by its property or name.
+fn meta_content(document: &Html, name: &str) -> Option<String> {
+    select_attr(
+        document,
+        &format!("meta[property=\"{0}\"], meta[name=\"{0}\"]", name),
+        "content",
+    )
+    .map(|content| html_to_text(&content))
+    .filter(|content| !content.is_empty())
+}
+
+// An attribute of the first element that matches a selector.
+fn select_attr(document: &Html, selector: &str, attribute: &str) -> Option<String> {
+    let selector = Selector::parse(selector).ok()?;
+    document
+        .select(&selector)
+        .find_map(|element| element.value().attr(attribute))
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+// The text of the first element that matches a selector.
+fn select_text(document: &Html, selector: &str) -> Option<String> {
+    let selector = Selector::parse(selector).ok()?;
+    let element = document.select(&selector).next()?;
+    Some(element.text().collect::<String>().trim().to_string()).filter(|t| !t.is_empty())
+}
+
+/// Turn (a piece of) HTML into plain text, with a line for each paragraph or list item.
+///
+/// The descriptions in JSON-LD are usually HTML, sometimes escaped a second time.
+pub fn html_to_text(html: &str) -> String {
+    let mut fragment = Html::parse_fragment(html);
+    // Escaped HTML ("&lt;p&gt;Build...") is parsed again once unescaped:
+    if !html.contains('<') && html.contains("&lt;") {
+        let unescaped: String = fragment.root_element().text().collect();
+        fragment = Html::parse_fragment(&unescaped);
+    }
+
+    let mut text = String::new();
+    for node in fragment.root_element().descendants() {
+        match node.value() {
+            Node::Text(t) => text.push_str(t),
+            Node::Element(element) => match element.name() {
+                "br" | "p" | "div" | "li" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                    text.push('\n');
+                    if element.name() == "li" {
+                        text.push_str("- ");
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    // Collapse the spaces in each line, and the empty lines between paragraphs:
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push(line);
+        }
+    }
+    lines.join("\n").trim().to_string()
+}
+
+/// Testing the parsing of hand-made posting pages.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A posting page like the ones most job sites make:
+    const JSON_LD_PAGE: &str = r#"<html><head>
+        <title>Careers | Mozilla</title>
+        <meta property="og:title" content="Not this title">
+        <script type="application/ld+json">{"@context": "https://schema.org", "@type": "Organization", "name": "Mozilla"}</script>
+        <script type="application/ld+json">
+        {
+            "@context": "https://schema.org/",
+            "@type": "JobPosting",
+            "title": "Rust Developer",
+            "description": "<p>Build the &amp; browser.</p><ul><li>Rust</li><li>C++</li></ul>",
+            "hiringOrganization": {"@type": "Organization", "name": "Mozilla"},
+            "jobLocation": {"@type": "Place", "address": {"@type": "PostalAddress",
+                "addressLocality": "Portland", "addressRegion": "OR", "addressCountry": "US"}},
+            "baseSalary": {"@type": "MonetaryAmount", "currency": "USD",
+                "value": {"@type": "QuantitativeValue", "minValue": 104000, "maxValue": 130000, "unitText": "YEAR"}},
+            "url": "https://example.com/jobs/1"
+        }
+        </script></head><body><h1>Rust Developer</h1></body></html>"#;
+
+    #[test]
+    fn test_parse_json_ld() {
+        let posting = parse_posting(JSON_LD_PAGE);
+        assert_eq!(posting.title.as_deref(), Some("Rust Developer"));
+        assert_eq!(posting.company.as_deref(), Some("Mozilla"));
+        assert_eq!(
+            posting.hourly,
+            Some(50.0),
+            "$104,000 a year is $50 an hour."
+        );
+        assert_eq!(posting.location.as_deref(), Some("Portland, OR, US"));
+        assert_eq!(posting.link.as_deref(), Some("https://example.com/jobs/1"));
+        assert_eq!(
+            posting.description.as_deref(),
+            Some("Build the & browser.\n- Rust\n- C++")
+        );
+
+        assert_eq!(
+            posting.full_description().as_deref(),
+            Some("Location: Portland, OR, US\n\nBuild the & browser.\n- Rust\n- C++")
+        );
+    }
+
+    // Postings in an `@graph`, with raw line breaks in the JSON and a remote job:
+    #[test]
+    fn test_parse_graph() {
+        let page = "<script type=\"application/ld+json\">{\"@graph\": [
+            {\"@type\": \"WebPage\", \"name\": \"Jobs\"},
+            {\"@type\": [\"JobPosting\"], \"title\": \"Welder\", \"hiringOrganization\": \"Daimler\",
+             \"description\": \"Line one\nline two\", \"jobLocationType\": \"TELECOMMUTE\",
+             \"baseSalary\": {\"value\": {\"value\": \"28.50\", \"unitText\": \"HOUR\"}}}
+        ]}</script>";
+        let posting = parse_posting(page);
+        assert_eq!(posting.title.as_deref(), Some("Welder"));
+        assert_eq!(posting.company.as_deref(), Some("Daimler"));
+        assert_eq!(posting.hourly, Some(28.5));
+        assert_eq!(posting.location.as_deref(), Some("Remote"));
+        assert_eq!(posting.description.as_deref(), Some("Line one line two"));
+    }
+
+    // Without JSON-LD, the meta tags and the title are used:
+    #[test]
+    fn test_parse_meta_tags() {
+        let page = r#"<html><head><title> Welder - Daimler </title>
+            <meta property="og:site_name" content="Daimler Careers">
+            <meta name="description" content="Weld trucks.">
+            <link rel="canonical" href="https://example.com/welder">
+            </head><body></body></html>"#;
+        let posting = parse_posting(page);
+        assert_eq!(posting.title.as_deref(), Some("Welder - Daimler"));
+        assert_eq!(posting.company.as_deref(), Some("Daimler Careers"));
+        assert_eq!(posting.link.as_deref(), Some("https://example.com/welder"));
+        assert_eq!(posting.description.as_deref(), Some("Weld trucks."));
+        assert_eq!(posting.hourly, None);
+
+        assert!(parse_posting("<p>Nothing here</p>").is_empty());
+        assert!(
+            parse_posting("<script type=\"application/ld+json\">{not json</script>").is_empty()
+        );
+    }
+
+    #[test]
+    fn test_hourly_rate() {
+        let rate = |salary: &str| hourly_rate(&serde_json::from_str(salary).unwrap());
+        assert_eq!(rate(r#"{"value": 25}"#), Some(25.0));
+        assert_eq!(
+            rate(r#"{"value": 52000}"#),
+            Some(25.0),
+            "A salary without a unit."
+        );
+        assert_eq!(rate(r#"{"value": 200, "unitText": "DAY"}"#), Some(25.0));
+        assert_eq!(
+            rate(r#"{"value": {"value": 1000, "unitText": "week"}}"#),
+            Some(25.0)
+        );
+        assert_eq!(
+            rate(r#"{"value": {"minValue": "$4,000", "unitText": "MONTH"}}"#),
+            Some(23.08)
+        );
+        assert_eq!(rate(r#"{"currency": "USD"}"#), None);
+        assert_eq!(rate("null"), None);
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        assert_eq!(html_to_text("Plain  text"), "Plain text");
+        assert_eq!(
+            html_to_text("&lt;p&gt;Escaped&lt;/p&gt;&lt;p&gt;twice&lt;/p&gt;"),
+            "Escaped\ntwice"
+        );
+        assert_eq!(
+            html_to_text("<h2>About</h2><p>Us</p><br><br><p>More</p>"),
+            "About\nUs\n\nMore"
+        );
+    }
+}
@@ -0,0 +1,166 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Search Module
+//!
+//! Searches the titles, companies and notes of the jobs with the full-text
+//! search index in the database. What the user typed is turned into a safe
+//! FTS5 query (so quotes or dashes never cause a syntax error), and the
+//! snippets of the results are escaped for HTML with the matching words
+//! highlighted.
+
+use crate::database_methods::{get_jobs, search_jobs, DatabaseError};
+use serde::Serialize;
+
+/// The most results shown for a search.
+pub const RESULTS_SHOWN: i64 = 50;
+
+/// The characters put around each matching word by the database. They can't
+/// be typed in the search box, so they are replaced with `<mark>` tags after
+/// the snippet is escaped.
+const OPEN_MARK: &str = "\u{2}";
+const CLOSE_MARK: &str = "\u{3}";
+
+/// A job that matches a search.
+///
+/// # Fields
+/// - `id`, `title`, `company`: The job.
+/// - `snippet`: HTML of the best matching text of the job, with the matches in `<mark>` tags.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SearchResult {
+    pub id: i64,
+    pub title: String,
+    pub company: Option<String>,
+    pub snippet: String,
+}
+
+/// Turn what a user typed into an FTS5 query that matches every word, each
+/// as a prefix ("dev port" finds "Developer in Portland").
+///
+/// # Returns
+/// * `Some(query)`, or `None` if there are no words to search for.
+pub fn fts_query(input: &str) -> Option<String> {
+    let words: Vec<String> = input
+        .split_whitespace()
+        .map(|word| word.replace('"', ""))
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// Escape a snippet for HTML, then turn the marks of the database into `<mark>` tags.
+pub fn highlight(snippet: &str) -> String {
+    snippet
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace(OPEN_MARK, "<mark>")
+        .replace(CLOSE_MARK, "</mark>")
+}
+
+/// Search a user's jobs, best matches first.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user whose jobs are searched.
+/// * `input` - What the user typed in the search box.
+///
+/// # Returns
+/// * `Ok(Vec<SearchResult>)` with the matching jobs (empty without any words).
+/// * `Err(DatabaseError)` if a query fails.
+pub fn search(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+    input: &str,
+) -> Result<Vec<SearchResult>, DatabaseError> {
+    let Some(query) = fts_query(input) else {
+        return Ok(Vec::new());
+    };
+    let hits = search_jobs(
+        connection,
+        user_id,
+        &query,
+        OPEN_MARK,
+        CLOSE_MARK,
+        RESULTS_SHOWN,
+    )?;
+    let jobs = get_jobs(connection, user_id)?;
+
+    Ok(hits
+        .into_iter()
+        .filter_map(|(id, snippet)| {
+            let job = jobs.iter().find(|job| job.get_id() == Some(id))?;
+            Some(SearchResult {
+                id,
+                title: job.get_title(),
+                company: job.get_company(),
+                snippet: highlight(&snippet),
+            })
+        })
+        .collect())
+}
+
+/// Testing the search on an in-memory database.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database_methods::{create_table, enter_data};
+    use crate::job::Job;
+    use rusqlite::Connection;
+
+    // The user the test jobs belong to:
+    const USER: i64 = 1;
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query("  "), None);
+        assert_eq!(
+            fts_query("rust dev"),
+            Some("\"rust\"* \"dev\"*".to_string())
+        );
+        assert_eq!(
+            fts_query("\"C++\" OR-"),
+            Some("\"C++\"* \"OR-\"*".to_string()),
+            "Quotes are dropped and operators are just words."
+        );
+        assert_eq!(fts_query("\"\""), None);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let snippet = format!("<b> {}Rust{} & co", OPEN_MARK, CLOSE_MARK);
+        assert_eq!(highlight(&snippet), "&lt;b&gt; <mark>Rust</mark> &amp; co");
+    }
+
+    // Searching finds jobs by the start of their words, with the matches highlighted:
+    #[test]
+    fn test_search() {
+        let connection = Connection::open_in_memory().expect("Failed to open in-memory database");
+        create_table(&connection).expect("Failed to create table");
+        for (title, company) in [("Rust Developer", "Mozilla"), ("Welder", "Daimler")] {
+            let job = Job::new(
+                None,
+                title.to_string(),
+                30.0,
+                "0".to_string(),
+                Some("https://example.com".to_string()),
+            )
+            .with_company(Some(company.to_string()));
+            enter_data(&connection, USER, &job).expect("Failed to insert job");
+        }
+
+        let results = search(&connection, USER, "dev").expect("Failed to search");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "Rust Developer");
+        assert_eq!(results[0].snippet, "Rust <mark>Developer</mark>");
+
+        assert_eq!(search(&connection, USER, "daim").unwrap()[0].id, 2);
+        assert!(search(&connection, USER, "\"unbalanced")
+            .unwrap()
+            .is_empty());
+        assert!(search(&connection, USER, "").unwrap().is_empty());
+    }
+}
@@ -6,15 +6,48 @@
 //!
 //! This module contains all the mothods used by the server to
 //! list the jobs in the database, add a jobs to the database,
-//! remove a job from the database, and change the applciation
-//! status for a job in the database.
+//! remove a job from the database, change the applciation
+//! status for a job in the database, check for duplicate jobs,
+//! preview and import an uploaded csv file, add and serve the
+//! dates (events) of each job as iCalendar files, archive and serve
+//! the posting page of each job, show the statistics of the job list,
+//! log users in and out, and manage their API tokens.
 
-use crate::database_methods::{enter_data, get_jobs, remove_data, update_applied};
+use crate::archive::{archive_link, archive_upload, HttpFetcher};
+use crate::auth::{self, create_api_token, safe_next, SESSION_COOKIE, SESSION_DAYS};
+use crate::calendar::build_calendar;
+use crate::charts::{render_chart, ChartKind};
+use crate::csrf::{CsrfToken, MAX_FORM_BYTES};
+use crate::csv_reader::{
+    import_csv, parse_csv, parse_delimiter, ConflictPolicy, ImportOptions, ImportReport,
+};
+use crate::database_methods::{
+    add_event, bulk_update, enter_data, get_api_tokens, get_archive, get_events, get_job, get_jobs,
+    remove_api_token, remove_jobs, set_goal, set_stage, update_applied, update_job, Database,
+    DatabaseError,
+};
+use crate::duplicates::find_duplicates;
+use crate::error::{ApiError, AppError};
+use crate::flash::{Flash, FlashKind, FlashMessage};
+use crate::goals::goal_progress;
+use crate::job::relative_age;
 use crate::job::Job;
-use crate::job::{ApiResponse, JobRemovalForm, JobStatusUpdate};
-use actix_web::{web, HttpResponse, Responder};
+use crate::job::{
+    ApiResponse, BulkAction, CsvImportForm, CsvUploadForm, DuplicateQuery, DuplicatesResponse,
+    EventForm, FieldErrors, GoalForm, HtmlUploadForm, JobEvent, JobForm, JobListQuery,
+    JobRemovalForm, JobSelection, JobStageUpdate, JobStatusUpdate, LoginForm, LoginQuery, Stage,
+    TokenForm, User,
+};
+use crate::links::LinkHealth;
+use crate::posting::parse_posting;
+use crate::search::search;
+use crate::stats::get_stats;
+use actix_multipart::form::{MultipartForm, MultipartFormConfig};
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::SubsecRound;
 use log::{error, info};
-use rusqlite::Connection;
 use tera::Tera;
 
 /// Remove a Job from the server.
@@ -22,99 +55,199 @@ use tera::Tera;
 /// This function:
 /// - Established a connection to the database using the database methods.
 /// - Captures the id from the HTML form that asks the user which job to delete.
-/// - Calls the database method that removes a job by id and returns to home route.
-pub async fn rem_job(form: web::Form<JobRemovalForm>) -> impl Responder {
+/// - Calls the database remove_jobs method, which removes the job with its dates,
+///   history and archived page in one transaction, and returns to home route,
+///   with a flash message saying if the job was removed, not found, or the database failed.
+pub async fn rem_job(
+    form: web::Form<JobRemovalForm>,
+    database: web::Data<Database>,
+    user: User,
+    flash: Flash,
+) -> impl Responder {
     info!("DELETE Request to Database...");
-    let database_file = "jobs_data.db";
+    let job_id = form.id;
 
-    let connection = match Connection::open(database_file) {
-        Ok(conn) => conn,
+    // Call remove method with the connection and the id captured from the html form,
+    // keeping the job to name it in the message:
+    let removed = database.open().and_then(|connection| {
+        let job = get_job(&connection, user.id, job_id)?;
+        let removed = remove_jobs(&connection, user.id, &[job_id])?;
+        Ok(job.filter(|_| removed == 1))
+    });
 
+    // Redirect to the jobs list page, which shows what happened:
+    match removed {
+        Ok(Some(job)) => {
+            info!("Successful DELETE in database.");
+            let message = format!("Removed {} (job {}).", job.get_title(), job_id);
+            flash.redirect("/", FlashKind::Success, &message)
+        }
+        Ok(None) => {
+            info!("No job with id {} found in the database.", job_id);
+            let message = format!("No job with id {}.", job_id);
+            flash.redirect("/", FlashKind::Error, &message)
+        }
         Err(err) => {
-            eprintln!("Error opening the database: {}", err);
-            return HttpResponse::InternalServerError().body("Error opening the database.");
+            error!("Error removing job {} from the database: {}", job_id, err);
+            let message = format!("Job {} could not be removed, the database failed.", job_id);
+            flash.redirect("/", FlashKind::Error, &message)
         }
-    };
+    }
+}
 
-    let job_id = form.id;
+/// Remove the Jobs Checked in the Job List:
+///
+/// This function:
+/// - Captures the ids of the checked jobs from the form (see `JobSelection`).
+/// - Calls the database remove_jobs method, which removes them all in one transaction.
+/// - Returns to the home route with a flash message saying how many jobs were removed.
+pub async fn remove_selected(
+    body: web::Bytes,
+    database: web::Data<Database>,
+    user: User,
+    flash: Flash,
+) -> impl Responder {
+    let selection = match JobSelection::from_form(&body) {
+        Ok(selection) if !selection.ids.is_empty() => selection,
+        Ok(_) => return flash.redirect("/", FlashKind::Error, "Check the jobs to remove first."),
+        Err(message) => return flash.redirect("/", FlashKind::Error, &message),
+    };
+    info!("DELETE Request of {} jobs...", selection.ids.len());
 
-    // Call remove method with the connection and the id captured from the html form:
-    match remove_data(&connection, job_id) {
-        Ok(true) => {
-            // Redirect to the jobs list page after successful form submission:
-            info!("Successful DELETE in database.");
-            HttpResponse::Found()
-                .append_header(("LOCATION", "/"))
-                .finish()
+    let removed = database
+        .open()
+        .and_then(|connection| remove_jobs(&connection, user.id, &selection.ids));
+    match removed {
+        Ok(removed) => {
+            let mut message = format!("Removed {} job{}.", removed, plural(removed));
+            let missing = selection.ids.len() - removed;
+            if missing > 0 {
+                message += &format!(" {} job{} not found.", missing, plural(missing));
+            }
+            flash.redirect("/", FlashKind::Success, &message)
         }
-
-        Ok(false) => {
-            info!("No job with id {} found in the database.", job_id);
-            HttpResponse::Found()
-                .append_header(("LOCATION", "/"))
-                .finish()
+        Err(err) => {
+            error!("Error removing jobs from the database: {}", err);
+            let message = "The jobs could not be removed, the database failed. None were removed.";
+            flash.redirect("/", FlashKind::Error, message)
         }
+    }
+}
 
-        Err(_) => {
-            eprintln!("Error removing the job from the database.");
-            HttpResponse::Found()
-                .append_header(("LOCATION", "/"))
-                .finish()
+/// Change the Jobs Checked in the Job List:
+///
+/// This function:
+/// - Captures the ids of the checked jobs and what to do with them (see `BulkAction`):
+///   move them to a stage, mark them not applied, tag, archive, unarchive or remove them.
+/// - Calls the database bulk_update method, which changes them all in one transaction.
+/// - Returns to the job list (or to the archived jobs, with `?archived=1`) with a
+///   flash message saying how many jobs were changed.
+pub async fn bulk_jobs(
+    body: web::Bytes,
+    query: web::Query<JobListQuery>,
+    database: web::Data<Database>,
+    user: User,
+    flash: Flash,
+) -> impl Responder {
+    let back = if query.archived.as_deref() == Some("1") {
+        "/?archived=1"
+    } else {
+        "/"
+    };
+    let selection = match JobSelection::from_form(&body) {
+        Ok(selection) if !selection.ids.is_empty() => selection,
+        Ok(_) => return flash.redirect(back, FlashKind::Error, "Check the jobs to change first."),
+        Err(message) => return flash.redirect(back, FlashKind::Error, &message),
+    };
+    let action = match BulkAction::from_form(&body) {
+        Ok(action) => action,
+        Err(message) => return flash.redirect(back, FlashKind::Error, &message),
+    };
+    info!("Bulk Request of {} jobs: {:?}", selection.ids.len(), action);
+
+    let changed = database
+        .open()
+        .and_then(|connection| bulk_update(&connection, user.id, &selection.ids, &action));
+    match changed {
+        Ok(changed) => {
+            let mut message = format!("{} job{} {}.", changed, plural(changed), action.done());
+            let missing = selection.ids.len() - changed;
+            if missing > 0 {
+                message += &format!(" {} job{} not found.", missing, plural(missing));
+            }
+            flash.redirect(back, FlashKind::Success, &message)
+        }
+        Err(err) => {
+            error!("Error changing jobs in the database: {}", err);
+            let message = "The jobs could not be changed, the database failed. None were changed.";
+            flash.redirect(back, FlashKind::Error, message)
         }
     }
 }
 
+// The "s" of a count of things, if it isn't one.
+fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
 /// Add a Job to the Server's Database.
 ///
 /// This function:
 /// - Connects to the database.
-/// - Gets the form data and ensures that the application status is ready to insert
-///   into the database by converting the type to a string.
-/// - Creates a new Job object using the Job constructor in the Job module.
+/// - Checks every field of the add form (see `JobForm::validate`).
+/// - Shows the job list again with the add form open, filled in as typed and
+///   with a message next to each bad field, if the form has errors.
 /// - Calls the database method to enter the job.
-/// - Checks that the result is as expected.
-pub async fn add_job(form: web::Form<Job>) -> impl Responder {
+/// - Checks that the result is as expected, and returns to the job list with
+///   a flash message saying if the job was added.
+pub async fn add_job(
+    tera: web::Data<Tera>,
+    form: web::Form<JobForm>,
+    database: web::Data<Database>,
+    user: User,
+    csrf: CsrfToken,
+    flash: Flash,
+) -> Result<HttpResponse, AppError> {
     info!("POST Request to Database...");
     info!("Received Job Form: {:?}", form);
-    // If the form has been submitted, process the data (POST)
-    // Open the SQLite database
-    let database_file = "jobs_data.db";
-    let connection = match Connection::open(database_file) {
-        Ok(conn) => conn,
-        Err(err) => {
-            eprintln!("Error opening the database: {}", err);
-            return HttpResponse::InternalServerError().body("Error opening the database.");
-        }
-    };
 
-    // Insert the new job into the database
-    let applied_int = match form.get_applied().as_str() {
-        "Yes" => 1,
-        _ => 0, // Default to "No" if somehow invalid value is sent
+    let new_job = match form.validate() {
+        Ok(job) => job,
+        Err(errors) => {
+            info!("The job form has errors: {:?}", errors);
+            let query = JobListQuery::default();
+            let add_form = Some((&*form, &errors));
+            let mut response =
+                render_job_list(&tera, &database, &user, &query, &csrf, None, add_form)?;
+            *response.status_mut() = actix_web::http::StatusCode::BAD_REQUEST;
+            return Ok(response);
+        }
     };
 
-    let new_job = Job::new(
-        None, // For autoincrement in database.
-        form.get_title().clone(),
-        form.get_hourly(),
-        applied_int.to_string(),
-        Some(form.get_link().clone()),
-    );
-    info!("Job Link: {:?}", new_job.get_link());
-
-    let result = enter_data(&connection, &new_job);
+    // If the form has been submitted, process the data (POST)
+    // Open the SQLite database
+    let added = database
+        .open()
+        .and_then(|connection| enter_data(&connection, user.id, &new_job));
 
-    match result {
-        Ok(_) => {
-            // Redirect to the jobs list page after successful form submission
+    // Redirect to the jobs list page after the form submission, which shows what happened:
+    match added {
+        Ok(job_id) => {
             info!("Successful POST to database.");
-            HttpResponse::Found()
-                .append_header(("LOCATION", "/"))
-                .finish()
+            let message = format!("Added {} (job {}).", new_job.get_title(), job_id);
+            Ok(flash.redirect("/", FlashKind::Success, &message))
         }
         Err(err) => {
-            eprintln!("Error inserting job into the database: {}", err);
-            HttpResponse::InternalServerError().body("Error inserting job into the database.")
+            error!("Error inserting job into the database: {}", err);
+            let message = format!(
+                "{} could not be added, the database failed.",
+                new_job.get_title()
+            );
+            Ok(flash.redirect("/", FlashKind::Error, &message))
         }
     }
 }
@@ -125,43 +258,407 @@ pub async fn add_job(form: web::Form<Job>) -> impl Responder {
 /// - Creates a connection to the database.
 /// - Call the get_jobs method that inserts all the jobs from the database
 ///   into the HTML front end to be displayed.
-pub async fn list_jobs(tera: web::Data<Tera>) -> impl Responder {
-    // Job application database file:
-    let database_file: &str = "jobs_data.db";
+/// - Adds the upcoming events (from today on) of every job to the page.
+/// - Sorts the jobs newest first by `?sort=created`, `?sort=updated` or
+///   `?sort=applied` (jobs without that time go last).
+/// - Searches the jobs for `?q=`, showing the results above the list.
+/// - Leaves out the archived jobs, which are listed instead with `?archived=1`.
+/// - Counts the links found dead by the last link check.
+/// - Shows who is logged in, with a button to log out.
+/// - Shows the flash message of the last change (see the flash module) once.
+pub async fn list_jobs(
+    tera: web::Data<Tera>,
+    query: web::Query<JobListQuery>,
+    database: web::Data<Database>,
+    user: User,
+    csrf: CsrfToken,
+    flash: Flash,
+) -> Result<HttpResponse, AppError> {
+    let mut response = render_job_list(
+        &tera,
+        &database,
+        &user,
+        &query,
+        &csrf,
+        flash.message(),
+        None,
+    )?;
+    flash.clear(&mut response);
+    Ok(response)
+}
+
+// Render the job list, with a flash message. The add form is filled in with
+// `add_form`, and opened with its errors (from `add_job`).
+fn render_job_list(
+    tera: &Tera,
+    database: &Database,
+    user: &User,
+    query: &JobListQuery,
+    csrf: &CsrfToken,
+    flash: Option<&FlashMessage>,
+    add_form: Option<(&JobForm, &FieldErrors)>,
+) -> Result<HttpResponse, AppError> {
+    let (blank_form, no_errors) = (JobForm::default(), FieldErrors::new());
+    let (add_form, form_errors) = add_form.unwrap_or((&blank_form, &no_errors));
 
     // Create an SQLite database file. Open the database
     // file if it already exists.
-    let connection = match Connection::open(database_file) {
-        Ok(conn) => conn,
-        Err(err) => {
-            eprintln!("Error opening the database: {}", err);
-            return HttpResponse::InternalServerError().body("Error opening the database.");
-        }
-    };
+    let connection = database.open()?;
 
-    match get_jobs(&connection) {
-        Ok(jobs) => {
-            info!("Jobs to render: {:?}", jobs); // Add this log to debug
+    let mut jobs = get_jobs(&connection, user.id)?;
+    info!("Jobs to render: {:?}", jobs); // Add this log to debug
 
-            let mut context = tera::Context::new();
-            context.insert("jobs", &jobs);
+    // Newest first, None is less than any time so it goes last:
+    match query.sort.as_deref() {
+        Some("created") => jobs.sort_by_key(|job| std::cmp::Reverse(job.get_created_at())),
+        Some("updated") => jobs.sort_by_key(|job| std::cmp::Reverse(job.get_updated_at())),
+        Some("applied") => jobs.sort_by_key(|job| std::cmp::Reverse(job.get_applied_at())),
+        _ => {}
+    }
 
-            match tera.render("jobs.html", &context) {
-                Ok(renderer) => HttpResponse::Ok().content_type("text/html").body(renderer),
-                Err(err) => {
-                    error!("Template rendering error: {:?}", err);
-                    HttpResponse::InternalServerError()
-                        .body(format!("Error rendering template: {:?}", err))
-                }
-            }
+    // The upcoming dates, with the title of their job:
+    let today = chrono::Local::now().date_naive();
+    let upcoming: Vec<serde_json::Value> = get_events(&connection, user.id, None)?
+        .into_iter()
+        .filter(|event| event.date >= today)
+        .map(|event| {
+            let title = jobs
+                .iter()
+                .find(|job| job.get_id() == Some(event.job_id))
+                .map(|job| job.get_title())
+                .unwrap_or_default();
+            serde_json::json!({
+                "job_id": event.job_id,
+                "title": title,
+                "label": event.kind.label(),
+                "date": event.date.to_string(),
+                "time": event.time.map(|t| t.format("%H:%M").to_string()),
+                "note": event.note,
+            })
+        })
+        .collect();
+
+    // Archived jobs are only listed with `?archived=1`, the other list counts them:
+    let show_archived = query.archived.as_deref() == Some("1");
+    let archived_count = jobs
+        .iter()
+        .filter(|job| job.get_archived_at().is_some())
+        .count();
+    // The tags already used, suggested in the bulk form:
+    let mut tags: Vec<String> = jobs
+        .iter()
+        .flat_map(|job| job.get_tags().iter().cloned())
+        .collect();
+    tags.sort_by_key(|tag| tag.to_lowercase());
+    tags.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+    jobs.retain(|job| job.get_archived_at().is_some() == show_archived);
+
+    let mut context = tera::Context::new();
+    context.insert("user", &user);
+    context.insert("jobs", &jobs);
+    context.insert("show_archived", &show_archived);
+    context.insert("archived_count", &archived_count);
+    context.insert("tags", &tags);
+    context.insert("upcoming", &upcoming);
+    context.insert("stages", &Stage::ALL);
+    let dead_links = jobs
+        .iter()
+        .filter_map(|job| job.get_link_status())
+        .filter(|&status| LinkHealth::of(status) == LinkHealth::Dead)
+        .count();
+    context.insert("dead_links", &dead_links);
+
+    // The progress towards the weekly goal, shown at the top of the page:
+    let today = chrono::Utc::now().date_naive();
+    let goal = goal_progress(&connection, user.id, today)?;
+    context.insert("goal", &goal);
+    context.insert("sort", &query.sort);
+    context.insert("csrf_token", &csrf.0); // For the forms (see the csrf module).
+    context.insert("add_form", add_form);
+    context.insert("form_errors", form_errors);
+    context.insert("flash", &flash);
+
+    // The search results, best matches first:
+    if let Some(q) = query.q.as_deref().filter(|q| !q.trim().is_empty()) {
+        let results = search(&connection, user.id, q)?;
+        context.insert("q", q);
+        context.insert("results", &results);
+    }
+
+    render(tera, "jobs.html", &context)
+}
+
+/// Show the Details of one Job:
+///
+/// This function:
+/// - Connects to the database.
+/// - Gets the job by the id in the url (`/jobs/3`) and its dates.
+/// - Renders the detail page, with the saved description and a form to edit the job.
+/// - Shows the flash message of the last edit once.
+pub async fn job_detail(
+    tera: web::Data<Tera>,
+    path: web::Path<i64>,
+    database: web::Data<Database>,
+    user: User,
+    csrf: CsrfToken,
+    flash: Flash,
+) -> Result<HttpResponse, AppError> {
+    let mut response = render_job_detail(
+        &tera,
+        &database,
+        &user,
+        path.into_inner(),
+        &csrf,
+        flash.message(),
+        None,
+    )?;
+    flash.clear(&mut response);
+    Ok(response)
+}
+
+// Render the detail page of a job, with a flash message. The edit form is filled
+// in with the job, or with `edit_form` and its errors when an edit failed (from `edit_job`).
+fn render_job_detail(
+    tera: &Tera,
+    database: &Database,
+    user: &User,
+    job_id: i64,
+    csrf: &CsrfToken,
+    flash: Option<&FlashMessage>,
+    edit_form: Option<(&JobForm, &FieldErrors)>,
+) -> Result<HttpResponse, AppError> {
+    let connection = database.open()?;
+
+    let job = get_job(&connection, user.id, job_id)?.ok_or(DatabaseError::JobNotFound(job_id))?;
+    let events: Vec<serde_json::Value> = get_events(&connection, user.id, Some(job_id))?
+        .into_iter()
+        .map(|event| {
+            serde_json::json!({
+                "label": event.kind.label(),
+                "date": event.date.to_string(),
+                "time": event.time.map(|t| t.format("%H:%M").to_string()),
+                "note": event.note,
+            })
+        })
+        .collect();
+
+    let archive = get_archive(&connection, user.id, job_id)?;
+
+    let mut context = tera::Context::new();
+    context.insert("job", &job);
+    context.insert("events", &events);
+    context.insert("csrf_token", &csrf.0);
+    context.insert("flash", &flash);
+    match edit_form {
+        Some((form, errors)) => {
+            context.insert("edit_form", form);
+            context.insert("form_errors", errors);
+        }
+        None => {
+            context.insert("edit_form", &JobForm::from(&job));
+            context.insert("form_errors", &FieldErrors::new());
+        }
+    }
+    if let Some(archive) = archive {
+        context.insert("archive_source", &archive.source);
+        context.insert("archive_saved_at", &archive.saved_at);
+    }
+    render(tera, "job.html", &context)
+}
+
+/// Edit a Job from its Detail Page:
+///
+/// This function:
+/// - Captures the edit form's info (the same fields as the add form), and checks
+///   every field like `add_job`, showing the page again with the errors.
+/// - Calls the database update_job method to replace the job's details, which
+///   also keeps the application time and stage history in step with the applied status.
+/// - Redirects back to the detail page, with a flash message saying if the job was saved.
+pub async fn edit_job(
+    tera: web::Data<Tera>,
+    path: web::Path<i64>,
+    form: web::Form<JobForm>,
+    database: web::Data<Database>,
+    user: User,
+    csrf: CsrfToken,
+    flash: Flash,
+) -> Result<HttpResponse, AppError> {
+    let job_id = path.into_inner();
+    info!("POST Edit of job {}: {:?}", job_id, form);
+    let edited_job = match form.validate() {
+        Ok(job) => job,
+        Err(errors) => {
+            let edit_form = Some((&*form, &errors));
+            let mut response =
+                render_job_detail(&tera, &database, &user, job_id, &csrf, None, edit_form)?;
+            *response.status_mut() = actix_web::http::StatusCode::BAD_REQUEST;
+            return Ok(response);
+        }
+    };
+
+    let updated = database
+        .open()
+        .and_then(|connection| update_job(&connection, user.id, job_id, &edited_job));
+    let back = format!("/jobs/{}", job_id);
+    match updated {
+        Ok(true) => {
+            let message = format!("Saved {} (job {}).", edited_job.get_title(), job_id);
+            Ok(flash.redirect(&back, FlashKind::Success, &message))
         }
+        Ok(false) => Err(DatabaseError::JobNotFound(job_id).into()),
         Err(err) => {
-            error!("Error fetching jobs: {}", err);
-            HttpResponse::InternalServerError().body("Error fetching jobs.")
+            error!("Error updating job {}: {}", job_id, err);
+            let message = format!(
+                "{} could not be saved, the database failed.",
+                edited_job.get_title()
+            );
+            Ok(flash.redirect(&back, FlashKind::Error, &message))
         }
     }
 }
 
+/// Show the Archived Posting Page of a Job:
+///
+/// The sanitized copy is shown inside a page that says where and when it was saved.
+/// The Content-Security-Policy header stops the page from running anything, even if
+/// the sanitizing missed something.
+pub async fn job_archive(
+    tera: web::Data<Tera>,
+    path: web::Path<i64>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, AppError> {
+    let job_id = path.into_inner();
+    let connection = database.open()?;
+
+    let job = get_job(&connection, user.id, job_id)?.ok_or(DatabaseError::JobNotFound(job_id))?;
+    let archive = get_archive(&connection, user.id, job_id)?
+        .ok_or_else(|| AppError::NotFound(format!("Job {} has no archived page.", job_id)))?;
+
+    let mut context = tera::Context::new();
+    context.insert("job", &job);
+    context.insert("archive", &archive);
+    let mut response = render(&tera, "archive.html", &context)?;
+    response.headers_mut().insert(
+        header::CONTENT_SECURITY_POLICY,
+        header::HeaderValue::from_static(
+            "default-src 'none'; style-src 'self'; img-src http: https: data:",
+        ),
+    );
+    Ok(response)
+}
+
+/// Archive an Uploaded Posting Page:
+///
+/// This function:
+/// - Captures the HTML file saved from the browser, from the form on the detail page.
+/// - Sanitizes and saves it with the archive module, replacing the older copy.
+/// - Redirects back to the detail page.
+pub async fn upload_archive(
+    path: web::Path<i64>,
+    MultipartForm(form): MultipartForm<HtmlUploadForm>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, AppError> {
+    let job_id = path.into_inner();
+    info!(
+        "Archive upload of {} bytes for job {}.",
+        form.file.data.len(),
+        job_id
+    );
+    let html = String::from_utf8_lossy(&form.file.data).into_owned();
+    let file_name = form
+        .file
+        .file_name
+        .unwrap_or_else(|| "uploaded page".to_string());
+
+    let connection = database.open()?;
+    archive_upload(&connection, user.id, job_id, &html, &file_name)?;
+    Ok(archived(job_id))
+}
+
+/// Fetch and Archive a Job's Posting Page:
+///
+/// This function:
+/// - Fetches the page at the job's link (on a worker thread, it can take a while).
+/// - Sanitizes and saves it with the archive module, replacing the older copy.
+/// - Redirects back to the detail page.
+pub async fn fetch_archive(
+    path: web::Path<i64>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, AppError> {
+    let job_id = path.into_inner();
+    info!("Fetching the posting page of job {}...", job_id);
+    web::block(move || {
+        let connection = database.open()?;
+        archive_link(&connection, user.id, job_id, &HttpFetcher::default())
+    })
+    .await??;
+    Ok(archived(job_id))
+}
+
+/// Read the Details of a Job from a Posting Page:
+///
+/// The add form uploads a saved posting page here, and fills its fields in with
+/// the details returned as JSON (`found` is false when the page had none).
+pub async fn parse_posting_upload(
+    MultipartForm(form): MultipartForm<HtmlUploadForm>,
+) -> impl Responder {
+    info!("Posting upload of {} bytes.", form.file.data.len());
+    let posting = parse_posting(&String::from_utf8_lossy(&form.file.data));
+    HttpResponse::Ok().json(serde_json::json!({
+        "found": !posting.is_empty(),
+        "title": posting.title,
+        "company": posting.company,
+        "hourly": posting.hourly,
+        "link": posting.link,
+        "description": posting.full_description(),
+    }))
+}
+
+// Redirect to the detail page once a page is archived.
+fn archived(job_id: i64) -> HttpResponse {
+    HttpResponse::Found()
+        .append_header(("LOCATION", format!("/jobs/{}", job_id)))
+        .finish()
+}
+
+/// Tera filter that shows a job's timestamp as its age ("3 days ago").
+///
+/// Used as `{{ job.created_at | age }}` in the templates. A missing
+/// timestamp (jobs from before the timestamps were kept) shows as "-".
+pub fn age_filter(
+    value: &tera::Value,
+    _args: &std::collections::HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let age = match value.as_str() {
+        Some(text) => {
+            let then: chrono::NaiveDateTime = text
+                .parse()
+                .map_err(|e| tera::Error::msg(format!("Bad timestamp {}: {}", text, e)))?;
+            relative_age(then, chrono::Utc::now().naive_utc())
+        }
+        None => "-".to_string(),
+    };
+    Ok(tera::Value::String(age))
+}
+
+/// Tera filter that names the health of a job's checked link ("ok", "blocked" or "dead").
+///
+/// Used as `{{ job.link_status | link_health }}` in the templates. A link
+/// that was never checked gives an empty string.
+pub fn link_health_filter(
+    value: &tera::Value,
+    _args: &std::collections::HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let health = value
+        .as_u64()
+        .and_then(|status| u16::try_from(status).ok())
+        .map_or("", |status| LinkHealth::of(status).name());
+    Ok(tera::Value::String(health.to_string()))
+}
+
 /// Update a Jobs Application Status in the Database:
 ///
 /// This function:
@@ -171,36 +668,1537 @@ pub async fn list_jobs(tera: web::Data<Tera>) -> impl Responder {
 ///
 /// This method returns JSON to the front end Javascript function so that the
 /// application status can be updated automatically with a color change.
-pub async fn update(form: web::Json<JobStatusUpdate>) -> impl Responder {
-    println!(
+pub async fn update(
+    form: web::Json<JobStatusUpdate>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, ApiError> {
+    info!(
         "Received update request: id={}, applied={}",
         form.id, form.applied
     );
-    // Job application database file:
-    let database_file: &str = "jobs_data.db";
-
     // Create an SQLite database file. Open the database:
     // file if it already exists.
-    let connection = match Connection::open(database_file) {
-        Ok(conn) => conn,
-        Err(err) => {
-            eprintln!("Error opening the database: {}", err);
-            return HttpResponse::InternalServerError().body("Error opening the database.");
-        }
-    };
+    let connection = database.open()?;
 
     let job_id = form.id;
     let job_applied = form.applied;
 
-    //let result = update_applied(&connection, job_applied, job_id);
-    match update_applied(&connection, job_applied, job_id) {
-        Ok(_) => {
-            info!("Successfully updated application status in database.");
-            HttpResponse::Ok().json(ApiResponse { success: true }) // Return JSON to the JS Method.
+    if !update_applied(&connection, user.id, job_applied, job_id)? {
+        info!("No job with id {} found for {}.", job_id, user.username);
+        return Err(DatabaseError::JobNotFound(job_id).into());
+    }
+    info!("Successfully updated application status in database.");
+    Ok(HttpResponse::Ok().json(ApiResponse { success: true })) // Return JSON to the JS Method.
+}
+
+/// Set the Weekly Application Goal:
+///
+/// This function:
+/// - Captures the goal from the form on the job list.
+/// - Calls the database set_goal method, which keeps the old goals for the streaks.
+/// - Redirects back to the job list.
+pub async fn set_goal_form(
+    form: web::Form<GoalForm>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, AppError> {
+    if form.target < 1 {
+        return Err(AppError::BadRequest(
+            "The goal must be at least 1 application.".to_string(),
+        ));
+    }
+
+    let connection = database.open()?;
+    set_goal(&connection, user.id, form.target)?;
+    info!("Set the weekly goal to {} applications.", form.target);
+    Ok(HttpResponse::Found()
+        .append_header(("LOCATION", "/"))
+        .finish())
+}
+
+/// Move a Job to a New Stage of its Application:
+///
+/// This function:
+/// - Connects to the database.
+/// - Captures the job id and the new stage as JSON from the front-end.
+/// - Calls the database set_stage method, which keeps the change in the status history.
+///
+/// This method returns JSON to the front end Javascript function, like `update`.
+pub async fn update_stage(
+    form: web::Json<JobStageUpdate>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, ApiError> {
+    let connection = database.open()?;
+
+    if !set_stage(&connection, user.id, form.id, form.stage)? {
+        return Err(DatabaseError::JobNotFound(form.id).into());
+    }
+    info!("Moved job {} to stage {}.", form.id, form.stage.name());
+    Ok(HttpResponse::Ok().json(ApiResponse { success: true }))
+}
+
+/// Check for Duplicates of a Job Before it is Added:
+///
+/// This function:
+/// - Connects to the database.
+/// - Captures the add form's title, company and link as JSON from the front-end.
+/// - Calls the duplicates module to find jobs that are the same posting.
+///
+/// This method returns JSON so the add form can warn the user before the job is added.
+pub async fn check_duplicates(
+    form: web::Json<DuplicateQuery>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, ApiError> {
+    let connection = database.open()?;
+
+    let query = form.into_inner();
+    let job =
+        Job::new(None, query.title, 0.0, "0".to_string(), query.link).with_company(query.company);
+
+    let duplicates = find_duplicates(&connection, user.id, &job)?;
+    info!("Found {} possible duplicates.", duplicates.len());
+    Ok(HttpResponse::Ok().json(DuplicatesResponse { duplicates }))
+}
+
+// Build the import options from the delimiter and conflict policy of the upload forms.
+fn upload_options(delimiter: Option<&str>, on_conflict: Option<&str>) -> ImportOptions {
+    ImportOptions {
+        delimiter: delimiter.and_then(parse_delimiter).unwrap_or(b','),
+        on_conflict: on_conflict
+            .and_then(ConflictPolicy::from_name)
+            .unwrap_or_default(),
+        ..ImportOptions::default()
+    }
+}
+
+/// Preview an Uploaded CSV File:
+///
+/// This function:
+/// - Captures the csv file and import options from the multipart upload form.
+/// - Parses every row with the csv_reader module, keeping the errors for each line.
+/// - Does a dry run of the import to count what would be imported, updated and skipped.
+/// - Renders the preview page, which sends the csv data back to `upload_import` to confirm.
+pub async fn upload_preview(
+    tera: web::Data<Tera>,
+    MultipartForm(form): MultipartForm<CsvUploadForm>,
+    database: web::Data<Database>,
+    user: User,
+    csrf: CsrfToken,
+) -> Result<HttpResponse, AppError> {
+    info!("CSV upload of {} bytes.", form.file.data.len());
+    let data = String::from_utf8(form.file.data.to_vec())
+        .map_err(|_| AppError::BadRequest("The csv file must be UTF-8 text.".to_string()))?;
+    let delimiter = form.delimiter.as_ref().map(|d| d.as_str());
+    let on_conflict = form.on_conflict.as_ref().map(|p| p.as_str());
+    let options = upload_options(delimiter, on_conflict);
+
+    let connection = database.open()?;
+
+    // Parse the rows for the preview, then do a dry run to find conflicts:
+    let preview = parse_csv(&mut options.reader(data.as_bytes()), &options.mapping);
+    let dry_run = ImportOptions {
+        dry_run: true,
+        ..options.clone()
+    };
+    let report = import_csv(
+        options.reader(data.as_bytes()),
+        &connection,
+        user.id,
+        &dry_run,
+    );
+
+    // Errors in the file are shown on the preview page, to fix and upload it again:
+    let mut context = tera::Context::new();
+    match (preview, report) {
+        (Ok((columns, rows)), Ok(report)) => {
+            let rows: Vec<serde_json::Value> = rows
+                .iter()
+                .map(|row| match &row.job {
+                    Ok(job) => serde_json::json!({ "line": row.line, "job": job }),
+                    Err(error) => serde_json::json!({ "line": row.line, "error": error }),
+                })
+                .collect();
+            let conflicts: Vec<String> = report.conflicts.iter().map(|c| c.to_string()).collect();
+            context.insert("columns", &columns.to_string());
+            context.insert("rows", &rows);
+            context.insert("conflicts", &conflicts);
+            context.insert("imported", &report.imported);
+            context.insert("updated", &report.updated);
+            context.insert("skipped", &report.skipped);
+        }
+        (Err(err), _) | (_, Err(err)) => context.insert("error", &err.to_string()),
+    }
+    context.insert("data", &data);
+    context.insert("delimiter", delimiter.unwrap_or(","));
+    context.insert("on_conflict", on_conflict.unwrap_or("skip"));
+    context.insert("csrf_token", &csrf.0);
+
+    render(&tera, "import.html", &context)
+}
+
+/// Import a Previewed CSV File:
+///
+/// This function:
+/// - Captures the csv data and options confirmed on the preview page.
+/// - Imports the jobs with the csv_reader module.
+/// - Redirects to the home route with a flash message saying how many jobs were
+///   imported, updated and skipped, and why rows were rejected.
+pub async fn upload_import(
+    form: web::Form<CsvImportForm>,
+    database: web::Data<Database>,
+    user: User,
+    flash: Flash,
+) -> Result<HttpResponse, AppError> {
+    info!("CSV Import Request to Database...");
+    let connection = database.open()?;
+
+    let options = ImportOptions {
+        atomic: form.atomic.is_some(),
+        ..upload_options(form.delimiter.as_deref(), form.on_conflict.as_deref())
+    };
+
+    match import_csv(
+        options.reader(form.data.as_bytes()),
+        &connection,
+        user.id,
+        &options,
+    ) {
+        Ok(report) => {
+            info!("CSV import: {}", report);
+            let (kind, message) = import_message(&report);
+            Ok(flash.redirect("/", kind, &message))
         }
         Err(err) => {
-            eprintln!("Error updating application status in database: {}", err);
-            HttpResponse::InternalServerError().json(ApiResponse { success: false })
+            error!("Error importing the csv file: {}", err);
+            let message = format!("Nothing was imported: {}.", err);
+            Ok(flash.redirect("/", FlashKind::Error, &message))
         }
     }
 }
+
+// The flash message of a csv import. Only the first row errors fit in the cookie:
+fn import_message(report: &ImportReport) -> (FlashKind, String) {
+    const SHOWN_ERRORS: usize = 3;
+    let mut message = if report.committed {
+        format!(
+            "Imported {} jobs, updated {} and skipped {} rows.",
+            report.imported, report.updated, report.skipped
+        )
+    } else {
+        format!(
+            "Nothing was imported, {} rows have errors.",
+            report.errors.len()
+        )
+    };
+    for error in report.errors.iter().take(SHOWN_ERRORS) {
+        message.push_str(&format!(" Line {}: {}.", error.line, error.message));
+    }
+    if report.errors.len() > SHOWN_ERRORS {
+        message.push_str(&format!(
+            " And {} more.",
+            report.errors.len() - SHOWN_ERRORS
+        ));
+    }
+    let kind = if report.errors.is_empty() {
+        FlashKind::Success
+    } else {
+        FlashKind::Error
+    };
+    (kind, message)
+}
+
+/// Add a Date to a Job:
+///
+/// This function:
+/// - Captures the job id, kind, date, time and note from the HTML date form.
+/// - Validates them into a JobEvent.
+/// - Calls the database add_event method and returns to the home route.
+pub async fn add_event_form(
+    form: web::Form<EventForm>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, AppError> {
+    info!("POST Event Request to Database...");
+    let event = JobEvent::parse(
+        form.job_id,
+        &form.kind,
+        &form.date,
+        form.time.as_deref(),
+        form.note.as_deref(),
+    )
+    .map_err(AppError::BadRequest)?;
+
+    let connection = database.open()?;
+    let id =
+        add_event(&connection, user.id, &event)?.ok_or(DatabaseError::JobNotFound(event.job_id))?;
+    info!("Added event {} to job {}.", id, event.job_id);
+    Ok(HttpResponse::Found()
+        .append_header(("LOCATION", "/"))
+        .finish())
+}
+
+// Build the calendar of one job (or every job) of a user as an HTTP response.
+fn calendar_response(
+    database: &Database,
+    user_id: i64,
+    job_id: Option<i64>,
+) -> Result<HttpResponse, AppError> {
+    let connection = database.open()?;
+    let events = get_events(&connection, user_id, job_id)?;
+    let jobs = get_jobs(&connection, user_id)?;
+
+    let mut response = HttpResponse::Ok();
+    response.content_type("text/calendar; charset=utf-8");
+    let name = match job_id {
+        Some(id) => {
+            if !jobs.iter().any(|job| job.get_id() == Some(id)) {
+                return Err(DatabaseError::JobNotFound(id).into());
+            }
+            response.append_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"job-{}.ics\"", id),
+            ));
+            format!("Job {}", id)
+        }
+        None => "Job Applications".to_string(),
+    };
+
+    Ok(response.body(build_calendar(&name, &events, &jobs, chrono::Utc::now())))
+}
+
+/// The iCalendar Feed of Every Job's Dates:
+///
+/// Calendar apps can subscribe to this feed. Each event keeps the same UID
+/// every time, so changed events are updated instead of duplicated.
+pub async fn calendar_feed(
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, AppError> {
+    info!("GET Calendar Feed...");
+    calendar_response(&database, user.id, None)
+}
+
+/// Download the iCalendar File of One Job's Dates.
+pub async fn job_calendar(
+    path: web::Path<i64>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, AppError> {
+    let job_id = path.into_inner();
+    info!("GET Calendar for job {}...", job_id);
+    calendar_response(&database, user.id, Some(job_id))
+}
+
+/// Show the Statistics Page:
+///
+/// This method:
+/// - Creates a connection to the database.
+/// - Gathers the statistics with the stats module.
+/// - Renders them into the stats HTML page.
+pub async fn stats_page(
+    tera: web::Data<Tera>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, AppError> {
+    let connection = database.open()?;
+    let stats = get_stats(&connection, user.id)?;
+
+    // The charts are drawn here, so the page needs no JavaScript to show them:
+    let mut charts: Vec<serde_json::Value> = Vec::new();
+    for kind in ChartKind::ALL {
+        let svg = render_chart(&connection, user.id, kind)?;
+        charts.push(serde_json::json!({ "name": kind.name(), "svg": svg }));
+    }
+
+    let mut context = tera::Context::new();
+    context.insert("stats", &stats);
+    context.insert("charts", &charts);
+    render(&tera, "stats.html", &context)
+}
+
+/// Return the Statistics as JSON.
+pub async fn api_stats(
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, ApiError> {
+    let connection = database.open()?;
+    Ok(HttpResponse::Ok().json(get_stats(&connection, user.id)?))
+}
+
+/// Return the Jobs as JSON, for scripts using an API token.
+pub async fn api_jobs(database: web::Data<Database>, user: User) -> Result<HttpResponse, ApiError> {
+    let connection = database.open()?;
+    Ok(HttpResponse::Ok().json(get_jobs(&connection, user.id)?))
+}
+
+/// Return a Chart of the Statistics as an SVG Image:
+///
+/// The chart is picked by the name in the url (`/charts/pay.svg`). With
+/// `?download` in the url, the browser saves it as a file.
+pub async fn chart_svg(
+    path: web::Path<String>,
+    query: web::Query<std::collections::HashMap<String, String>>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, AppError> {
+    let kind = ChartKind::from_name(&path)
+        .ok_or_else(|| AppError::NotFound("No chart with that name.".to_string()))?;
+
+    let connection = database.open()?;
+    let svg = render_chart(&connection, user.id, kind)?;
+
+    let mut response = HttpResponse::Ok();
+    response.content_type("image/svg+xml");
+    if query.contains_key("download") {
+        response.insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.svg\"", kind.name()),
+        ));
+    }
+    Ok(response.body(svg))
+}
+
+/// Show the Login Page:
+///
+/// Pages asked for without a login redirect here, with the page in `?next=`
+/// so the login can go back to it.
+pub async fn login_page(
+    tera: web::Data<Tera>,
+    query: web::Query<LoginQuery>,
+) -> Result<HttpResponse, AppError> {
+    render_login(&tera, safe_next(query.next.as_deref()), None, None)
+}
+
+/// Log In:
+///
+/// This function:
+/// - Checks the username and password of the login form.
+/// - Starts a session and keeps its token in an HttpOnly cookie for 30 days.
+/// - Redirects to the page asked for before logging in (only pages of this site).
+/// - Shows the login page again with an error if the username or password is wrong.
+pub async fn login(
+    tera: web::Data<Tera>,
+    form: web::Form<LoginForm>,
+    database: web::Data<Database>,
+) -> Result<HttpResponse, AppError> {
+    let next = safe_next(form.next.as_deref());
+    let connection = database.open()?;
+
+    let now = chrono::Utc::now().naive_utc();
+    match auth::login(&connection, &form.username, &form.password, now)? {
+        Some(token) => {
+            info!("{} logged in", form.username.trim());
+            let cookie = Cookie::build(SESSION_COOKIE, token)
+                .path("/")
+                .http_only(true)
+                .same_site(SameSite::Lax)
+                .max_age(CookieDuration::days(SESSION_DAYS))
+                .finish();
+            Ok(HttpResponse::SeeOther()
+                .cookie(cookie)
+                .append_header(("LOCATION", next))
+                .finish())
+        }
+        None => {
+            info!("Failed login for {}", form.username.trim());
+            let mut response = render_login(
+                &tera,
+                next,
+                Some(form.username.trim()),
+                Some("Wrong username or password."),
+            )?;
+            *response.status_mut() = actix_web::http::StatusCode::UNAUTHORIZED;
+            Ok(response)
+        }
+    }
+}
+
+/// Log Out:
+///
+/// Ends the session of the cookie, removes the cookie and goes to the login page.
+/// The cookie is removed even if the session can't be ended in the database.
+pub async fn logout(request: HttpRequest, database: web::Data<Database>) -> impl Responder {
+    if let Some(cookie) = request.cookie(SESSION_COOKIE) {
+        match database
+            .open()
+            .and_then(|connection| auth::logout(&connection, cookie.value()))
+        {
+            Ok(_) => info!("Logged out"),
+            Err(err) => error!("Error ending the session: {}", err),
+        }
+    }
+
+    let mut removal = Cookie::build(SESSION_COOKIE, "").path("/").finish();
+    removal.make_removal();
+    HttpResponse::SeeOther()
+        .cookie(removal)
+        .append_header(("LOCATION", "/login"))
+        .finish()
+}
+
+/// Show the API Tokens Page:
+///
+/// Lists the user's API tokens, with a form to make a new one.
+pub async fn tokens_page(
+    request: HttpRequest,
+    tera: web::Data<Tera>,
+    database: web::Data<Database>,
+    user: User,
+    csrf: CsrfToken,
+) -> Result<HttpResponse, AppError> {
+    render_tokens(&request, &tera, &database, &user, &csrf, None, None)
+}
+
+/// Make a New API Token:
+///
+/// The tokens page is shown again with the new token, the only time it can be seen.
+pub async fn create_token(
+    request: HttpRequest,
+    tera: web::Data<Tera>,
+    form: web::Form<TokenForm>,
+    database: web::Data<Database>,
+    user: User,
+    csrf: CsrfToken,
+) -> Result<HttpResponse, AppError> {
+    let connection = database.open()?;
+
+    // To the second, like the other times:
+    let now = chrono::Utc::now().naive_utc().trunc_subsecs(0);
+    match create_api_token(&connection, user.id, &form.name, form.scope, now) {
+        Ok(token) => {
+            info!(
+                "{} made the {} API token {}",
+                user.username,
+                form.scope.name(),
+                form.name.trim()
+            );
+            render_tokens(&request, &tera, &database, &user, &csrf, Some(&token), None)
+        }
+        Err(err @ auth::AuthError::BadTokenName) => {
+            let mut response = render_tokens(
+                &request,
+                &tera,
+                &database,
+                &user,
+                &csrf,
+                None,
+                Some(&err.to_string()),
+            )?;
+            *response.status_mut() = actix_web::http::StatusCode::BAD_REQUEST;
+            Ok(response)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Revoke an API Token:
+///
+/// Scripts using the token stop working at once. Redirects back to the tokens page.
+pub async fn revoke_token(
+    path: web::Path<i64>,
+    database: web::Data<Database>,
+    user: User,
+) -> Result<HttpResponse, AppError> {
+    let token_id = path.into_inner();
+    let connection = database.open()?;
+
+    if !remove_api_token(&connection, user.id, token_id)? {
+        return Err(AppError::NotFound("No API token with that id.".to_string()));
+    }
+    info!("{} revoked API token {}", user.username, token_id);
+    Ok(HttpResponse::SeeOther()
+        .append_header(("LOCATION", "/tokens"))
+        .finish())
+}
+
+// Render the tokens page, with a token that was just made or an error.
+fn render_tokens(
+    request: &HttpRequest,
+    tera: &Tera,
+    database: &Database,
+    user: &User,
+    csrf: &CsrfToken,
+    new_token: Option<&str>,
+    error: Option<&str>,
+) -> Result<HttpResponse, AppError> {
+    let tokens = get_api_tokens(&database.open()?, user.id)?;
+
+    let mut context = tera::Context::new();
+    context.insert("tokens", &tokens);
+    context.insert("new_token", &new_token);
+    context.insert("csrf_token", &csrf.0);
+    context.insert("error", &error);
+    context.insert("host", request.connection_info().host());
+    render(tera, "tokens.html", &context)
+}
+
+// Render the login page, with the page to go back to and an error if the last try failed.
+fn render_login(
+    tera: &Tera,
+    next: &str,
+    username: Option<&str>,
+    error: Option<&str>,
+) -> Result<HttpResponse, AppError> {
+    let mut context = tera::Context::new();
+    context.insert("next", next);
+    context.insert("username", &username);
+    context.insert("error", &error);
+    render(tera, "login.html", &context)
+}
+
+/// The size limit of the posted forms, larger than actix's default for a
+/// previewed csv file sent back to be imported, or a job description as long
+/// as `MAX_DESCRIPTION_LENGTH` (so the form shows its error, not a 413).
+pub fn form_config() -> web::FormConfig {
+    web::FormConfig::default().limit(MAX_FORM_BYTES)
+}
+
+/// The size limits of the uploaded files, larger than actix's default so the
+/// 5MB csv and page uploads fit.
+pub fn upload_config() -> MultipartFormConfig {
+    MultipartFormConfig::default()
+        .total_limit(MAX_FORM_BYTES)
+        .memory_limit(MAX_FORM_BYTES)
+}
+
+// Render a page of the templates directory.
+fn render(tera: &Tera, template: &str, context: &tera::Context) -> Result<HttpResponse, AppError> {
+    let page = tera.render(template, context)?;
+    Ok(HttpResponse::Ok().content_type("text/html").body(page))
+}
+
+/// Testing the handlers through the login middleware, with a database in memory.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{create_api_token, new_token, require_login, token_hash};
+    use crate::csrf::{csrf_token, verify_csrf, verify_origin, CSRF_HEADER};
+    use crate::database_methods::{add_session, add_user, create_table};
+    use crate::flash::FLASH_COOKIE;
+    use crate::job::{TokenScope, MAX_DESCRIPTION_LENGTH};
+    use actix_web::http::header::ContentType;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+
+    // Add a user with a session, returning their id and session token.
+    fn log_in(connection: &rusqlite::Connection, username: &str) -> (i64, String) {
+        let id = add_user(connection, username, "hash").unwrap().unwrap();
+        let token = new_token();
+        let now = chrono::Utc::now().naive_utc();
+        add_session(
+            connection,
+            &token_hash(&token),
+            id,
+            now,
+            now + chrono::Duration::days(1),
+        )
+        .unwrap();
+        (id, token)
+    }
+
+    // Guessing the id of another user's job can't remove or update it through `/rem` or `/update`:
+    #[actix_web::test]
+    async fn test_other_users_jobs() {
+        // A shared in-memory database, kept alive by this connection:
+        let database = Database::new("file:server_test_other_users?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (alice, alice_token) = log_in(&connection, "alice");
+        let (_, bob_token) = log_in(&connection, "bob");
+        let job = Job::new(
+            None,
+            "Welder".to_string(),
+            28.0,
+            "0".to_string(),
+            Some("https://example.com/welder".to_string()),
+        );
+        let job_id = enter_data(&connection, alice, &job).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(database.clone()))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(require_login))
+                        .route("/rem", web::post().to(rem_job))
+                        .route("/update", web::post().to(update)),
+                ),
+        )
+        .await;
+        let rem = |token: &str| {
+            test::TestRequest::post()
+                .uri("/rem")
+                .cookie(Cookie::new(SESSION_COOKIE, token.to_string()))
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload(format!("id={}", job_id))
+                .to_request()
+        };
+        let update = |token: &str| {
+            test::TestRequest::post()
+                .uri("/update")
+                .cookie(Cookie::new(SESSION_COOKIE, token.to_string()))
+                .set_json(serde_json::json!({ "id": job_id, "applied": true }))
+                .to_request()
+        };
+
+        // Bob's requests find no such job, and change nothing:
+        let response = test::call_service(&app, update(&bob_token)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = test::call_service(&app, rem(&bob_token)).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let kept = get_job(&connection, alice, job_id)
+            .unwrap()
+            .expect("Bob removed it");
+        assert_eq!(kept.get_applied(), "No");
+
+        // Without a login, nothing is changed either:
+        let response = test::call_service(&app, rem("not a session")).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert!(get_job(&connection, alice, job_id).unwrap().is_some());
+
+        // Alice can do both:
+        let response = test::call_service(&app, update(&alice_token)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            get_job(&connection, alice, job_id)
+                .unwrap()
+                .unwrap()
+                .get_applied(),
+            "Yes"
+        );
+        let response = test::call_service(&app, rem(&alice_token)).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert!(get_job(&connection, alice, job_id).unwrap().is_none());
+    }
+
+    // A job the database can't read is reported, as a page or as JSON:
+    #[actix_web::test]
+    async fn test_database_errors() {
+        let database = Database::new("file:server_test_errors?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (user, token) = log_in(&connection, "alice");
+        connection
+            .execute(
+                "INSERT INTO jobs (job_title, hourly_rate, applied, user_id)
+                VALUES ('Welder', 'lots', 0, ?)",
+                [user],
+            )
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(database.clone()))
+                .app_data(web::Data::new(templates()))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(require_login))
+                        .route("/", web::get().to(list_jobs))
+                        .route("/api/jobs", web::get().to(api_jobs)),
+                ),
+        )
+        .await;
+        let get = |uri: &str| {
+            test::TestRequest::get()
+                .uri(uri)
+                .cookie(Cookie::new(SESSION_COOKIE, token.clone()))
+                .to_request()
+        };
+
+        let response = test::call_service(&app, get("/")).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let page = String::from_utf8_lossy(&test::read_body(response).await).into_owned();
+        assert!(page.contains("Error reading or writing the database."));
+        assert!(page.contains("Back to the job list"));
+
+        let response = test::call_service(&app, get("/api/jobs")).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let json: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(json["success"], false);
+    }
+
+    // The templates, as the server loads them:
+    fn templates() -> Tera {
+        let mut tera = Tera::new("templates/**/*").expect("Failed to load the templates");
+        tera.register_filter("age", age_filter);
+        tera.register_filter("link_health", link_health_filter);
+        tera
+    }
+
+    // A bad add form shows the list again with its errors, and adds nothing:
+    #[actix_web::test]
+    async fn test_add_job_validation() {
+        let database = Database::new("file:server_test_add_job?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (alice, session) = log_in(&connection, "alice");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(templates()))
+                .app_data(web::Data::new(database.clone()))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(require_login))
+                        .route("/add", web::post().to(add_job)),
+                ),
+        )
+        .await;
+        let add = |body: &str| {
+            test::TestRequest::post()
+                .uri("/add")
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload(body.to_string())
+                .to_request()
+        };
+
+        let response = test::call_service(
+            &app,
+            add("title=Welder&hourly=-3&applied=Maybe&link=not%20a%20link&company=Daimler"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let page = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(page.contains("The hourly rate can&#x27;t be negative."));
+        assert!(page.contains("Pick Yes or No."));
+        assert!(page.contains("The link must be a web address"));
+        assert!(
+            page.contains(r#"value="Daimler""#),
+            "The form keeps what was typed."
+        );
+        assert!(get_jobs(&connection, alice).unwrap().is_empty());
+
+        // Missing fields and an empty link don't panic either:
+        let response = test::call_service(&app, add("")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response =
+            test::call_service(&app, add("title=Welder&hourly=28&applied=No&link=")).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let jobs = get_jobs(&connection, alice).unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].get_optional_link(), None);
+    }
+
+    // A description up to the limit goes through the CSRF check and the form's size
+    // limit, and one over it gets its message in the form:
+    #[actix_web::test]
+    async fn test_long_description() {
+        let database = Database::new("file:server_test_long_description?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (alice, session) = log_in(&connection, "alice");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(templates()))
+                .app_data(web::Data::new(database.clone()))
+                .app_data(form_config())
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(verify_csrf))
+                        .wrap(from_fn(require_login))
+                        .route("/add", web::post().to(add_job)),
+                ),
+        )
+        .await;
+        let add = |length: usize| {
+            let body = form_urlencoded::Serializer::new(String::new())
+                .append_pair("title", "Welder")
+                .append_pair("hourly", "28")
+                .append_pair("applied", "No")
+                .append_pair("description", &"é".repeat(length))
+                .append_pair("csrf_token", &csrf_token(&session))
+                .finish();
+            test::TestRequest::post()
+                .uri("/add")
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload(body)
+                .to_request()
+        };
+
+        let response = test::call_service(&app, add(MAX_DESCRIPTION_LENGTH + 1)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let page = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(page.contains(&format!(
+            "The description must be at most {} characters.",
+            MAX_DESCRIPTION_LENGTH
+        )));
+        assert!(get_jobs(&connection, alice).unwrap().is_empty());
+
+        let response = test::call_service(&app, add(MAX_DESCRIPTION_LENGTH)).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let jobs = get_jobs(&connection, alice).unwrap();
+        assert_eq!(
+            jobs[0].get_description().map(|d| d.chars().count()),
+            Some(MAX_DESCRIPTION_LENGTH)
+        );
+    }
+
+    // Editing a job starts its history once, and the detail page says it was saved:
+    #[actix_web::test]
+    async fn test_edit_job() {
+        let database = Database::new("file:server_test_edit_job?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (alice, session) = log_in(&connection, "alice");
+        let job = Job::new(None, "Welder".to_string(), 28.0, "0".to_string(), None);
+        let job_id = enter_data(&connection, alice, &job).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(templates()))
+                .app_data(web::Data::new(database.clone()))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(require_login))
+                        .route("/jobs/{id}", web::get().to(job_detail))
+                        .route("/jobs/{id}/edit", web::post().to(edit_job)),
+                ),
+        )
+        .await;
+        let edit = |body: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/jobs/{}/edit", job_id))
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload(body.to_string())
+                .to_request()
+        };
+        let history = || -> i64 {
+            connection
+                .query_row("SELECT COUNT(*) FROM status_history", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        let response = test::call_service(&app, edit("title=Welder&hourly=30&applied=Yes")).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            &format!("/jobs/{}", job_id)
+        );
+        let flash = response
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == FLASH_COOKIE)
+            .expect("The edit leaves a message")
+            .into_owned();
+        assert_eq!(history(), 1);
+        let edited = get_job(&connection, alice, job_id).unwrap().unwrap();
+        assert_eq!(edited.get_hourly(), 30.0);
+        assert_eq!(edited.get_stage(), Some(Stage::Applied));
+
+        // Saving it again doesn't add to the history:
+        let response = test::call_service(&app, edit("title=Welder&hourly=30&applied=Yes")).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(history(), 1);
+
+        let detail = test::TestRequest::get()
+            .uri(&format!("/jobs/{}", job_id))
+            .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+            .cookie(flash)
+            .to_request();
+        let page =
+            String::from_utf8(test::call_and_read_body(&app, detail).await.to_vec()).unwrap();
+        assert!(page.contains("flash-success"));
+        assert!(page.contains(&format!("Saved Welder (job {}).", job_id)));
+    }
+
+    // Adding and removing a job leave a message that the job list shows once:
+    #[actix_web::test]
+    async fn test_flash_messages() {
+        let database = Database::new("file:server_test_flash?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (alice, session) = log_in(&connection, "alice");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(templates()))
+                .app_data(web::Data::new(database.clone()))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(require_login))
+                        .route("/", web::get().to(list_jobs))
+                        .route("/add", web::post().to(add_job))
+                        .route("/rem", web::post().to(rem_job)),
+                ),
+        )
+        .await;
+        let post = |uri: &str, body: &str| {
+            test::TestRequest::post()
+                .uri(uri)
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload(body.to_string())
+                .to_request()
+        };
+        // Follow a redirect with the flash cookie it set, like a browser:
+        let follow = |response: actix_web::dev::ServiceResponse| {
+            let flash = response
+                .response()
+                .cookies()
+                .find(|cookie| cookie.name() == FLASH_COOKIE)
+                .expect("The redirect leaves a message")
+                .into_owned();
+            test::TestRequest::get()
+                .uri("/")
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .cookie(flash)
+                .to_request()
+        };
+        let page = |response: actix_web::dev::ServiceResponse| async {
+            String::from_utf8(test::read_body(response).await.to_vec()).unwrap()
+        };
+
+        let added =
+            test::call_service(&app, post("/add", "title=Welder&hourly=28&applied=No")).await;
+        assert_eq!(added.status(), StatusCode::FOUND);
+        let job_id = get_jobs(&connection, alice).unwrap()[0].get_id().unwrap();
+        let list = test::call_service(&app, follow(added)).await;
+        let removal = list
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == FLASH_COOKIE)
+            .expect("The message is only shown once");
+        assert_eq!(removal.value(), "");
+        assert!(page(list)
+            .await
+            .contains(&format!("Added Welder (job {}).", job_id)));
+
+        let removed = test::call_service(&app, post("/rem", &format!("id={}", job_id))).await;
+        let list = test::call_service(&app, follow(removed)).await;
+        let list = page(list).await;
+        assert!(list.contains("flash-success"));
+        assert!(list.contains(&format!("Removed Welder (job {}).", job_id)));
+
+        // A job that isn't there is reported too:
+        let missing = test::call_service(&app, post("/rem", "id=99")).await;
+        assert_eq!(missing.status(), StatusCode::FOUND);
+        let list = page(test::call_service(&app, follow(missing)).await).await;
+        assert!(list.contains("flash-error"));
+        assert!(list.contains("No job with id 99."));
+
+        // A job that fails to be removed keeps its dates, nothing is half removed:
+        let kept = Job::new(None, "Cook".to_string(), 18.0, "0".to_string(), None);
+        let kept = enter_data(&connection, alice, &kept).unwrap();
+        let event = JobEvent::parse(kept, "interview", "2025-03-14", None, None).unwrap();
+        add_event(&connection, alice, &event).unwrap();
+        connection
+            .execute(
+                "CREATE TRIGGER keep_jobs BEFORE DELETE ON jobs BEGIN SELECT RAISE(ABORT, 'kept'); END",
+                [],
+            )
+            .unwrap();
+        let failed = test::call_service(&app, post("/rem", &format!("id={}", kept))).await;
+        let list = page(test::call_service(&app, follow(failed)).await).await;
+        assert!(list.contains("could not be removed, the database failed."));
+        assert_eq!(get_events(&connection, alice, Some(kept)).unwrap().len(), 1);
+        connection.execute("DROP TRIGGER keep_jobs", []).unwrap();
+
+        // Without a message, or with a forged one, there is no banner:
+        let forged = test::TestRequest::get()
+            .uri("/")
+            .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+            .cookie(Cookie::new(FLASH_COOKIE, "success:0000:Hacked"))
+            .to_request();
+        let list = page(test::call_service(&app, forged).await).await;
+        assert!(!list.contains("Hacked"));
+        assert!(!list.contains("class=\"flash"));
+    }
+
+    // A csv file too long for actix's default limits is previewed and imported,
+    // and the job list tells what the import did:
+    #[actix_web::test]
+    async fn test_large_import() {
+        let database = Database::new("file:server_test_large_import?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (alice, session) = log_in(&connection, "alice");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(templates()))
+                .app_data(web::Data::new(database.clone()))
+                .app_data(form_config())
+                .app_data(upload_config())
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(require_login))
+                        .route("/", web::get().to(list_jobs))
+                        .route("/upload", web::post().to(upload_preview))
+                        .route("/import", web::post().to(upload_import)),
+                ),
+        )
+        .await;
+        let import = |data: &str, atomic: bool| {
+            let mut body = form_urlencoded::Serializer::new(String::new());
+            body.append_pair("data", data);
+            body.append_pair("on_conflict", "duplicate"); // The rows look alike.
+            if atomic {
+                body.append_pair("atomic", "on");
+            }
+            test::TestRequest::post()
+                .uri("/import")
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload(body.finish())
+                .to_request()
+        };
+        // Follow the redirect with the flash cookie it set, to the job list:
+        let follow = |response: actix_web::dev::ServiceResponse| {
+            assert_eq!(response.status(), StatusCode::FOUND);
+            let flash = response
+                .response()
+                .cookies()
+                .find(|cookie| cookie.name() == FLASH_COOKIE)
+                .expect("The import leaves a message")
+                .into_owned();
+            test::TestRequest::get()
+                .uri("/")
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .cookie(flash)
+                .to_request()
+        };
+        let message = |request| async {
+            let list = test::call_service(&app, request).await;
+            String::from_utf8(test::read_body(list).await.to_vec()).unwrap()
+        };
+
+        // An upload over 2MB (blank lines are skipped) is previewed:
+        let file = format!("title,hourly\nWelder,28\n{}", "\n".repeat(2_500_000));
+        let upload = format!(
+            "--XYZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"jobs.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n{}\r\n--XYZ--\r\n",
+            file
+        );
+        let preview = test::TestRequest::post()
+            .uri("/upload")
+            .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+            .insert_header(("Content-Type", "multipart/form-data; boundary=XYZ"))
+            .set_payload(upload)
+            .to_request();
+        let response = test::call_service(&app, preview).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // A file over 16KB is imported once confirmed:
+        let mut data = "title,company,hourly\n".to_string();
+        for row in 0..300 {
+            data.push_str(&format!(
+                "Welder {},Daimler Truck North America Manufacturing,28\n",
+                row
+            ));
+        }
+        assert!(data.len() > 16 * 1024);
+        let imported = test::call_service(&app, import(&data, false)).await;
+        let list = message(follow(imported)).await;
+        assert!(list.contains("Imported 300 jobs, updated 0 and skipped 0 rows."));
+        assert_eq!(get_jobs(&connection, alice).unwrap().len(), 300);
+
+        // An all-or-nothing import with bad rows doesn't look like a successful one:
+        let rolled_back =
+            test::call_service(&app, import("title,hourly\nCook,18\nBaker,lots\n", true)).await;
+        let list = message(follow(rolled_back)).await;
+        assert!(list.contains("flash-error"));
+        assert!(
+            list.contains("Nothing was imported, 1 rows have errors. Line 3: invalid hourly rate")
+        );
+        assert_eq!(get_jobs(&connection, alice).unwrap().len(), 300);
+    }
+
+    // The checked jobs of the user are removed together, with their CSRF token:
+    #[actix_web::test]
+    async fn test_remove_selected() {
+        let database = Database::new("file:server_test_remove_selected?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (alice, session) = log_in(&connection, "alice");
+        let (bob, _) = log_in(&connection, "bob");
+        let job = |title: &str| Job::new(None, title.to_string(), 20.0, "0".to_string(), None);
+        let cook = enter_data(&connection, alice, &job("Cook")).unwrap();
+        let host = enter_data(&connection, alice, &job("Host")).unwrap();
+        let kept = enter_data(&connection, alice, &job("Welder")).unwrap();
+        let bobs = enter_data(&connection, bob, &job("Barista")).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(database.clone()))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(verify_csrf))
+                        .wrap(from_fn(require_login))
+                        .route("/rem/selected", web::post().to(remove_selected)),
+                ),
+        )
+        .await;
+        let remove = |body: String| {
+            test::TestRequest::post()
+                .uri("/rem/selected")
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload(body)
+                .to_request()
+        };
+        let message = |response: &actix_web::dev::ServiceResponse| {
+            response
+                .response()
+                .cookies()
+                .find(|cookie| cookie.name() == FLASH_COOKIE)
+                .map(|cookie| cookie.value().to_string())
+                .unwrap_or_default()
+        };
+        let ids = format!("ids={}&ids={}&ids={}", cook, host, bobs);
+
+        // Without the token nothing is removed:
+        let response = test::call_service(&app, remove(ids.clone())).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(get_jobs(&connection, alice).unwrap().len(), 3);
+
+        // With it, Alice's checked jobs go, and Bob's job is not hers to remove:
+        let body = format!("csrf_token={}&{}", csrf_token(&session), ids);
+        let response = test::call_service(&app, remove(body)).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert!(message(&response).ends_with("Removed 2 jobs. 1 job not found."));
+        let left: Vec<Option<i64>> = get_jobs(&connection, alice)
+            .unwrap()
+            .iter()
+            .map(|job| job.get_id())
+            .collect();
+        assert_eq!(left, vec![Some(kept)]);
+        assert_eq!(get_jobs(&connection, bob).unwrap().len(), 1);
+
+        // Nothing checked:
+        let body = format!("csrf_token={}", csrf_token(&session));
+        let response = test::call_service(&app, remove(body)).await;
+        assert!(message(&response).ends_with("Check the jobs to remove first."));
+    }
+
+    // The checked jobs get the bulk action, and archived jobs move to their own list:
+    #[actix_web::test]
+    async fn test_bulk_jobs() {
+        let database = Database::new("file:server_test_bulk_jobs?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (alice, session) = log_in(&connection, "alice");
+        let job = |title: &str| Job::new(None, title.to_string(), 20.0, "0".to_string(), None);
+        let cook = enter_data(&connection, alice, &job("Cook")).unwrap();
+        let host = enter_data(&connection, alice, &job("Host")).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(templates()))
+                .app_data(web::Data::new(database.clone()))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(require_login))
+                        .route("/", web::get().to(list_jobs))
+                        .route("/bulk", web::post().to(bulk_jobs)),
+                ),
+        )
+        .await;
+        let post = |uri: &str, body: String| {
+            test::TestRequest::post()
+                .uri(uri)
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload(body)
+                .to_request()
+        };
+        let message = |response: &actix_web::dev::ServiceResponse| {
+            response
+                .response()
+                .cookies()
+                .find(|cookie| cookie.name() == FLASH_COOKIE)
+                .map(|cookie| cookie.value().to_string())
+                .unwrap_or_default()
+        };
+        let page = |uri: &str| {
+            let request = test::TestRequest::get()
+                .uri(uri)
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .to_request();
+            async { String::from_utf8(test::call_and_read_body(&app, request).await.to_vec()) }
+        };
+
+        let body = format!("ids={}&ids={}&action=tag&tag=remote", cook, host);
+        let response = test::call_service(&app, post("/bulk", body)).await;
+        assert!(message(&response).ends_with("2 jobs tagged \"remote\"."));
+        let body = format!("ids={}&ids=99&action=stage%3Ainterview&tag=", cook);
+        let response = test::call_service(&app, post("/bulk", body)).await;
+        assert!(message(&response).ends_with("1 job moved to interview. 1 job not found."));
+        let cook_job = get_job(&connection, alice, cook).unwrap().unwrap();
+        assert_eq!(cook_job.get_stage(), Some(Stage::Interview));
+
+        // An archived job leaves the job list for the archived jobs:
+        let response =
+            test::call_service(&app, post("/bulk", format!("ids={}&action=archive", host))).await;
+        assert_eq!(response.headers().get(header::LOCATION).unwrap(), "/");
+        let list = page("/").await.unwrap();
+        assert!(list.contains("Cook") && !list.contains("Host"));
+        assert!(list.contains("Archived jobs (1)"));
+        let archived = page("/?archived=1").await.unwrap();
+        assert!(archived.contains("Host") && !archived.contains("Cook"));
+        assert!(archived.contains(r#"<span class="tag">remote</span>"#));
+
+        // Putting it back goes back to the archived jobs:
+        let body = format!("ids={}&action=unarchive", host);
+        let response = test::call_service(&app, post("/bulk?archived=1", body)).await;
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "/?archived=1"
+        );
+        assert!(page("/").await.unwrap().contains("Host"));
+
+        // A bad action changes nothing:
+        let body = format!("ids={}&action=tag&tag=a,b", cook);
+        let response = test::call_service(&app, post("/bulk", body)).await;
+        assert!(message(&response).ends_with("A tag can't have a comma in it."));
+        let response = test::call_service(&app, post("/bulk", "action=archive".to_string())).await;
+        assert!(message(&response).ends_with("Check the jobs to change first."));
+        assert_eq!(
+            get_job(&connection, alice, cook)
+                .unwrap()
+                .unwrap()
+                .get_tags(),
+            ["remote"]
+        );
+    }
+
+    // A page of another site can't remove or update a job with the user's session:
+    #[actix_web::test]
+    async fn test_forged_requests() {
+        let database = Database::new("file:server_test_forged?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (alice, session) = log_in(&connection, "alice");
+        let job = Job::new(
+            None,
+            "Welder".to_string(),
+            28.0,
+            "0".to_string(),
+            Some("https://example.com/welder".to_string()),
+        );
+        let job_id = enter_data(&connection, alice, &job).unwrap();
+        auth::create_user(&connection, "mallory", "password123").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(templates()))
+                .app_data(web::Data::new(database.clone()))
+                .service(
+                    web::resource("/login")
+                        .wrap(from_fn(verify_origin))
+                        .route(web::post().to(login)),
+                )
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(verify_csrf))
+                        .wrap(from_fn(require_login))
+                        .route("/rem", web::post().to(rem_job))
+                        .route("/update", web::post().to(update)),
+                ),
+        )
+        .await;
+        let rem = |body: String| {
+            test::TestRequest::post()
+                .uri("/rem")
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload(body)
+                .to_request()
+        };
+        let update = || {
+            test::TestRequest::post()
+                .uri("/update")
+                .cookie(Cookie::new(SESSION_COOKIE, session.clone()))
+                .set_json(serde_json::json!({ "id": job_id, "applied": true }))
+        };
+
+        // The forged requests change nothing:
+        let response = test::call_service(&app, rem(format!("id={}", job_id))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = test::call_service(&app, update().to_request()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let kept = get_job(&connection, alice, job_id)
+            .unwrap()
+            .expect("Removed");
+        assert_eq!(kept.get_applied(), "No");
+
+        // The page's own form and JavaScript do:
+        let token = csrf_token(&session);
+        let request = update().insert_header((CSRF_HEADER, token.as_str()));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response =
+            test::call_service(&app, rem(format!("id={}&csrf_token={}", job_id, token))).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert!(get_job(&connection, alice, job_id).unwrap().is_none());
+
+        // Another site can't log the user into its own account, by its origin or its url:
+        let login_from = |header: (header::HeaderName, &str)| {
+            test::TestRequest::post()
+                .uri("/login")
+                .insert_header(header)
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload("username=mallory&password=password123")
+                .to_request()
+        };
+        let logged_in = |response: &actix_web::dev::ServiceResponse| {
+            response
+                .response()
+                .cookies()
+                .any(|cookie| cookie.name() == SESSION_COOKIE)
+        };
+        for forged in [
+            (header::ORIGIN, "https://evil.example"),
+            (header::REFERER, "https://evil.example/login.html"),
+        ] {
+            let response = test::call_service(&app, login_from(forged)).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            assert!(!logged_in(&response));
+        }
+
+        // The site's own login page does:
+        let own = (header::ORIGIN, "http://localhost:8080");
+        let response = test::call_service(&app, login_from(own)).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert!(logged_in(&response));
+    }
+
+    // API tokens only reach the JSON endpoints, and read-only tokens can't change a job:
+    #[actix_web::test]
+    async fn test_api_tokens() {
+        let database = Database::new("file:server_test_api_tokens?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (alice, _) = log_in(&connection, "alice");
+        let now = chrono::Utc::now().naive_utc();
+        let read = create_api_token(&connection, alice, "stats", TokenScope::Read, now).unwrap();
+        let write = create_api_token(&connection, alice, "sync", TokenScope::Write, now).unwrap();
+        let job = Job::new(
+            None,
+            "Welder".to_string(),
+            28.0,
+            "0".to_string(),
+            Some("https://example.com/welder".to_string()),
+        );
+        let job_id = enter_data(&connection, alice, &job).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(database.clone()))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(require_login))
+                        .route("/rem", web::post().to(rem_job))
+                        .route("/update", web::post().to(update))
+                        .route("/api/jobs", web::get().to(api_jobs)),
+                ),
+        )
+        .await;
+        let bearer = |token: &str| ("Authorization", format!("Bearer {}", token));
+        let jobs = |token: &str| {
+            test::TestRequest::get()
+                .uri("/api/jobs")
+                .insert_header(bearer(token))
+                .to_request()
+        };
+        let update = |token: &str| {
+            test::TestRequest::post()
+                .uri("/update")
+                .insert_header(bearer(token))
+                .set_json(serde_json::json!({ "id": job_id, "applied": true }))
+                .to_request()
+        };
+
+        // Any token can read, an unknown one can't:
+        let listed: serde_json::Value = test::call_and_read_body_json(&app, jobs(&read)).await;
+        assert_eq!(listed[0]["title"], "Welder");
+        let response = test::call_service(&app, jobs("jobs_guessed")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // Only a write token can change a job:
+        let response = test::call_service(&app, update(&read)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            get_job(&connection, alice, job_id)
+                .unwrap()
+                .unwrap()
+                .get_applied(),
+            "No"
+        );
+        let response = test::call_service(&app, update(&write)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            get_job(&connection, alice, job_id)
+                .unwrap()
+                .unwrap()
+                .get_applied(),
+            "Yes"
+        );
+
+        // The form routes need a login, even with a write token:
+        let response = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/rem")
+                .insert_header(bearer(&write))
+                .insert_header(ContentType::form_url_encoded())
+                .set_payload(format!("id={}", job_id))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(get_job(&connection, alice, job_id).unwrap().is_some());
+    }
+
+    // Calendar apps fetch the feed with a token in its url, without a login cookie:
+    #[actix_web::test]
+    async fn test_calendar_feed_token() {
+        let database = Database::new("file:server_test_calendar_feed?mode=memory&cache=shared");
+        let connection = database.open().unwrap();
+        create_table(&connection).unwrap();
+        let (alice, _) = log_in(&connection, "alice");
+        let now = chrono::Utc::now().naive_utc();
+        let read = create_api_token(&connection, alice, "calendar", TokenScope::Read, now).unwrap();
+        let job = Job::new(None, "Welder".to_string(), 28.0, "0".to_string(), None);
+        let job_id = enter_data(&connection, alice, &job).unwrap();
+        let event = JobEvent::parse(job_id, "interview", "2025-03-14", None, None).unwrap();
+        add_event(&connection, alice, &event).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(database.clone()))
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(require_login))
+                        .route("/calendar.ics", web::get().to(calendar_feed))
+                        .route("/api/jobs", web::get().to(api_jobs)),
+                ),
+        )
+        .await;
+        let get = |uri: String| test::TestRequest::get().uri(&uri).to_request();
+
+        let response = test::call_service(&app, get(format!("/calendar.ics?token={}", read))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let feed = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(feed.contains("BEGIN:VCALENDAR"));
+        assert!(feed.contains("Welder"));
+
+        // A wrong token, or none, gets nothing:
+        let response =
+            test::call_service(&app, get("/calendar.ics?token=jobs_guessed".to_string())).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = test::call_service(&app, get("/calendar.ics".to_string())).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        // The other routes don't take a token in their url:
+        let response = test::call_service(&app, get(format!("/api/jobs?token={}", read))).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
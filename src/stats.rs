@@ -0,0 +1,298 @@
+// Author: Nathan Metens
+// Class: Rust 523
+// Professor: Bart Massey
+
+//! # Stats Module
+//!
+//! Gathers the statistics shown on the `/stats` page and returned by
+//! `/api/stats`. Each number comes from an aggregate query in the
+//! database_methods module, except the funnel, which is computed from
+//! the status history of every job.
+
+use crate::database_methods::{
+    applications_per_week, count_by_status, pay_by_status, stage_history, top_employers,
+    DatabaseError,
+};
+use crate::job::Stage;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// How many weeks of applications the statistics show.
+pub const WEEKS_SHOWN: i64 = 12;
+
+/// How many employers the statistics show.
+pub const EMPLOYERS_SHOWN: i64 = 5;
+
+/// The average and median hourly rate of a group of jobs.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PayStats {
+    pub average: Option<f64>,
+    pub median: Option<f64>,
+}
+
+/// The number of applications made in one week.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WeekCount {
+    pub week: String, // The Monday of the week, as YYYY-MM-DD.
+    pub applications: i64,
+}
+
+/// An employer with its number of jobs in the list and applications made.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EmployerCount {
+    pub company: String,
+    pub jobs: i64,
+    pub applications: i64,
+}
+
+/// One step of the application funnel.
+///
+/// # Fields
+/// - `stage`: The stage of the step.
+/// - `jobs`: The number of jobs that reached this stage (or a later one).
+/// - `conversion`: The percent of jobs of the previous step that reached
+///   this one (`None` for the first step, or when the previous step is empty).
+/// - `median_days`: The median number of days from the previous stage to
+///   this one, over the jobs with both dates in their history.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FunnelStep {
+    pub stage: Stage,
+    pub jobs: i64,
+    pub conversion: Option<f64>,
+    pub median_days: Option<f64>,
+}
+
+/// Every statistic about the job list.
+///
+/// # Fields
+/// - `total`: The number of jobs.
+/// - `applied` / `not_applied`: The number of jobs by application status.
+/// - `application_rate`: The percent of jobs applied to (0 with no jobs).
+/// - `pay_applied` / `pay_not_applied`: The pay of the jobs by application status.
+/// - `per_week`: The applications made each week, oldest first.
+/// - `top_employers`: The companies with the most jobs.
+/// - `funnel`: The application funnel, from applied to offer.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Stats {
+    pub total: i64,
+    pub applied: i64,
+    pub not_applied: i64,
+    pub application_rate: f64,
+    pub pay_applied: PayStats,
+    pub pay_not_applied: PayStats,
+    pub per_week: Vec<WeekCount>,
+    pub top_employers: Vec<EmployerCount>,
+    pub funnel: Vec<FunnelStep>,
+}
+
+/// Gather the statistics of a user's job list.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user whose jobs are used.
+///
+/// # Returns
+/// * `Ok(Stats)` with every statistic.
+/// * `Err(DatabaseError)` if any query fails.
+pub fn get_stats(connection: &rusqlite::Connection, user_id: i64) -> Result<Stats, DatabaseError> {
+    let (applied, not_applied) = count_by_status(connection, user_id)?;
+    let total = applied + not_applied;
+    let application_rate = if total > 0 {
+        100.0 * applied as f64 / total as f64
+    } else {
+        0.0
+    };
+
+    let pay = |applied: bool| {
+        pay_by_status(connection, user_id, applied)
+            .map(|(average, median)| PayStats { average, median })
+    };
+
+    let per_week = applications_per_week(connection, user_id, WEEKS_SHOWN)?
+        .into_iter()
+        .map(|(week, applications)| WeekCount { week, applications })
+        .collect();
+
+    let top_employers = top_employers(connection, user_id, EMPLOYERS_SHOWN)?
+        .into_iter()
+        .map(|(company, jobs, applications)| EmployerCount {
+            company,
+            jobs,
+            applications,
+        })
+        .collect();
+
+    Ok(Stats {
+        total,
+        applied,
+        not_applied,
+        application_rate,
+        pay_applied: pay(true)?,
+        pay_not_applied: pay(false)?,
+        per_week,
+        top_employers,
+        funnel: get_funnel(connection, user_id)?,
+    })
+}
+
+/// Compute the application funnel from the status history of a user's jobs.
+///
+/// # Arguments
+/// * `connection` - A reference to an active SQLite connection.
+/// * `user_id` - The user whose jobs are used.
+///
+/// # Returns
+/// * `Ok(Vec<FunnelStep>)` with one step for each stage of `Stage::FUNNEL`.
+/// * `Err(DatabaseError)` if the history can't be read.
+pub fn get_funnel(
+    connection: &rusqlite::Connection,
+    user_id: i64,
+) -> Result<Vec<FunnelStep>, DatabaseError> {
+    Ok(funnel(&stage_history(connection, user_id)?))
+}
+
+/// Compute the application funnel from the first time each job reached
+/// each stage (as Julian day numbers).
+///
+/// A job counts for every funnel stage up to the furthest one it reached,
+/// even if a stage was skipped (an offer straight after applying still
+/// counts as a screen and an interview). Rejections don't move a job
+/// along the funnel.
+pub fn funnel(history: &[(i64, Stage, f64)]) -> Vec<FunnelStep> {
+    let mut reached: HashMap<i64, HashMap<Stage, f64>> = HashMap::new();
+    for &(job_id, stage, time) in history {
+        reached.entry(job_id).or_default().insert(stage, time);
+    }
+
+    let mut steps: Vec<FunnelStep> = Vec::new();
+    for (index, stage) in Stage::FUNNEL.into_iter().enumerate() {
+        let jobs = reached
+            .values()
+            .filter(|stages| {
+                Stage::FUNNEL[index..]
+                    .iter()
+                    .any(|later| stages.contains_key(later))
+            })
+            .count() as i64;
+
+        let (conversion, median_days) = match steps.last() {
+            None => (None, None),
+            Some(previous) => {
+                let conversion =
+                    (previous.jobs > 0).then(|| 100.0 * jobs as f64 / previous.jobs as f64);
+                let mut days: Vec<f64> = reached
+                    .values()
+                    .filter_map(|stages| Some(stages.get(&stage)? - stages.get(&previous.stage)?))
+                    .collect();
+                (conversion, median(&mut days))
+            }
+        };
+
+        steps.push(FunnelStep {
+            stage,
+            jobs,
+            conversion,
+            median_days,
+        });
+    }
+    steps
+}
+
+// The median of some numbers, or None if there are none.
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    })
+}
+
+/// Testing the statistics on an in-memory database.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database_methods::{create_table, enter_data};
+    use crate::job::Job;
+    use rusqlite::Connection;
+
+    // The user the test jobs belong to:
+    const USER: i64 = 1;
+
+    #[test]
+    fn test_get_stats() {
+        let connection = Connection::open_in_memory().expect("Failed to open in-memory database");
+        create_table(&connection).expect("Failed to create table");
+
+        let empty = get_stats(&connection, USER).expect("Failed to get stats");
+        assert_eq!(empty.total, 0);
+        assert_eq!(empty.application_rate, 0.0);
+
+        for (rate, applied) in [(20.0, "1"), (30.0, "0"), (40.0, "0"), (50.0, "0")] {
+            let job = Job::new(
+                None,
+                "Job".to_string(),
+                rate,
+                applied.to_string(),
+                Some("https://example.com".to_string()),
+            )
+            .with_company(Some("TriMet".to_string()));
+            enter_data(&connection, USER, &job).expect("Failed to insert job");
+        }
+
+        let stats = get_stats(&connection, USER).expect("Failed to get stats");
+        assert_eq!((stats.total, stats.applied, stats.not_applied), (4, 1, 3));
+        assert_eq!(stats.application_rate, 25.0);
+        assert_eq!(stats.pay_applied.median, Some(20.0));
+        assert_eq!(stats.pay_not_applied.average, Some(40.0));
+        assert_eq!(
+            stats.per_week.len(),
+            1,
+            "The application was made this week."
+        );
+        assert_eq!(stats.top_employers[0].jobs, 4);
+    }
+
+    // Every job counts up to its furthest stage, and the days between stages use the first dates:
+    #[test]
+    fn test_funnel() {
+        let history = [
+            (1, Stage::Applied, 0.0),
+            (1, Stage::Screen, 2.0),
+            (1, Stage::Interview, 5.0),
+            (2, Stage::Applied, 0.0),
+            (2, Stage::Screen, 4.0),
+            (2, Stage::Rejected, 6.0),
+            (3, Stage::Applied, 1.0),
+            (4, Stage::Applied, 0.0),
+            (4, Stage::Offer, 10.0), // Skipped the screen and interview.
+        ];
+        let steps = funnel(&history);
+
+        let jobs: Vec<i64> = steps.iter().map(|step| step.jobs).collect();
+        assert_eq!(jobs, vec![4, 3, 2, 1]);
+        assert_eq!(steps[0].conversion, None);
+        assert_eq!(steps[1].conversion, Some(75.0));
+        assert_eq!(steps[2].conversion, Some(100.0 * 2.0 / 3.0));
+        assert_eq!(
+            steps[1].median_days,
+            Some(3.0),
+            "The median of 2 and 4 days."
+        );
+        assert_eq!(steps[2].median_days, Some(3.0));
+        assert_eq!(
+            steps[3].median_days, None,
+            "No job has both an interview and an offer."
+        );
+
+        let empty = funnel(&[]);
+        assert_eq!(empty.len(), Stage::FUNNEL.len());
+        assert!(empty
+            .iter()
+            .all(|step| step.jobs == 0 && step.conversion.is_none()));
+    }
+}
@@ -1,46 +1,57 @@
 /* JS methods to interact with the onclicks inside of the html page.
 Buttons and pop-ups are the main usage of this JS. */
 
+// The CSRF token of the page, sent with every request that changes something:
+function csrfToken() {
+    return document.querySelector('meta[name="csrf-token"]').content;
+}
+
 // AJAX scripting for the clicking of buttons:
 $(document).ready(function() {
-    // Click event for the "Add a Job" button
-    $('#addJobBtn').click(function() {
-        // Hide the "Remove a Job" form if it is visible
-        $('#removeJobForm').hide();
-        
-        // Toggle the visibility of the "Add a Job" form
-        $('#addJobForm').toggle();
+    // Dismiss the message of the last change:
+    $('.flash-close').click(function() {
+        $(this).closest('.flash').remove();
     });
 
-    // Click event for the "Remove a Job" button
-    $('#removeJobBtn').click(function() {
-        // Hide the "Add a Job" form if it is visible
-        $('#addJobForm').hide();
-        
-        // Toggle the visibility of the "Remove a Job" form
-        $('#removeJobForm').toggle();
+    // Check or uncheck every job from the checkbox in the table header:
+    $('#selectAll').change(function() {
+        $('.job-select').prop('checked', this.checked);
+        updateSelectedCount();
     });
+    $('.job-select').change(updateSelectedCount);
 
     // Get references to elements for the Modal:
     const addJobBtn = document.getElementById("addJobBtn");
-    const removeJobBtn = document.getElementById("removeJobBtn");
     const modalOverlay = document.getElementById("modalOverlay");
     const closeModalBtn = document.getElementById("closeModalBtn");
     const addJobForm = document.getElementById("addJobForm");
-    const removeJobForm = document.getElementById("removeJobForm");
+    const importJobsBtn = document.getElementById("importJobsBtn");
+    const importJobsForm = document.getElementById("importJobsForm");
+    const addEventBtn = document.getElementById("addEventBtn");
+    const addEventForm = document.getElementById("addEventForm");
 
     // Show the Add Job form in the modal:
     addJobBtn.addEventListener("click", () => {
         modalOverlay.style.display = "flex";
         addJobForm.style.display = "block";
-        removeJobForm.style.display = "none"; // Hide remove job form again.
+        importJobsForm.style.display = "none";
+        addEventForm.style.display = "none";
+    });
+
+    // Show the Import Jobs form in the modal:
+    importJobsBtn.addEventListener("click", () => {
+        modalOverlay.style.display = "flex";
+        addJobForm.style.display = "none";
+        importJobsForm.style.display = "block";
+        addEventForm.style.display = "none";
     });
 
-    // Show the Remove Job form in the modal:
-    removeJobBtn.addEventListener("click", () => {
+    // Show the Add a Date form in the modal:
+    addEventBtn.addEventListener("click", () => {
         modalOverlay.style.display = "flex";
         addJobForm.style.display = "none";
-        removeJobForm.style.display = "block";
+        importJobsForm.style.display = "none";
+        addEventForm.style.display = "block";
     });
 
     // Close the modal when the close button is clicked:
@@ -48,6 +59,82 @@ $(document).ready(function() {
         modalOverlay.style.display = "none";
     });
 
+    // Check for duplicates before adding a job. The first submit shows a warning
+    // if the job may already be in the list, and a second submit adds it anyway:
+    const addForm = document.getElementById("addForm");
+    const duplicateWarning = document.getElementById("duplicateWarning");
+    const addJobSubmit = document.getElementById("addJobSubmit");
+
+    addForm.addEventListener("submit", (event) => {
+        if (addForm.dataset.checked === "yes") {
+            return; // Already warned, add the job.
+        }
+        event.preventDefault();
+
+        fetch("/duplicates", {
+            method: "POST",
+            headers: { "Content-Type": "application/json", "X-CSRF-Token": csrfToken() },
+            body: JSON.stringify({
+                title: addForm.title.value,
+                company: addForm.company.value || null,
+                link: addForm.link.value || null
+            })
+        })
+        .then(response => response.json())
+        .then(data => {
+            addForm.dataset.checked = "yes";
+            if (data.duplicates.length === 0) {
+                addForm.submit(); // No duplicates, add the job.
+                return;
+            }
+            // List the matching jobs and let the user decide:
+            duplicateWarning.textContent = "This job may already be in the list: " +
+                data.duplicates.map(d => "#" + d.id + " " + d.title).join(", ");
+            duplicateWarning.style.display = "block";
+            addJobSubmit.textContent = "Add Anyway";
+        })
+        .catch(() => addForm.submit()); // Don't block adding a job if the check fails.
+    });
+
+    // Check again when the job details change:
+    addForm.addEventListener("input", () => {
+        addForm.dataset.checked = "";
+        duplicateWarning.style.display = "none";
+        addJobSubmit.textContent = "Add Job";
+    });
+
+    // Fill in the add form from a saved posting page. The fields that the page
+    // doesn't have are left as they are:
+    const postingFile = document.getElementById("postingFile");
+    const postingStatus = document.getElementById("postingStatus");
+
+    postingFile.addEventListener("change", () => {
+        if (postingFile.files.length === 0) {
+            return;
+        }
+        const data = new FormData();
+        data.append("file", postingFile.files[0]);
+
+        fetch("/posting", { method: "POST", headers: { "X-CSRF-Token": csrfToken() }, body: data })
+        .then(response => response.json())
+        .then(posting => {
+            for (const field of ["title", "company", "hourly", "link", "description"]) {
+                if (posting[field] !== null) {
+                    addForm[field].value = posting[field];
+                }
+            }
+            addForm.dispatchEvent(new Event("input")); // Check for duplicates again.
+            postingStatus.textContent = posting.found
+                ? "Filled in from the page, check the details before adding the job."
+                : "No job details were found in that page.";
+            postingStatus.style.display = "block";
+        })
+        .catch(() => {
+            postingStatus.textContent = "The page could not be read.";
+            postingStatus.style.display = "block";
+        });
+    });
+
     // Close the modal if the background is clicked:
     modalOverlay.addEventListener("click", (event) => {
         if (event.target === modalOverlay) {
@@ -67,6 +154,11 @@ function toggleAppliedStatus(element) {
 
     // Get the parent row and change the color based on the status:
     const row = element.closest('tr');
+    if (newStatus === "No") { // Not applied clears the stage history.
+        row.querySelector('.stage-select').value = "";
+    } else if (!row.querySelector('.stage-select').value) { // Applying starts it.
+        row.querySelector('.stage-select').value = "applied";
+    }
     if (newStatus === "Yes") { // Green color for Yes.
         row.classList.add('highlight-green');
         row.classList.remove('highlight-red');
@@ -77,7 +169,7 @@ function toggleAppliedStatus(element) {
     // Send an AJAX request to update the database:
     fetch("/update", {
         method: "POST",
-        headers: { "Content-Type": "application/json" },
+        headers: { "Content-Type": "application/json", "X-CSRF-Token": csrfToken() },
         body: JSON.stringify({ id: parseInt(jobId), applied: newStatus === "Yes" }) // Send boolean caught in rust.
     })
     .then(response => response.json()) // Ensure response is parsed as JSON.
@@ -89,3 +181,56 @@ function toggleAppliedStatus(element) {
         }
     })
 }
+
+// Move a job to the stage chosen in its Stage column:
+function setStage(element) {
+    const jobId = element.dataset.jobId;
+    const stage = element.value;
+
+    // Every stage means the job was applied to, so update the applied column and color:
+    const row = element.closest('tr');
+    row.querySelector('.applied-status').textContent = "Yes";
+    row.classList.add('highlight-green');
+    row.classList.remove('highlight-red');
+
+    // Send an AJAX request to keep the stage in the status history:
+    fetch("/stage", {
+        method: "POST",
+        headers: { "Content-Type": "application/json", "X-CSRF-Token": csrfToken() },
+        body: JSON.stringify({ id: parseInt(jobId), stage: stage })
+    })
+    .then(response => response.json())
+    .then(data => {
+        if (data.success) {
+            console.log("Stage updated successfully on the server.");
+        } else {
+            console.log("Stage updated unsuccessfully on the server.");
+        }
+    })
+}
+
+// Ask before removing a job with the delete button of its row:
+function confirmRemove(form) {
+    return confirm("Remove " + form.dataset.title + "? Its dates and history are removed too.");
+}
+
+// Ask before removing every checked job:
+function confirmRemoveSelected() {
+    const count = document.querySelectorAll(".job-select:checked").length;
+    return count > 0 && confirm("Remove the " + count + " checked job" + (count === 1 ? "" : "s") + "?");
+}
+
+// Show how many jobs are checked on the remove button, the bulk buttons need at least one:
+function updateSelectedCount() {
+    const count = document.querySelectorAll(".job-select:checked").length;
+    document.getElementById("selectedCount").textContent = count;
+    document.getElementById("removeSelectedBtn").disabled = count === 0;
+    document.getElementById("applyBulkBtn").disabled = count === 0;
+}
+
+// Show the tag box when the bulk action adds a tag, which then needs one:
+function updateBulkAction(select) {
+    const tag = document.getElementById("bulkTag");
+    tag.hidden = select.value !== "tag";
+    tag.required = select.value === "tag";
+}